@@ -0,0 +1,319 @@
+use crate::chat::TextComponent;
+use crate::encryption::{generate_shared_secret, rsa_encrypt, Cipher};
+use crate::framing::read_frame_length;
+use crate::game::{CompressionStatus, ConnectionState, MinecraftConnection};
+use crate::protocol::{Packet, PacketError};
+use crate::session::{self, SessionService};
+use bytes::BytesMut;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use std::io::{Error, ErrorKind, Result};
+use tokio::net::TcpStream;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+pub type PacketReader = SplitStream<Framed<TcpStream, MinecraftCodec>>;
+pub type PacketWriter = SplitSink<Framed<TcpStream, MinecraftCodec>, Packet>;
+
+/// Frames, (de)compresses and en-/decrypts packets for a `Framed` tcp stream
+pub struct MinecraftCodec {
+    pub connection: MinecraftConnection,
+    cipher: Option<Cipher>,
+    /// How many bytes at the start of the read buffer are decrypted already
+    decrypted: usize,
+}
+
+impl MinecraftCodec {
+    pub fn new(connection: MinecraftConnection) -> MinecraftCodec {
+        MinecraftCodec {
+            connection,
+            cipher: None,
+            decrypted: 0,
+        }
+    }
+
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<()> {
+        self.cipher = Some(Cipher::new(shared_secret)?);
+        Ok(())
+    }
+}
+
+impl Decoder for MinecraftCodec {
+    type Item = Packet;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>> {
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(&mut src[self.decrypted..]);
+        }
+        self.decrypted = src.len();
+
+        loop {
+            let (frame_len, header_len) = match read_frame_length(&src[..])? {
+                Some(length) => length,
+                None => return Ok(None),
+            };
+            if src.len() < header_len + frame_len {
+                return Ok(None);
+            }
+
+            let frame = src.split_to(header_len + frame_len).split_off(header_len);
+            self.decrypted -= header_len + frame_len;
+            match Packet::from_frame(frame.to_vec(), &self.connection) {
+                Ok(packet) => return Ok(Some(packet)),
+                Err(PacketError::UnknownPacketIdentifier { .. }) => {}
+                Err(err) => return Err(Error::new(ErrorKind::InvalidData, err.to_string())),
+            }
+        }
+    }
+}
+
+impl Encoder<Packet> for MinecraftCodec {
+    type Error = Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> Result<()> {
+        let mut data = packet.serialize(&self.connection)?;
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut data);
+        }
+        dst.extend_from_slice(&data);
+        Ok(())
+    }
+}
+
+/// A connection driven by the tokio runtime instead of its own threads
+pub struct AsyncConnection {
+    framed: Framed<TcpStream, MinecraftCodec>,
+}
+
+impl AsyncConnection {
+    pub async fn connect(connection: MinecraftConnection) -> Result<AsyncConnection> {
+        let stream = TcpStream::connect(connection.server_address).await?;
+        Ok(AsyncConnection {
+            framed: Framed::new(stream, MinecraftCodec::new(connection)),
+        })
+    }
+
+    /// Handshake, encryption and compression; returns the name and UUID the server gave us
+    pub async fn login<S>(&mut self, session: &S) -> Result<(String, String)>
+    where
+        S: SessionService + Clone + Send + 'static,
+    {
+        let connection = &self.framed.codec().connection;
+        let handshake = connection.handshake(ConnectionState::Login);
+        let join = Packet::ClientJoin {
+            player_name: connection.player_name.clone(),
+        };
+        self.framed.codec_mut().connection.state = ConnectionState::Login;
+        self.framed.send(handshake).await?;
+        self.framed.send(join).await?;
+
+        while let Some(packet) = self.framed.next().await {
+            match packet? {
+                Packet::ServerEncryptionRequest {
+                    serverid,
+                    pubkey,
+                    verifytoken,
+                } => {
+                    let shared_secret = generate_shared_secret();
+                    // the session server is asked over blocking HTTP
+                    let joined = tokio::task::spawn_blocking({
+                        let session = session.clone();
+                        let account = self.framed.codec().connection.account.clone();
+                        let serverid = serverid.clone();
+                        let pubkey = pubkey.clone();
+                        move || {
+                            session::join_session(
+                                &session,
+                                account.as_ref(),
+                                &serverid,
+                                &shared_secret,
+                                &pubkey,
+                            )
+                        }
+                    })
+                    .await
+                    .map_err(Error::other)?;
+                    joined.map_err(|err| Error::new(ErrorKind::PermissionDenied, err))?;
+                    let response = Packet::ClientEncryptionResponse {
+                        shared_secret: rsa_encrypt(&pubkey, &shared_secret)?,
+                        verify_token: rsa_encrypt(&pubkey, &verifytoken)?,
+                    };
+                    self.framed.send(response).await?;
+                    self.framed.codec_mut().enable_encryption(&shared_secret)?;
+                }
+                Packet::ServerCompressionLevelSet { compression_level } => {
+                    self.framed.codec_mut().connection.compression =
+                        CompressionStatus::Enabled(compression_level);
+                }
+                Packet::ServerLoginSuccess { uuid, name } => {
+                    self.framed.codec_mut().connection.state = ConnectionState::Play;
+                    return Ok((name, uuid));
+                }
+                Packet::ServerLoginDisconnect { reason } => {
+                    let reason = TextComponent::from_json(&reason)
+                        .map(|reason| reason.to_plain())
+                        .unwrap_or(reason);
+                    return Err(Error::new(ErrorKind::ConnectionRefused, reason));
+                }
+                _ => {}
+            }
+        }
+        Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "connection closed during login",
+        ))
+    }
+
+    pub async fn send(&mut self, packet: Packet) -> Result<()> {
+        self.framed.send(packet).await
+    }
+
+    pub async fn next_packet(&mut self) -> Option<Result<Packet>> {
+        self.framed.next().await
+    }
+
+    /// Splits the connection into halves that can be driven from different tasks
+    pub fn split(self) -> (PacketWriter, PacketReader) {
+        self.framed.split()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{Account, SessionError};
+    use mc_varint::VarIntRead;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddrV4, TcpListener};
+    use std::thread;
+
+    #[derive(Clone)]
+    struct NoSession;
+
+    impl SessionService for NoSession {
+        fn join_server(&self, _: &Account, _: &str) -> std::result::Result<(), SessionError> {
+            Err(SessionError::Unreachable("offline".into()))
+        }
+    }
+
+    /// A server that reads the handshake and Login Start, then answers with `reply`
+    fn login_server(reply: Packet) -> (SocketAddrV4, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = match listener.local_addr().unwrap() {
+            std::net::SocketAddr::V4(address) => address,
+            _ => unreachable!(),
+        };
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for _ in 0..2 {
+                let len = stream.read_var_i32().unwrap();
+                stream.read_exact(&mut vec![0; len as usize]).unwrap();
+            }
+            let login = MinecraftConnection::new(address, String::new());
+            stream.write_all(&reply.serialize(&login).unwrap()).unwrap();
+        });
+        (address, server)
+    }
+
+    fn login(address: SocketAddrV4) -> Result<(String, String)> {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut connection = MinecraftConnection::new(address, "bot".into());
+            connection.account = Some(Account {
+                access_token: "token".into(),
+                profile_id: "id".into(),
+            });
+            AsyncConnection::connect(connection)
+                .await?
+                .login(&NoSession)
+                .await
+        })
+    }
+
+    fn codec() -> MinecraftCodec {
+        let mut connection = MinecraftConnection::new(
+            SocketAddrV4::new([127, 0, 0, 1].into(), 25565),
+            "bot".into(),
+        );
+        connection.state = ConnectionState::Play;
+        MinecraftCodec::new(connection)
+    }
+
+    #[test]
+    fn decodes_encrypted_packets_split_across_reads() {
+        let secret = [7; 16];
+        let mut writer = codec();
+        writer.enable_encryption(&secret).unwrap();
+        let mut bytes = BytesMut::new();
+        writer
+            .encode(Packet::ServerKeepAlive { magic: 1 }, &mut bytes)
+            .unwrap();
+        writer
+            .encode(Packet::ServerKeepAlive { magic: 2 }, &mut bytes)
+            .unwrap();
+
+        let mut reader = codec();
+        reader.enable_encryption(&secret).unwrap();
+        let mut src = BytesMut::new();
+        let mut magics = Vec::new();
+        // a byte at a time, so frames and the cipher are cut everywhere
+        for byte in bytes.iter() {
+            src.extend_from_slice(&[*byte]);
+            while let Some(packet) = reader.decode(&mut src).unwrap() {
+                match packet {
+                    Packet::ServerKeepAlive { magic } => magics.push(magic),
+                    packet => panic!("unexpected {:?}", packet),
+                }
+            }
+        }
+        assert_eq!(magics, [1, 2]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn skips_unknown_packets_but_fails_on_malformed_ones() {
+        let mut codec = codec();
+        // an id no packet has, then a keep alive
+        let mut src = BytesMut::from(&[1, 0x7F][..]);
+        codec
+            .encode(Packet::ServerKeepAlive { magic: 3 }, &mut src)
+            .unwrap();
+        assert!(matches!(
+            codec.decode(&mut src),
+            Ok(Some(Packet::ServerKeepAlive { magic: 3 }))
+        ));
+
+        // a keep alive without its payload
+        let mut src = BytesMut::from(&[3, 0x20, 0, 0][..]);
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn login_disconnects_carry_the_reason() {
+        let (address, server) = login_server(Packet::ServerLoginDisconnect {
+            reason: r#"{"text": "Failed to verify username!"}"#.into(),
+        });
+        let err = login(address).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        assert_eq!(err.to_string(), "Failed to verify username!");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn failing_to_join_the_session_fails_the_login() {
+        let (address, server) = login_server(Packet::ServerEncryptionRequest {
+            serverid: String::new(),
+            pubkey: Vec::new(),
+            verifytoken: vec![1, 2, 3, 4],
+        });
+        let err = login(address).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(err.to_string(), "session server unreachable: offline");
+        server.join().unwrap();
+    }
+}
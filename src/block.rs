@@ -0,0 +1,331 @@
+use crate::world::ChunkBlockID;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// How a block behaves for movement
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockClass {
+    /// Has a collision box, though not necessarily a full cube: slabs, stairs, carpets and
+    /// snow layers are solid too, `physics::block_shape` knows their shapes
+    Solid,
+    /// Can be walked through: air, plants, torches, signs...
+    Transparent,
+    Liquid,
+}
+
+/// One block state of the global palette
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockState {
+    pub id: ChunkBlockID,
+    /// With namespace, e.g. `minecraft:oak_stairs`
+    pub name: String,
+    /// e.g. facing=north, half=bottom, waterlogged=false
+    pub properties: BTreeMap<String, String>,
+    pub class: BlockClass,
+}
+
+impl BlockState {
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    pub fn is_air(&self) -> bool {
+        matches!(
+            self.name.as_str(),
+            "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"
+        )
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.class == BlockClass::Solid
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.class == BlockClass::Transparent
+    }
+
+    /// Water and lava, waterlogged blocks count too
+    pub fn is_liquid(&self) -> bool {
+        self.class == BlockClass::Liquid || self.property("waterlogged") == Some("true")
+    }
+}
+
+/// Block-state id <-> block name and properties for one version.
+///
+/// Like the item registry it is read from a report of the vanilla server, `blocks.json` here:
+/// `java -cp server.jar net.minecraft.data.Main --reports`
+#[derive(Debug, Default)]
+pub struct BlockRegistry {
+    states: Vec<Option<BlockState>>,
+    default_states: HashMap<String, ChunkBlockID>,
+}
+
+#[derive(Deserialize)]
+struct ReportBlock {
+    #[serde(default)]
+    states: Vec<ReportState>,
+}
+
+#[derive(Deserialize)]
+struct ReportState {
+    id: u16,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+    #[serde(default)]
+    default: bool,
+}
+
+impl BlockRegistry {
+    pub fn from_report<P: AsRef<Path>>(path: P) -> Result<BlockRegistry> {
+        BlockRegistry::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<BlockRegistry> {
+        let report: HashMap<String, ReportBlock> =
+            serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        let mut registry = BlockRegistry::default();
+        for (name, block) in report {
+            for state in block.states {
+                let class = classify(&name, &state.properties);
+                let id = ChunkBlockID(state.id);
+                if registry.states.len() <= state.id as usize {
+                    registry.states.resize(state.id as usize + 1, None);
+                }
+                if state.default {
+                    registry.default_states.insert(name.clone(), id);
+                }
+                registry.states[state.id as usize] = Some(BlockState {
+                    id,
+                    name: name.clone(),
+                    properties: state.properties,
+                    class,
+                });
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn state(&self, id: ChunkBlockID) -> Option<&BlockState> {
+        self.states.get(id.0 as usize)?.as_ref()
+    }
+
+    /// Without a registry (or for unknown ids) only id 0 is known to be air, everything else
+    /// is treated as solid so the bot doesn't walk into walls it can't name
+    pub fn class(&self, id: ChunkBlockID) -> BlockClass {
+        match self.state(id) {
+            Some(state) => state.class,
+            None if id.0 == 0 => BlockClass::Transparent,
+            None => BlockClass::Solid,
+        }
+    }
+
+    /// Accepts names with and without the `minecraft:` namespace
+    pub fn default_state(&self, name: &str) -> Option<ChunkBlockID> {
+        match self.default_states.get(name) {
+            Some(id) => Some(*id),
+            None => self
+                .default_states
+                .get(&format!("minecraft:{}", name))
+                .copied(),
+        }
+    }
+
+    /// The state of `name` whose properties include all of `properties`
+    pub fn find_state(&self, name: &str, properties: &[(&str, &str)]) -> Option<ChunkBlockID> {
+        let default = self.state(self.default_state(name)?)?;
+        self.states
+            .iter()
+            .flatten()
+            .filter(|state| state.name == default.name)
+            .find(|state| {
+                properties
+                    .iter()
+                    .all(|(key, value)| state.property(key) == Some(value))
+            })
+            .map(|state| state.id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+/// Blocks without a collision box, by name and properties. Everything not listed (or liquid)
+/// is solid
+fn classify(name: &str, properties: &BTreeMap<String, String>) -> BlockClass {
+    let name = name.trim_start_matches("minecraft:");
+    match name {
+        // the lowest layer has no collision box, the others are 2 pixels each
+        "snow" if properties.get("layers").map(String::as_str) != Some("1") => {
+            return BlockClass::Solid
+        }
+        "water" | "lava" | "bubble_column" => return BlockClass::Liquid,
+        "air"
+        | "cave_air"
+        | "void_air"
+        | "grass"
+        | "tall_grass"
+        | "fern"
+        | "large_fern"
+        | "dead_bush"
+        | "seagrass"
+        | "tall_seagrass"
+        | "kelp"
+        | "kelp_plant"
+        | "sugar_cane"
+        | "vine"
+        | "cobweb"
+        | "tripwire"
+        | "tripwire_hook"
+        | "redstone_wire"
+        | "lever"
+        | "torch"
+        | "wall_torch"
+        | "redstone_torch"
+        | "redstone_wall_torch"
+        | "fire"
+        | "nether_portal"
+        | "end_portal"
+        | "end_gateway"
+        | "structure_void"
+        | "snow"
+        | "wheat"
+        | "carrots"
+        | "potatoes"
+        | "beetroots"
+        | "nether_wart"
+        | "melon_stem"
+        | "pumpkin_stem"
+        | "attached_melon_stem"
+        | "attached_pumpkin_stem"
+        | "sweet_berry_bush"
+        | "brown_mushroom"
+        | "red_mushroom"
+        | "dandelion"
+        | "poppy"
+        | "blue_orchid"
+        | "allium"
+        | "azure_bluet"
+        | "oxeye_daisy"
+        | "cornflower"
+        | "lily_of_the_valley"
+        | "wither_rose"
+        | "sunflower"
+        | "lilac"
+        | "rose_bush"
+        | "peony"
+        | "bamboo_sapling" => return BlockClass::Transparent,
+        _ => {}
+    }
+    let transparent_suffixes = [
+        "_sapling",
+        "_tulip",
+        "_sign",
+        "_banner",
+        "_button",
+        "_pressure_plate",
+        "rail",
+        "_coral",
+        "_fan",
+    ];
+    if transparent_suffixes
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        BlockClass::Transparent
+    } else {
+        BlockClass::Solid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A few blocks in the format of the server's blocks.json report
+    const REPORT: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+        "minecraft:water": {"properties": {"level": ["0", "1"]}, "states": [
+            {"id": 2, "properties": {"level": "0"}, "default": true},
+            {"id": 3, "properties": {"level": "1"}}
+        ]},
+        "minecraft:white_carpet": {"states": [{"id": 4, "default": true}]},
+        "minecraft:snow": {"properties": {"layers": ["1", "2"]}, "states": [
+            {"id": 5, "properties": {"layers": "1"}, "default": true},
+            {"id": 6, "properties": {"layers": "2"}}
+        ]},
+        "minecraft:oak_slab": {"states": [
+            {"id": 7, "properties": {"type": "bottom", "waterlogged": "true"}},
+            {"id": 8, "properties": {"type": "bottom", "waterlogged": "false"}, "default": true}
+        ]},
+        "minecraft:oak_stairs": {"states": [
+            {"id": 9, "properties": {"facing": "north", "half": "bottom"}, "default": true}
+        ]},
+        "minecraft:poppy": {"states": [{"id": 10, "default": true}]},
+        "minecraft:repeater": {"states": [{"id": 11, "default": true}]}
+    }"#;
+
+    fn class(registry: &BlockRegistry, id: u16) -> BlockClass {
+        registry.class(ChunkBlockID(id))
+    }
+
+    #[test]
+    fn classes() {
+        let registry = BlockRegistry::from_json(REPORT).unwrap();
+        assert_eq!(class(&registry, 0), BlockClass::Transparent);
+        assert_eq!(class(&registry, 1), BlockClass::Solid);
+        assert_eq!(class(&registry, 2), BlockClass::Liquid);
+        assert_eq!(class(&registry, 4), BlockClass::Solid);
+        assert_eq!(class(&registry, 5), BlockClass::Transparent);
+        assert_eq!(class(&registry, 6), BlockClass::Solid);
+        assert_eq!(class(&registry, 7), BlockClass::Solid);
+        assert_eq!(class(&registry, 9), BlockClass::Solid);
+        assert_eq!(class(&registry, 10), BlockClass::Transparent);
+        assert_eq!(class(&registry, 11), BlockClass::Solid);
+    }
+
+    #[test]
+    fn unknown_ids_are_walls_and_zero_is_air() {
+        let registry = BlockRegistry::default();
+        assert_eq!(class(&registry, 0), BlockClass::Transparent);
+        assert_eq!(class(&registry, 1), BlockClass::Solid);
+        let registry = BlockRegistry::from_json(REPORT).unwrap();
+        assert_eq!(class(&registry, 12), BlockClass::Solid);
+        assert!(registry.state(ChunkBlockID(12)).is_none());
+    }
+
+    #[test]
+    fn waterlogged_states_are_liquid() {
+        let registry = BlockRegistry::from_json(REPORT).unwrap();
+        assert!(registry.state(ChunkBlockID(7)).unwrap().is_liquid());
+        assert!(!registry.state(ChunkBlockID(8)).unwrap().is_liquid());
+    }
+
+    #[test]
+    fn states_by_name_and_properties() {
+        let registry = BlockRegistry::from_json(REPORT).unwrap();
+        assert_eq!(registry.len(), 12);
+        assert_eq!(registry.default_state("stone"), Some(ChunkBlockID(1)));
+        assert_eq!(
+            registry.default_state("minecraft:oak_slab"),
+            Some(ChunkBlockID(8))
+        );
+        assert_eq!(
+            registry.find_state("water", &[("level", "1")]),
+            Some(ChunkBlockID(3))
+        );
+        assert_eq!(registry.find_state("water", &[("level", "9")]), None);
+        let stairs = registry.state(ChunkBlockID(9)).unwrap();
+        assert_eq!(stairs.property("facing"), Some("north"));
+        assert!(registry.state(ChunkBlockID(0)).unwrap().is_air());
+    }
+}
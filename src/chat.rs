@@ -0,0 +1,361 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{Error, ErrorKind, Result};
+
+/// A JSON chat component as used for chat messages, disconnect reasons and the MOTD.
+///
+/// The wire format also allows a plain string or an array (first element with the rest as
+/// `extra`) wherever a component is expected, both are turned into the object form
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ComponentRepr")]
+pub struct TextComponent {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translate: Option<String>,
+    /// Arguments of `translate`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub with: Vec<TextComponent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<TextComponent>,
+    /// A color name like `gold`, or `#RRGGBB` since 1.16
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insertion: Option<String>,
+    #[serde(rename = "clickEvent", skip_serializing_if = "Option::is_none")]
+    pub click_event: Option<ClickEvent>,
+    #[serde(rename = "hoverEvent", skip_serializing_if = "Option::is_none")]
+    pub hover_event: Option<HoverEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClickEvent {
+    /// `open_url`, `run_command`, `suggest_command`, `change_page` or `copy_to_clipboard`
+    pub action: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HoverEvent {
+    /// `show_text`, `show_item` or `show_entity`
+    pub action: String,
+    /// Before 1.16
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// Since 1.16
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<Value>,
+}
+
+/// Every form a component can take in JSON
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComponentRepr {
+    Text(String),
+    Number(serde_json::Number),
+    Bool(bool),
+    List(Vec<TextComponent>),
+    Object(Box<ComponentObject>),
+}
+
+/// Same fields as `TextComponent`, derived without the `from` so it doesn't recurse
+#[derive(Deserialize)]
+struct ComponentObject {
+    #[serde(default)]
+    text: Value,
+    translate: Option<String>,
+    #[serde(default)]
+    with: Vec<TextComponent>,
+    #[serde(default)]
+    extra: Vec<TextComponent>,
+    color: Option<String>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underlined: Option<bool>,
+    strikethrough: Option<bool>,
+    obfuscated: Option<bool>,
+    insertion: Option<String>,
+    #[serde(rename = "clickEvent")]
+    click_event: Option<ClickEvent>,
+    #[serde(rename = "hoverEvent")]
+    hover_event: Option<HoverEvent>,
+}
+
+impl From<ComponentRepr> for TextComponent {
+    fn from(repr: ComponentRepr) -> TextComponent {
+        match repr {
+            ComponentRepr::Text(text) => TextComponent::from(text),
+            ComponentRepr::Number(number) => TextComponent::from(number.to_string()),
+            ComponentRepr::Bool(value) => TextComponent::from(value.to_string()),
+            ComponentRepr::List(mut list) => {
+                if list.is_empty() {
+                    return TextComponent::default();
+                }
+                let mut first = list.remove(0);
+                first.extra.extend(list);
+                first
+            }
+            ComponentRepr::Object(object) => {
+                let object = *object;
+                TextComponent {
+                    // servers sometimes send numbers as text
+                    text: match object.text {
+                        Value::String(text) => text,
+                        Value::Null => String::new(),
+                        other => other.to_string(),
+                    },
+                    translate: object.translate,
+                    with: object.with,
+                    extra: object.extra,
+                    color: object.color,
+                    bold: object.bold,
+                    italic: object.italic,
+                    underlined: object.underlined,
+                    strikethrough: object.strikethrough,
+                    obfuscated: object.obfuscated,
+                    insertion: object.insertion,
+                    click_event: object.click_event,
+                    hover_event: object.hover_event,
+                }
+            }
+        }
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(text: String) -> TextComponent {
+        TextComponent {
+            text,
+            ..TextComponent::default()
+        }
+    }
+}
+
+impl From<&str> for TextComponent {
+    fn from(text: &str) -> TextComponent {
+        TextComponent::from(text.to_owned())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChatKind {
+    /// A player talking in public chat
+    Public,
+    /// A player whispering to us with /msg or /tell
+    Whisper,
+}
+
+/// Who said what, pulled out of a chat component
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub kind: ChatKind,
+    pub sender: String,
+    pub body: String,
+}
+
+impl TextComponent {
+    pub fn from_json(json: &str) -> Result<TextComponent> {
+        serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// The text the vanilla client would show, without colors and formatting
+    pub fn to_plain(&self) -> String {
+        let mut plain = String::new();
+        self.write_plain(&mut plain);
+        plain
+    }
+
+    fn write_plain(&self, plain: &mut String) {
+        plain.push_str(&self.text);
+        if let Some(key) = &self.translate {
+            let args: Vec<String> = self.with.iter().map(TextComponent::to_plain).collect();
+            match translation(key) {
+                Some(format) => plain.push_str(&format_translation(format, &args)),
+                None if args.is_empty() => plain.push_str(key),
+                None => {
+                    plain.push_str(key);
+                    plain.push(' ');
+                    plain.push_str(&args.join(" "));
+                }
+            }
+        }
+        for extra in &self.extra {
+            extra.write_plain(plain);
+        }
+    }
+
+    /// Sender and body of a player chat or whisper message, `None` for everything else.
+    /// Servers that don't use the vanilla translations usually send `<sender> body` as text
+    pub fn chat_message(&self) -> Option<ChatMessage> {
+        let kind = match self.translate.as_deref() {
+            Some("chat.type.text") => Some(ChatKind::Public),
+            Some("commands.message.display.incoming") => Some(ChatKind::Whisper),
+            _ => None,
+        };
+        if let (Some(kind), [sender, body, ..]) = (kind, &self.with[..]) {
+            return Some(ChatMessage {
+                kind,
+                sender: sender.to_plain(),
+                body: body.to_plain(),
+            });
+        }
+        if self.translate.is_some() {
+            return None;
+        }
+
+        let plain = self.to_plain();
+        let rest = plain.strip_prefix('<')?;
+        let end = rest.find("> ")?;
+        Some(ChatMessage {
+            kind: ChatKind::Public,
+            sender: rest[..end].to_owned(),
+            body: rest[end + 2..].to_owned(),
+        })
+    }
+}
+
+/// The en_us strings of the translations the bot runs into, others are shown as their key
+fn translation(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "chat.type.text" => "<%s> %s",
+        "chat.type.emote" => "* %s %s",
+        "chat.type.announcement" => "[%s] %s",
+        "chat.type.admin" => "[%s: %s]",
+        "commands.message.display.incoming" => "%s whispers to you: %s",
+        "commands.message.display.outgoing" => "You whisper to %s: %s",
+        "multiplayer.player.joined" => "%s joined the game",
+        "multiplayer.player.joined.renamed" => "%s (formerly known as %s) joined the game",
+        "multiplayer.player.left" => "%s left the game",
+        "multiplayer.disconnect.kicked" => "Kicked by an operator",
+        "multiplayer.disconnect.server_shutdown" => "Server closed",
+        "multiplayer.disconnect.duplicate_login" => "You logged in from another location",
+        "death.attack.generic" => "%s died",
+        _ => return None,
+    })
+}
+
+/// Fills in `%s`, `%1$s` style placeholders, `%%` is a literal percent sign
+fn format_translation(format: &str, args: &[String]) -> String {
+    let mut result = String::new();
+    let mut next_arg = 0;
+    let mut rest = format;
+    while let Some(start) = rest.find('%') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        if rest.starts_with('%') {
+            result.push('%');
+            rest = &rest[1..];
+            continue;
+        }
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        let index = if digits > 0 && rest[digits..].starts_with("$s") {
+            let index = rest[..digits].parse::<usize>().unwrap_or(1);
+            rest = &rest[digits + 2..];
+            index.saturating_sub(1)
+        } else if rest.starts_with('s') {
+            rest = &rest[1..];
+            next_arg += 1;
+            next_arg - 1
+        } else {
+            result.push('%');
+            continue;
+        };
+        result.push_str(args.get(index).map(String::as_str).unwrap_or(""));
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_arrays_and_objects() {
+        assert_eq!(TextComponent::from_json(r#""hi""#).unwrap(), "hi".into());
+        let list = TextComponent::from_json(r#"["a", {"text": "b", "bold": true}, 3]"#).unwrap();
+        assert_eq!(list.text, "a");
+        assert_eq!(list.extra[0].bold, Some(true));
+        assert_eq!(list.to_plain(), "ab3");
+        let number = TextComponent::from_json(r#"{"text": 5, "color": "gold"}"#).unwrap();
+        assert_eq!(number.text, "5");
+        assert_eq!(number.color.as_deref(), Some("gold"));
+        assert!(TextComponent::from_json("{").is_err());
+    }
+
+    #[test]
+    fn serializes_only_set_fields() {
+        let mut component = TextComponent::from("hi");
+        component.italic = Some(false);
+        assert_eq!(component.to_json(), r#"{"text":"hi","italic":false}"#);
+        assert_eq!(
+            TextComponent::from_json(&component.to_json()).unwrap(),
+            component
+        );
+    }
+
+    #[test]
+    fn translations() {
+        let joined = TextComponent::from_json(
+            r#"{"translate": "multiplayer.player.joined", "with": ["Steve"], "color": "yellow"}"#,
+        )
+        .unwrap();
+        assert_eq!(joined.to_plain(), "Steve joined the game");
+        let unknown =
+            TextComponent::from_json(r#"{"translate": "some.key", "with": ["a", "b"]}"#).unwrap();
+        assert_eq!(unknown.to_plain(), "some.key a b");
+        let args = ["a".to_owned(), "b".to_owned()];
+        assert_eq!(format_translation("%2$s %1$s 100%%", &args), "b a 100%");
+        assert_eq!(format_translation("%s %s %s %d", &args), "a b  %d");
+    }
+
+    #[test]
+    fn chat_messages() {
+        let public = TextComponent::from_json(
+            r#"{"translate": "chat.type.text", "with": [{"text": "Steve"}, "hi there"]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            public.chat_message(),
+            Some(ChatMessage {
+                kind: ChatKind::Public,
+                sender: "Steve".into(),
+                body: "hi there".into(),
+            })
+        );
+        let whisper = TextComponent::from_json(
+            r#"{"translate": "commands.message.display.incoming", "with": ["Alex", "psst"]}"#,
+        )
+        .unwrap();
+        assert_eq!(whisper.chat_message().unwrap().kind, ChatKind::Whisper);
+
+        // plugin servers often send plain text
+        let plain = TextComponent::from_json(r#"["<", "Steve", "> #walk"]"#).unwrap();
+        assert_eq!(plain.chat_message().unwrap().body, "#walk");
+
+        let joined = TextComponent::from_json(
+            r#"{"translate": "multiplayer.player.joined", "with": ["<Steve> hi"]}"#,
+        )
+        .unwrap();
+        assert_eq!(joined.chat_message(), None);
+        assert_eq!(
+            TextComponent::from("Server restarting").chat_message(),
+            None
+        );
+    }
+}
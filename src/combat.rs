@@ -0,0 +1,338 @@
+use crate::entity::{Entities, TrackedEntity};
+use crate::game::Entity;
+use crate::interaction::MAIN_HAND;
+use crate::inventory::Inventory;
+use crate::physics::{Aabb, PLAYER_HEIGHT, PLAYER_WIDTH};
+use crate::protocol::{CombatEvent, EntityUse, Packet};
+use crate::registry::Registry;
+
+/// Client Status actions
+const PERFORM_RESPAWN: i32 = 0;
+
+/// How far a survival player hits, from the eyes to the target's hitbox
+pub const ATTACK_REACH: f64 = 3.0;
+/// Attacks per second with an empty hand or anything that isn't a weapon or tool
+const DEFAULT_ATTACK_SPEED: f32 = 4.0;
+/// Hits are only worth it once the cooldown is this far
+const FULL_STRENGTH: f32 = 1.0;
+
+/// Attacks per second of the 1.9+ cooldown, by item name
+pub fn attack_speed(item: &str) -> f32 {
+    let name = item.trim_start_matches("minecraft:");
+    match name {
+        "wooden_axe" | "stone_axe" => 0.8,
+        "iron_axe" => 0.9,
+        "golden_axe" | "diamond_axe" => 1.0,
+        "stone_hoe" => 2.0,
+        "iron_hoe" => 3.0,
+        "diamond_hoe" => 4.0,
+        "wooden_hoe" | "golden_hoe" => 1.0,
+        "trident" => 1.1,
+        _ if name.ends_with("_sword") => 1.6,
+        _ if name.ends_with("_pickaxe") => 1.2,
+        _ if name.ends_with("_shovel") => 1.0,
+        _ => DEFAULT_ATTACK_SPEED,
+    }
+}
+
+/// Our health and hunger, the target we fight and the attack cooldown. Respawns on its own
+/// after dying unless `auto_respawn` is turned off
+pub struct Combat {
+    /// `minecraft:item`, for attack speeds
+    pub items: Registry,
+    pub health: f32,
+    pub food: i32,
+    pub saturation: f32,
+    pub auto_respawn: bool,
+    entity_id: i32,
+    dead: bool,
+    target: Option<i32>,
+    /// Switching items restarts the cooldown
+    held_item: Option<i32>,
+    ticks_since_swing: u32,
+}
+
+impl Combat {
+    pub fn new(items: Registry) -> Combat {
+        Combat {
+            items,
+            health: 20.0,
+            food: 20,
+            saturation: 5.0,
+            auto_respawn: true,
+            entity_id: 0,
+            dead: false,
+            target: None,
+            held_item: None,
+            ticks_since_swing: 0,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    /// The entity attacked whenever the cooldown allows
+    pub fn target(&self) -> Option<i32> {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Option<i32>) {
+        self.target = target;
+    }
+
+    /// Tracks health and death, replies with a respawn request when we died and
+    /// `auto_respawn` is on
+    pub fn apply(&mut self, packet: &Packet) -> Option<Packet> {
+        match packet {
+            Packet::ServerJoinGame { entity_id, .. } => {
+                self.entity_id = *entity_id;
+                self.dead = false;
+                self.target = None;
+            }
+            Packet::ServerUpdateHealth {
+                health,
+                food,
+                saturation,
+            } => {
+                self.health = *health;
+                self.food = *food;
+                self.saturation = *saturation;
+                if *health <= 0.0 {
+                    return self.died();
+                }
+            }
+            Packet::ServerCombatEvent {
+                event: CombatEvent::EntityDead { player_id, .. },
+            } if *player_id == self.entity_id => {
+                return self.died();
+            }
+            // entity ids of the old world mean nothing anymore
+            Packet::ServerRespawn { .. } => {
+                self.dead = false;
+                self.target = None;
+                self.ticks_since_swing = 0;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn died(&mut self) -> Option<Packet> {
+        if self.dead {
+            return None;
+        }
+        self.dead = true;
+        self.target = None;
+        if self.auto_respawn {
+            Some(self.respawn())
+        } else {
+            None
+        }
+    }
+
+    /// Leaves the death screen
+    pub fn respawn(&self) -> Packet {
+        Packet::ClientStatus {
+            action: PERFORM_RESPAWN,
+        }
+    }
+
+    /// How far the attack cooldown has recovered, 1 hits with full strength
+    pub fn cooldown(&self, inventory: &Inventory) -> f32 {
+        let speed = inventory
+            .held_item()
+            .and_then(|item| item.name(&self.items))
+            .map_or(DEFAULT_ATTACK_SPEED, attack_speed);
+        let ticks = 20.0 / speed;
+        ((self.ticks_since_swing as f32 + 0.5) / ticks).min(1.0)
+    }
+
+    /// Whether `target`'s hitbox is within reach of the eyes. Entity sizes aren't known, so
+    /// every target is assumed to be player sized
+    pub fn in_reach(&self, entity: &Entity, target: &TrackedEntity) -> bool {
+        let (x, y, z) = entity.eye_position();
+        let hitbox = Aabb::entity(target.x, target.y, target.z, PLAYER_WIDTH, PLAYER_HEIGHT);
+        let dx = x - x.max(hitbox.min_x).min(hitbox.max_x);
+        let dy = y - y.max(hitbox.min_y).min(hitbox.max_y);
+        let dz = z - z.max(hitbox.min_z).min(hitbox.max_z);
+        (dx * dx + dy * dy + dz * dz).sqrt() <= ATTACK_REACH
+    }
+
+    /// Hits `target` right away, whatever the cooldown
+    pub fn attack(&mut self, entity: &mut Entity, target: &TrackedEntity) -> Vec<Packet> {
+        entity.look_at(target.x, target.y + PLAYER_HEIGHT / 2.0, target.z);
+        self.ticks_since_swing = 0;
+        vec![
+            Packet::ClientUseEntity {
+                target: target.id,
+                action: EntityUse::Attack,
+                sneaking: false,
+            },
+            Packet::ClientAnimation { hand: MAIN_HAND },
+        ]
+    }
+
+    /// Advances the cooldown and attacks the target once it has fully recovered. Targets that
+    /// died or left are dropped
+    pub fn tick(
+        &mut self,
+        entity: &mut Entity,
+        entities: &Entities,
+        inventory: &Inventory,
+    ) -> Vec<Packet> {
+        self.ticks_since_swing = self.ticks_since_swing.saturating_add(1);
+        let held_item = inventory.held_item().map(|item| item.item_id);
+        if held_item != self.held_item {
+            self.held_item = held_item;
+            self.ticks_since_swing = 0;
+        }
+        if self.dead {
+            return Vec::new();
+        }
+        let target = match self.target.and_then(|id| entities.get(id)) {
+            Some(target) if target.health().is_none_or(|health| health > 0.0) => target,
+            _ => {
+                self.target = None;
+                return Vec::new();
+            }
+        };
+        if self.cooldown(inventory) < FULL_STRENGTH || !self.in_reach(entity, target) {
+            return Vec::new();
+        }
+        self.attack(entity, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::ItemStack;
+    use crate::protocol::EntityMetadata;
+
+    const ITEMS: &str = r#"{"minecraft:item": {"entries": {
+        "minecraft:air": {"protocol_id": 0},
+        "minecraft:iron_sword": {"protocol_id": 1},
+        "minecraft:stick": {"protocol_id": 2}
+    }}}"#;
+
+    fn combat() -> Combat {
+        Combat::new(Registry::from_json(ITEMS, "minecraft:item").unwrap())
+    }
+
+    fn bot() -> Entity {
+        Entity {
+            entityid: 1,
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+
+    /// A zombie `distance` blocks in front of the bot
+    fn zombie(distance: f64) -> Entities {
+        let mut entities = Entities::new(Registry::default());
+        entities.apply(&Packet::ServerSpawnMob {
+            entity_id: 5,
+            uuid: 5,
+            kind: 0,
+            x: 0.5,
+            y: 64.0,
+            z: 0.5 + distance,
+            yaw: 0.0,
+            pitch: 0.0,
+            head_yaw: 0.0,
+            velocity_x: 0,
+            velocity_y: 0,
+            velocity_z: 0,
+            metadata: EntityMetadata::new(),
+        });
+        entities
+    }
+
+    /// Ticks until the first attack, `None` if none within 40 ticks
+    fn ticks_until_attack(
+        combat: &mut Combat,
+        entities: &Entities,
+        inventory: &Inventory,
+    ) -> Option<u32> {
+        let mut entity = bot();
+        (1..=40).find(|_| !combat.tick(&mut entity, entities, inventory).is_empty())
+    }
+
+    #[test]
+    fn attack_speeds() {
+        assert_eq!(attack_speed("minecraft:diamond_sword"), 1.6);
+        assert_eq!(attack_speed("wooden_axe"), 0.8);
+        assert_eq!(attack_speed("minecraft:stick"), DEFAULT_ATTACK_SPEED);
+    }
+
+    #[test]
+    fn attacks_wait_for_the_cooldown() {
+        let entities = zombie(2.0);
+        let mut inventory = Inventory::new();
+        let mut combat = combat();
+        combat.set_target(Some(5));
+        // an empty hand recovers in 5 ticks
+        assert_eq!(
+            ticks_until_attack(&mut combat, &entities, &inventory),
+            Some(5)
+        );
+        assert_eq!(combat.cooldown(&inventory), 0.1);
+
+        // a sword takes 12.5 ticks, counted after the tick that noticed the switch
+        inventory.player.slots[36] = Some(ItemStack::new(1, 1));
+        assert_eq!(
+            ticks_until_attack(&mut combat, &entities, &inventory),
+            Some(13)
+        );
+
+        let mut entity = bot();
+        let packets = combat.tick(&mut entity, &entities, &inventory);
+        assert!(packets.is_empty());
+        assert_eq!(combat.target(), Some(5));
+    }
+
+    #[test]
+    fn targets_out_of_reach_or_gone_are_not_attacked() {
+        let inventory = Inventory::new();
+        let mut combat = combat();
+        combat.set_target(Some(5));
+        assert_eq!(
+            ticks_until_attack(&mut combat, &zombie(4.0), &inventory),
+            None
+        );
+        // reach is measured to the hitbox, not its center
+        assert!(combat.in_reach(&bot(), zombie(3.2).get(5).unwrap()));
+
+        let mut entity = bot();
+        combat.tick(&mut entity, &Entities::new(Registry::default()), &inventory);
+        assert_eq!(combat.target(), None);
+    }
+
+    #[test]
+    fn respawns_once_after_dying() {
+        let mut automatic = combat();
+        let health = |health| Packet::ServerUpdateHealth {
+            health,
+            food: 20,
+            saturation: 0.0,
+        };
+        assert!(automatic.apply(&health(5.0)).is_none());
+        assert!(matches!(
+            automatic.apply(&health(0.0)),
+            Some(Packet::ClientStatus {
+                action: PERFORM_RESPAWN
+            })
+        ));
+        assert!(automatic.is_dead());
+        assert!(automatic.apply(&health(0.0)).is_none());
+
+        let mut manual = combat();
+        manual.auto_respawn = false;
+        assert!(manual.apply(&health(0.0)).is_none());
+        assert!(manual.is_dead());
+    }
+}
@@ -0,0 +1,484 @@
+use crate::chat::{ChatKind, ChatMessage};
+use crate::events::{Actions, Event, EventKind, Plugin};
+use crate::protocol::Packet;
+use crossbeam_channel::Sender;
+use err_derive::Error;
+use std::collections::{BTreeMap, HashMap};
+
+/// Longest chat message the server accepts
+const MAX_CHAT_LENGTH: usize = 256;
+
+/// What an argument parses to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArgKind {
+    Int,
+    Float,
+    /// A single word, like a player name
+    Word,
+    /// Everything up to the end of the message
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Int(i32),
+    Float(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+struct Arg {
+    name: &'static str,
+    kind: ArgKind,
+    optional: bool,
+}
+
+/// The parsed arguments of one command, by name
+#[derive(Debug, Default)]
+pub struct Args {
+    values: HashMap<&'static str, ArgValue>,
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&ArgValue> {
+        self.values.get(name)
+    }
+
+    pub fn int(&self, name: &str) -> Option<i32> {
+        match self.get(name)? {
+            ArgValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Ints are floats too
+    pub fn float(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            ArgValue::Float(value) => Some(*value),
+            ArgValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            ArgValue::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error(display = "unknown command {}, try {}help", 0, 1)]
+    Unknown(String, String),
+    #[error(display = "you may not use {}", 0)]
+    NotAllowed(String),
+    #[error(display = "usage: {}", 0)]
+    Usage(String),
+    #[error(display = "{}", 0)]
+    Failed(String),
+}
+
+/// Who ran a command, and where its replies go
+pub struct Context<'a> {
+    pub sender: &'a str,
+    pub kind: ChatKind,
+    outbound: &'a Sender<Packet>,
+    replies: Vec<String>,
+}
+
+impl<'a> Context<'a> {
+    pub fn send(&self, packet: Packet) {
+        let _ = self.outbound.send(packet);
+    }
+
+    /// Answers in the chat the command came from, whispers are answered with a whisper
+    pub fn reply<S: Into<String>>(&mut self, text: S) {
+        self.replies.push(text.into());
+    }
+}
+
+type Handler = Box<dyn FnMut(&mut Context, &Args) -> Result<(), CommandError>>;
+
+/// A chat command: its name, arguments and what it does
+pub struct Command {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Usable by everyone, not only by the allowed senders
+    pub public: bool,
+    args: Vec<Arg>,
+    handler: Handler,
+}
+
+impl Command {
+    pub fn new<F>(name: &'static str, description: &'static str, handler: F) -> Command
+    where
+        F: FnMut(&mut Context, &Args) -> Result<(), CommandError> + 'static,
+    {
+        Command {
+            name,
+            description,
+            public: false,
+            args: Vec::new(),
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn arg(mut self, name: &'static str, kind: ArgKind) -> Command {
+        self.args.push(Arg {
+            name,
+            kind,
+            optional: false,
+        });
+        self
+    }
+
+    /// Optional arguments go after all required ones
+    pub fn optional(mut self, name: &'static str, kind: ArgKind) -> Command {
+        self.args.push(Arg {
+            name,
+            kind,
+            optional: true,
+        });
+        self
+    }
+
+    pub fn public(mut self) -> Command {
+        self.public = true;
+        self
+    }
+
+    /// Like `#goto <x> <y> [z]`
+    pub fn usage(&self, prefix: &str) -> String {
+        let mut usage = format!("{}{}", prefix, self.name);
+        for arg in &self.args {
+            if arg.optional {
+                usage.push_str(&format!(" [{}]", arg.name));
+            } else {
+                usage.push_str(&format!(" <{}>", arg.name));
+            }
+        }
+        usage
+    }
+
+    /// `None` if an argument is missing, malformed or left over
+    fn parse(&self, input: &str) -> Option<Args> {
+        let mut args = Args::default();
+        let mut rest = input.trim_start();
+        for arg in &self.args {
+            if rest.is_empty() {
+                if arg.optional {
+                    break;
+                }
+                return None;
+            }
+            let word = match arg.kind {
+                ArgKind::Text => rest.trim_end(),
+                _ => rest.split_whitespace().next()?,
+            };
+            rest = rest[word.len()..].trim_start();
+            let value = match arg.kind {
+                ArgKind::Int => ArgValue::Int(word.parse().ok()?),
+                ArgKind::Float => ArgValue::Float(word.parse().ok()?),
+                ArgKind::Word | ArgKind::Text => ArgValue::Text(word.to_string()),
+            };
+            args.values.insert(arg.name, value);
+        }
+        if rest.is_empty() {
+            Some(args)
+        } else {
+            None
+        }
+    }
+}
+
+/// All chat commands, run from chat messages that start with `prefix`
+pub struct Commands {
+    pub prefix: String,
+    /// Who may use commands that aren't public, nobody if empty
+    pub allowed: Vec<String>,
+    /// Our own name, so our replies never run commands
+    own_name: String,
+    commands: BTreeMap<&'static str, Command>,
+    outbound: Sender<Packet>,
+}
+
+impl Commands {
+    pub fn new(prefix: &str, own_name: &str, outbound: Sender<Packet>) -> Commands {
+        Commands {
+            prefix: prefix.to_string(),
+            allowed: Vec::new(),
+            own_name: own_name.to_string(),
+            commands: BTreeMap::new(),
+            outbound,
+        }
+    }
+
+    /// Adds a command, replacing one with the same name
+    pub fn register(&mut self, command: Command) {
+        self.commands.insert(command.name, command);
+    }
+
+    pub fn is_allowed(&self, sender: &str) -> bool {
+        self.allowed
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(sender))
+    }
+
+    /// Runs the command in a chat message and sends its replies. Returns false if the
+    /// message isn't a command
+    pub fn handle(&mut self, message: &ChatMessage) -> bool {
+        if message.sender == self.own_name {
+            return false;
+        }
+        let line = match message.body.strip_prefix(self.prefix.as_str()) {
+            Some(line) => line.trim(),
+            None => return false,
+        };
+        let (name, input) = match line.find(char::is_whitespace) {
+            Some(end) => (&line[..end], &line[end..]),
+            None => (line, ""),
+        };
+
+        let outbound = self.outbound.clone();
+        let mut context = Context {
+            sender: &message.sender,
+            kind: message.kind,
+            outbound: &outbound,
+            replies: Vec::new(),
+        };
+        if let Err(err) = self.run(name, input, &mut context) {
+            context.reply(err.to_string());
+        }
+        for reply in &context.replies {
+            context.send(reply_packet(message, reply));
+        }
+        true
+    }
+
+    fn run(&mut self, name: &str, input: &str, context: &mut Context) -> Result<(), CommandError> {
+        if name == "help" {
+            return self.help(input.trim(), context);
+        }
+        let allowed = self.is_allowed(context.sender);
+        let prefix = self.prefix.clone();
+        let command = self
+            .commands
+            .get_mut(name)
+            .ok_or_else(|| CommandError::Unknown(name.to_string(), prefix.clone()))?;
+        if !command.public && !allowed {
+            return Err(CommandError::NotAllowed(command.name.to_string()));
+        }
+        let args = command
+            .parse(input)
+            .ok_or_else(|| CommandError::Usage(command.usage(&prefix)))?;
+        (command.handler)(context, &args)
+    }
+
+    /// Lists the commands the sender may use in one line, or explains one of them
+    fn help(&self, name: &str, context: &mut Context) -> Result<(), CommandError> {
+        if !name.is_empty() {
+            let command = self
+                .commands
+                .get(name)
+                .ok_or_else(|| CommandError::Unknown(name.to_string(), self.prefix.clone()))?;
+            context.reply(format!(
+                "{}: {}",
+                command.usage(&self.prefix),
+                command.description
+            ));
+            return Ok(());
+        }
+        let allowed = self.is_allowed(context.sender);
+        let names: Vec<&str> = self
+            .commands
+            .values()
+            .filter(|command| command.public || allowed)
+            .map(|command| command.name)
+            .collect();
+        context.reply(format!(
+            "Commands: {}. {}help <command> explains one",
+            names.join(", "),
+            self.prefix
+        ));
+        Ok(())
+    }
+}
+
+/// Runs commands from chat
+impl Plugin for Commands {
+    fn name(&self) -> &str {
+        "commands"
+    }
+
+    fn subscriptions(&self) -> Vec<EventKind> {
+        vec![EventKind::Chat]
+    }
+
+    fn on_event(&mut self, event: &Event, _: &mut Actions) {
+        if let Event::Chat(message) = event {
+            self.handle(message);
+        }
+    }
+}
+
+/// A chat message answering `message`, cut to what the server accepts
+fn reply_packet(message: &ChatMessage, text: &str) -> Packet {
+    let line = match message.kind {
+        ChatKind::Whisper => format!("/msg {} {}", message.sender, text),
+        ChatKind::Public => text.to_string(),
+    };
+    Packet::ClientChat {
+        message: line.chars().take(MAX_CHAT_LENGTH).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::Receiver;
+
+    fn commands() -> (Commands, Receiver<Packet>) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut commands = Commands::new("#", "bot", sender);
+        commands.allowed = vec!["Operator".to_string()];
+        commands.register(
+            Command::new("goto", "walks somewhere", |context, args| {
+                let (x, y) = (args.float("x").unwrap(), args.int("y").unwrap());
+                match args.float("z") {
+                    Some(z) => context.reply(format!("{} {} {}", x, y, z)),
+                    None => context.reply(format!("{} {}", x, y)),
+                }
+                Ok(())
+            })
+            .arg("x", ArgKind::Float)
+            .arg("y", ArgKind::Int)
+            .optional("z", ArgKind::Float),
+        );
+        commands.register(
+            Command::new("echo", "repeats text", |context, args| {
+                let word = args.text("word").unwrap().to_string();
+                context.reply(format!("{}: {}", word, args.text("text").unwrap()));
+                Ok(())
+            })
+            .arg("word", ArgKind::Word)
+            .arg("text", ArgKind::Text)
+            .public(),
+        );
+        (commands, receiver)
+    }
+
+    /// Runs a chat message and returns what was sent back
+    fn chat(
+        commands: &mut Commands,
+        replies: &Receiver<Packet>,
+        sender: &str,
+        body: &str,
+    ) -> Vec<String> {
+        let message = ChatMessage {
+            kind: ChatKind::Public,
+            sender: sender.to_string(),
+            body: body.to_string(),
+        };
+        assert!(commands.handle(&message));
+        replies
+            .try_iter()
+            .map(|packet| match packet {
+                Packet::ClientChat { message } => message,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn arguments_are_parsed_by_kind() {
+        let (mut commands, replies) = commands();
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#goto 1.5 64"),
+            vec!["1.5 64"]
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "operator", "#goto  -3 7  2 "),
+            vec!["-3 7 2"]
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "Anyone", "#echo hi there  you"),
+            vec!["hi: there  you"]
+        );
+    }
+
+    #[test]
+    fn bad_arguments_show_the_usage() {
+        let (mut commands, replies) = commands();
+        let usage = vec!["usage: #goto <x> <y> [z]"];
+        assert_eq!(chat(&mut commands, &replies, "Operator", "#goto 1"), usage);
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#goto 1 2.5"),
+            usage
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#goto 1 2 3 4"),
+            usage
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#jump"),
+            vec!["unknown command jump, try #help"]
+        );
+    }
+
+    #[test]
+    fn only_allowed_senders_run_private_commands() {
+        let (mut commands, replies) = commands();
+        assert_eq!(
+            chat(&mut commands, &replies, "Stranger", "#goto 1 2"),
+            vec!["you may not use goto"]
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "Stranger", "#help"),
+            vec!["Commands: echo. #help <command> explains one"]
+        );
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#help"),
+            vec!["Commands: echo, goto. #help <command> explains one"]
+        );
+
+        commands.allowed.clear();
+        assert!(!commands.is_allowed("Operator"));
+        assert_eq!(
+            chat(&mut commands, &replies, "Operator", "#goto 1 2"),
+            vec!["you may not use goto"]
+        );
+    }
+
+    #[test]
+    fn other_messages_are_not_commands() {
+        let (mut commands, replies) = commands();
+        let message = |sender: &str, body: &str| ChatMessage {
+            kind: ChatKind::Public,
+            sender: sender.to_string(),
+            body: body.to_string(),
+        };
+        assert!(!commands.handle(&message("Operator", "goto 1 2")));
+        assert!(!commands.handle(&message("bot", "#goto 1 2")));
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn whispers_are_answered_with_whispers() {
+        let (mut commands, replies) = commands();
+        let message = ChatMessage {
+            kind: ChatKind::Whisper,
+            sender: "Operator".to_string(),
+            body: format!("#echo a {}", "b".repeat(300)),
+        };
+        commands.handle(&message);
+        match replies.try_recv().unwrap() {
+            Packet::ClientChat { message } => {
+                assert!(message.starts_with("/msg Operator a: bbb"));
+                assert_eq!(message.len(), MAX_CHAT_LENGTH);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,943 @@
+use crate::block::BlockState;
+use crate::game::Entity;
+use crate::interaction::MAIN_HAND;
+use crate::inventory::Inventory;
+use crate::item::ItemStack;
+use crate::nbt::NbtTag;
+use crate::protocol::{Face, Packet, Position};
+use crate::registry::Registry;
+use crate::world::{ChunkBlockID, World};
+use std::collections::HashMap;
+
+/// Player Digging status values
+const STARTED_DIGGING: i32 = 0;
+const CANCELLED_DIGGING: i32 = 1;
+const FINISHED_DIGGING: i32 = 2;
+
+/// Effect ids
+const HASTE: i8 = 3;
+const MINING_FATIGUE: i8 = 4;
+
+/// Player inventory slot of the helmet
+const HELMET_SLOT: usize = 5;
+/// Digging speed is divided by this underwater and in the air
+const WATER_PENALTY: f32 = 5.0;
+const AIR_PENALTY: f32 = 5.0;
+/// Blocks that need the right tool take longer without it
+const HARVEST_DIVISOR: f32 = 30.0;
+const NO_HARVEST_DIVISOR: f32 = 100.0;
+/// Ticks to wait for the server to answer Finish before giving up on the dig
+const ACK_TIMEOUT_TICKS: u32 = 20;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToolKind {
+    Pickaxe,
+    Axe,
+    Shovel,
+    Sword,
+    Shears,
+}
+
+/// A tool the bot may hold
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tool {
+    pub kind: ToolKind,
+    /// Destroy speed on blocks it's made for
+    pub speed: f32,
+    /// 0 wood and gold, 1 stone, 2 iron, 3 diamond
+    pub tier: u8,
+}
+
+impl Tool {
+    /// From an item name like `minecraft:iron_pickaxe`
+    pub fn from_name(name: &str) -> Option<Tool> {
+        let name = name.trim_start_matches("minecraft:");
+        if name == "shears" {
+            return Some(Tool {
+                kind: ToolKind::Shears,
+                speed: 1.0,
+                tier: 0,
+            });
+        }
+        let (material, kind) = name.split_at(name.rfind('_')?);
+        let kind = match kind {
+            "_pickaxe" => ToolKind::Pickaxe,
+            "_axe" => ToolKind::Axe,
+            "_shovel" => ToolKind::Shovel,
+            "_sword" => ToolKind::Sword,
+            _ => return None,
+        };
+        let (speed, tier) = match material {
+            "wooden" => (2.0, 0),
+            "stone" => (4.0, 1),
+            "iron" => (6.0, 2),
+            "diamond" => (8.0, 3),
+            "golden" => (12.0, 0),
+            _ => return None,
+        };
+        Some(Tool { kind, speed, tier })
+    }
+
+    /// How fast it breaks a block, 1 is by hand
+    fn speed_on(&self, block: &str) -> f32 {
+        match self.kind {
+            ToolKind::Sword if block == "cobweb" => 15.0,
+            ToolKind::Sword if sword_effective(block) => 1.5,
+            ToolKind::Shears if block == "cobweb" || block.ends_with("_leaves") => 15.0,
+            ToolKind::Shears if block.ends_with("_wool") => 5.0,
+            ToolKind::Shears | ToolKind::Sword => 1.0,
+            kind if effective_tool(block) == Some(kind) => self.speed,
+            _ => 1.0,
+        }
+    }
+}
+
+/// The tool that speeds up breaking a block, by name without namespace
+fn effective_tool(block: &str) -> Option<ToolKind> {
+    // shears work as well, they are handled where they matter
+    if block == "cobweb" {
+        return Some(ToolKind::Sword);
+    }
+    if harvest_requirement(block).is_some() {
+        return Some(if block == "snow" || block == "snow_block" {
+            ToolKind::Shovel
+        } else {
+            ToolKind::Pickaxe
+        });
+    }
+    if block.ends_with("rail") || block.ends_with("ice") || block == "stone_button" {
+        return Some(ToolKind::Pickaxe);
+    }
+    if block.ends_with("_concrete_powder") {
+        return Some(ToolKind::Shovel);
+    }
+    match block {
+        "dirt" | "coarse_dirt" | "podzol" | "grass_block" | "mycelium" | "farmland"
+        | "grass_path" | "sand" | "red_sand" | "gravel" | "clay" | "soul_sand" => {
+            return Some(ToolKind::Shovel)
+        }
+        "chest" | "trapped_chest" | "crafting_table" | "bookshelf" | "jukebox" | "note_block"
+        | "ladder" | "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "melon" | "barrel"
+        | "lectern" | "composter" | "loom" | "cartography_table" | "fletching_table"
+        | "smithing_table" | "campfire" | "daylight_detector" => return Some(ToolKind::Axe),
+        _ => {}
+    }
+    if is_wooden(block) {
+        Some(ToolKind::Axe)
+    } else {
+        None
+    }
+}
+
+fn sword_effective(block: &str) -> bool {
+    block.ends_with("_leaves")
+        || matches!(
+            block,
+            "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "melon" | "vine" | "cocoa"
+        )
+}
+
+const WOODS: &[&str] = &["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Logs, planks and everything made of them
+fn is_wooden(block: &str) -> bool {
+    let wood = block.trim_start_matches("stripped_");
+    WOODS.iter().any(|kind| {
+        wood.starts_with(kind)
+            && !wood[kind.len()..].starts_with("_leaves")
+            && !wood[kind.len()..].starts_with("_sapling")
+    })
+}
+
+/// The pickaxe tier a block needs to drop anything, `None` if it drops by hand. Cobwebs need
+/// a sword or shears of any tier instead
+fn harvest_requirement(block: &str) -> Option<u8> {
+    match block {
+        "obsidian" => return Some(3),
+        "cobweb" => return Some(0),
+        "diamond_ore" | "diamond_block" | "emerald_ore" | "emerald_block" | "gold_ore"
+        | "gold_block" | "redstone_ore" => return Some(2),
+        "iron_ore" | "iron_block" | "lapis_ore" | "lapis_block" => return Some(1),
+        "snow" | "snow_block" => return Some(0),
+        _ => {}
+    }
+    let stone_like = [
+        "stone",
+        "cobblestone",
+        "granite",
+        "diorite",
+        "andesite",
+        "brick",
+        "sandstone",
+        "terracotta",
+        "concrete",
+        "ore",
+        "prismarine",
+        "purpur",
+        "quartz",
+        "netherrack",
+        "end_stone",
+        "furnace",
+        "smoker",
+        "anvil",
+        "iron_",
+        "coal_block",
+        "redstone_block",
+        "dispenser",
+        "dropper",
+        "observer",
+        "hopper",
+        "cauldron",
+        "brewing_stand",
+        "enchanting_table",
+        "ender_chest",
+        "spawner",
+        "magma_block",
+        "bone_block",
+        "coral_block",
+        "stonecutter",
+        "grindstone",
+        "bell",
+        "lantern",
+        "conduit",
+    ];
+    // named like stone-ish blocks but drop by hand
+    let by_hand = [
+        "_concrete_powder",
+        "_button",
+        "redstone_wire",
+        "redstone_torch",
+        "redstone_wall_torch",
+        "redstone_lamp",
+        "glowstone",
+        "sea_lantern",
+        "jack_o_lantern",
+    ];
+    let stone_like = stone_like.iter().any(|part| block.contains(part))
+        && !by_hand.iter().any(|part| block.ends_with(part));
+    if stone_like {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+/// Blocks digging can't break
+const UNBREAKABLE: &[&str] = &[
+    "air",
+    "cave_air",
+    "void_air",
+    "water",
+    "lava",
+    "bubble_column",
+    "bedrock",
+    "barrier",
+    "end_portal",
+    "end_portal_frame",
+    "end_gateway",
+    "nether_portal",
+    "command_block",
+    "chain_command_block",
+    "repeating_command_block",
+    "structure_block",
+    "jigsaw",
+    "moving_piston",
+];
+/// For blocks neither the table nor the suffixes know
+const DEFAULT_HARDNESS: f32 = 1.5;
+
+/// How long a block resists digging, `None` for unbreakable blocks. Blocks the tables
+/// don't know take as long as stone, `is_known` tells them apart
+pub fn hardness(block: &str) -> Option<f32> {
+    let block = block.trim_start_matches("minecraft:");
+    if UNBREAKABLE.contains(&block) {
+        return None;
+    }
+    Some(known_hardness(block).unwrap_or(DEFAULT_HARDNESS))
+}
+
+/// Whether the hardness tables know a block, by name
+pub fn is_known(block: &str) -> bool {
+    let block = block.trim_start_matches("minecraft:");
+    UNBREAKABLE.contains(&block) || known_hardness(block).is_some()
+}
+
+fn known_hardness(block: &str) -> Option<f32> {
+    listed_hardness(block).or_else(|| hardness_by_suffix(block))
+}
+
+fn listed_hardness(block: &str) -> Option<f32> {
+    let hardness = match block {
+        "obsidian" => 50.0,
+        "ender_chest" => 22.5,
+        "anvil" | "chipped_anvil" | "damaged_anvil" | "enchanting_table" | "iron_block"
+        | "diamond_block" | "emerald_block" | "redstone_block" | "coal_block" | "iron_door"
+        | "iron_trapdoor" | "iron_bars" | "spawner" | "bell" => 5.0,
+        "cobweb" => 4.0,
+        "dispenser" | "dropper" | "furnace" | "blast_furnace" | "smoker" | "stonecutter"
+        | "lantern" => 3.5,
+        "gold_block" | "lapis_block" | "hopper" | "observer" | "end_stone" | "dragon_egg"
+        | "beacon" | "conduit" => 3.0,
+        "blue_ice" => 2.8,
+        "chest" | "trapped_chest" | "crafting_table" | "barrel" | "lectern" | "loom"
+        | "cartography_table" | "fletching_table" | "smithing_table" => 2.5,
+        "cobblestone"
+        | "mossy_cobblestone"
+        | "bricks"
+        | "nether_bricks"
+        | "red_nether_bricks"
+        | "smooth_stone"
+        | "smooth_sandstone"
+        | "smooth_red_sandstone"
+        | "jukebox"
+        | "bone_block"
+        | "cauldron"
+        | "grindstone"
+        | "campfire" => 2.0,
+        "stone"
+        | "granite"
+        | "diorite"
+        | "andesite"
+        | "polished_granite"
+        | "polished_diorite"
+        | "polished_andesite"
+        | "stone_bricks"
+        | "mossy_stone_bricks"
+        | "cracked_stone_bricks"
+        | "chiseled_stone_bricks"
+        | "prismarine"
+        | "prismarine_bricks"
+        | "dark_prismarine"
+        | "purpur_block"
+        | "purpur_pillar"
+        | "bookshelf"
+        | "piston"
+        | "sticky_piston"
+        | "piston_head" => 1.5,
+        "terracotta" => 1.25,
+        "pumpkin" | "carved_pumpkin" | "jack_o_lantern" | "melon" | "bamboo"
+        | "nether_wart_block" => 1.0,
+        "sandstone"
+        | "chiseled_sandstone"
+        | "cut_sandstone"
+        | "red_sandstone"
+        | "chiseled_red_sandstone"
+        | "cut_red_sandstone"
+        | "quartz_block"
+        | "chiseled_quartz_block"
+        | "quartz_pillar"
+        | "note_block" => 0.8,
+        "rail" | "powered_rail" | "detector_rail" | "activator_rail" => 0.7,
+        "grass_path" => 0.65,
+        "grass_block" | "mycelium" | "farmland" | "gravel" | "clay" | "sponge" | "wet_sponge"
+        | "composter" => 0.6,
+        "dirt" | "coarse_dirt" | "podzol" | "sand" | "red_sand" | "soul_sand" | "ice"
+        | "packed_ice" | "frosted_ice" | "magma_block" | "hay_block" | "lever" | "cake"
+        | "brewing_stand" => 0.5,
+        "netherrack" | "cactus" | "ladder" => 0.4,
+        "glass" | "glass_pane" | "glowstone" | "sea_lantern" | "redstone_lamp" => 0.3,
+        "snow_block" | "vine" | "daylight_detector" => 0.2,
+        "snow" => 0.1,
+        "redstone_wire"
+        | "tnt"
+        | "sugar_cane"
+        | "lily_pad"
+        | "scaffolding"
+        | "slime_block"
+        | "dandelion"
+        | "poppy"
+        | "blue_orchid"
+        | "allium"
+        | "azure_bluet"
+        | "oxeye_daisy"
+        | "cornflower"
+        | "lily_of_the_valley"
+        | "wither_rose"
+        | "sunflower"
+        | "lilac"
+        | "rose_bush"
+        | "peony"
+        | "dead_bush"
+        | "wheat"
+        | "carrots"
+        | "potatoes"
+        | "beetroots"
+        | "nether_wart"
+        | "melon_stem"
+        | "pumpkin_stem"
+        | "attached_melon_stem"
+        | "attached_pumpkin_stem"
+        | "sweet_berry_bush"
+        | "kelp"
+        | "kelp_plant"
+        | "seagrass"
+        | "tall_seagrass"
+        | "tripwire"
+        | "tripwire_hook"
+        | "comparator"
+        | "repeater"
+        | "fire"
+        | "structure_void"
+        | "bamboo_sapling" => 0.0,
+        _ => return None,
+    };
+    Some(hardness)
+}
+
+fn hardness_by_suffix(block: &str) -> Option<f32> {
+    let suffixes: &[(&str, f32)] = &[
+        ("_ore", 3.0),
+        ("_glazed_terracotta", 1.4),
+        ("_terracotta", 1.25),
+        ("_concrete_powder", 0.5),
+        ("_concrete", 1.8),
+        ("shulker_box", 2.0),
+        ("_coral_block", 1.5),
+        ("_trapdoor", 3.0),
+        ("_door", 3.0),
+        ("_log", 2.0),
+        ("_wood", 2.0),
+        ("_planks", 2.0),
+        ("_fence_gate", 2.0),
+        ("_fence", 2.0),
+        ("_wall", 2.0),
+        ("_slab", 2.0),
+        ("_leaves", 0.2),
+        ("_wool", 0.8),
+        ("_carpet", 0.1),
+        ("_bed", 0.2),
+        ("_button", 0.5),
+        ("_pressure_plate", 0.5),
+        ("_sign", 1.0),
+        ("_banner", 1.0),
+        ("_skull", 1.0),
+        ("_head", 1.0),
+        ("_glass", 0.3),
+        ("_glass_pane", 0.3),
+    ];
+    if let Some((_, hardness)) = suffixes.iter().find(|(suffix, _)| block.ends_with(suffix)) {
+        return Some(*hardness);
+    }
+    if block.ends_with("_stairs") {
+        if is_wooden(block) {
+            return Some(2.0);
+        }
+        // brick_stairs are made of bricks, quartz_stairs of quartz_block
+        let base = block.trim_end_matches("_stairs");
+        return [
+            base.to_owned(),
+            format!("{}s", base),
+            format!("{}_block", base),
+        ]
+        .iter()
+        .find_map(|base| listed_hardness(base));
+    }
+    let plant = block.ends_with("_sapling")
+        || block.ends_with("_tulip")
+        || block.ends_with("_mushroom")
+        || block.ends_with("_coral")
+        || block.ends_with("_coral_fan")
+        || block.ends_with("_coral_wall_fan")
+        || block.contains("torch")
+        || block.contains("grass")
+        || block.contains("fern")
+        || block.contains("flower_pot");
+    if plant {
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+/// Ticks needed to break `state` with the held item, 0 breaks instantly and `None` never.
+///
+/// Follows the vanilla formula: the tool's speed, Efficiency, Haste and Mining Fatigue make
+/// digging faster or slower, being underwater without Aqua Affinity or in the air divides
+/// the speed by 5, and blocks that need a better tool take more than three times as long
+pub fn break_ticks(
+    state: &BlockState,
+    held: Option<(&ItemStack, Tool)>,
+    effects: &HashMap<i8, i8>,
+    underwater: bool,
+    onground: bool,
+) -> Option<u32> {
+    let block = state.name.trim_start_matches("minecraft:");
+    let hardness = hardness(block)?;
+    if hardness == 0.0 {
+        return Some(0);
+    }
+
+    let mut speed = held.map_or(1.0, |(_, tool)| tool.speed_on(block));
+    if speed > 1.0 {
+        let efficiency = held.map_or(0, |(item, _)| enchantment_level(item, "efficiency"));
+        if efficiency > 0 {
+            speed += (efficiency * efficiency + 1) as f32;
+        }
+    }
+    if let Some(amplifier) = effects.get(&HASTE) {
+        speed *= 1.0 + (*amplifier as f32 + 1.0) * 0.2;
+    }
+    if let Some(amplifier) = effects.get(&MINING_FATIGUE) {
+        speed *= match amplifier {
+            0 => 0.3,
+            1 => 0.09,
+            2 => 0.0027,
+            _ => 0.00081,
+        };
+    }
+    if underwater {
+        speed /= WATER_PENALTY;
+    }
+    if !onground {
+        speed /= AIR_PENALTY;
+    }
+
+    let can_harvest = match (harvest_requirement(block), held) {
+        (None, _) => true,
+        (Some(tier), Some((_, tool))) => {
+            let kind = effective_tool(block);
+            (kind == Some(tool.kind) && tool.tier >= tier)
+                || (block == "cobweb"
+                    && (tool.kind == ToolKind::Sword || tool.kind == ToolKind::Shears))
+        }
+        (Some(_), None) => false,
+    };
+    let divisor = if can_harvest {
+        HARVEST_DIVISOR
+    } else {
+        NO_HARVEST_DIVISOR
+    };
+    let damage = speed / hardness / divisor;
+    if damage >= 1.0 {
+        Some(0)
+    } else {
+        Some((1.0 / damage).ceil() as u32)
+    }
+}
+
+/// The level of an enchantment like `efficiency`, 0 if the item doesn't have it
+pub fn enchantment_level(item: &ItemStack, enchantment: &str) -> i64 {
+    let enchantments = match item.tag("Enchantments").and_then(NbtTag::as_list) {
+        Some(enchantments) => enchantments,
+        None => return 0,
+    };
+    enchantments
+        .iter()
+        .filter(|entry| {
+            entry
+                .get("id")
+                .and_then(NbtTag::as_str)
+                .map(|id| id.trim_start_matches("minecraft:"))
+                == Some(enchantment)
+        })
+        .filter_map(|entry| entry.get("lvl").and_then(NbtTag::as_i64))
+        .next()
+        .unwrap_or(0)
+}
+
+/// How a dig ended
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DigResult {
+    Finished(Position),
+    /// The server disagreed, the block is still there
+    Failed(Position),
+}
+
+struct Dig {
+    position: Position,
+    face: Face,
+    ticks_left: u32,
+    /// Finish was sent, waiting for Acknowledge Player Digging
+    finished: bool,
+    /// Ticks since Finish was sent
+    waited: u32,
+}
+
+/// Breaks one block at a time: starts digging, swings the arm until the block should be
+/// broken, then waits for the server to acknowledge or the block to turn into air
+pub struct Digger {
+    /// `minecraft:item`, to tell tools apart
+    pub items: Registry,
+    entity_id: i32,
+    /// Our own potion effects, id to amplifier
+    pub effects: HashMap<i8, i8>,
+    current: Option<Dig>,
+}
+
+impl Digger {
+    pub fn new(items: Registry) -> Digger {
+        Digger {
+            items,
+            entity_id: 0,
+            effects: HashMap::new(),
+            current: None,
+        }
+    }
+
+    pub fn is_digging(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// The block being dug, if any
+    pub fn target(&self) -> Option<Position> {
+        self.current.as_ref().map(|dig| dig.position)
+    }
+
+    /// Tracks our effects and the server's answers to digging
+    pub fn apply(&mut self, packet: &Packet) -> Option<DigResult> {
+        match *packet {
+            Packet::ServerJoinGame { entity_id, .. } => {
+                self.entity_id = entity_id;
+                self.effects.clear();
+                self.current = None;
+            }
+            // dying clears effects, and the old world is gone after a dimension change
+            Packet::ServerRespawn { .. } => {
+                self.effects.clear();
+                self.current = None;
+            }
+            Packet::ServerEntityEffect {
+                entity_id,
+                effect_id,
+                amplifier,
+                ..
+            } if entity_id == self.entity_id => {
+                self.effects.insert(effect_id, amplifier);
+            }
+            Packet::ServerRemoveEntityEffect {
+                entity_id,
+                effect_id,
+            } if entity_id == self.entity_id => {
+                self.effects.remove(&effect_id);
+            }
+            Packet::ServerAcknowledgePlayerDigging {
+                location,
+                status,
+                successful,
+                ..
+            } => {
+                let dig = self.current.as_ref()?;
+                if dig.position != location {
+                    return None;
+                }
+                // instant breaks are acknowledged as started
+                let done =
+                    status == FINISHED_DIGGING || (status == STARTED_DIGGING && dig.finished);
+                if !successful {
+                    self.current = None;
+                    return Some(DigResult::Failed(location));
+                }
+                if done {
+                    self.current = None;
+                    return Some(DigResult::Finished(location));
+                }
+            }
+            // not every server acknowledges, the block going away is enough
+            Packet::ServerBlockChange { location, block_id }
+                if block_id == ChunkBlockID::AIR.0 as i32 && self.target() == Some(location) =>
+            {
+                self.current = None;
+                return Some(DigResult::Finished(location));
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Ticks to break the block at `position` with what we hold right now
+    pub fn break_ticks(
+        &self,
+        position: Position,
+        world: &World,
+        inventory: &Inventory,
+        entity: &Entity,
+        onground: bool,
+    ) -> Option<u32> {
+        let state = world.block_state_at(position.x, position.y, position.z)?;
+        let held = inventory.held_item().and_then(|item| {
+            let tool = Tool::from_name(item.name(&self.items)?)?;
+            Some((item, tool))
+        });
+        let (x, y, z) = entity.eye_position();
+        let eyes = world.block_state_at(x.floor() as i32, y.floor() as i32, z.floor() as i32);
+        let aqua_affinity = inventory.player.slots[HELMET_SLOT]
+            .as_ref()
+            .is_some_and(|helmet| enchantment_level(helmet, "aqua_affinity") > 0);
+        let underwater = eyes.is_some_and(|eyes| eyes.is_liquid()) && !aqua_affinity;
+        break_ticks(state, held, &self.effects, underwater, onground)
+    }
+
+    /// Starts digging the block at `position` from the side facing the bot, cancelling any
+    /// other dig. Nothing happens for unbreakable or unloaded blocks
+    pub fn dig(
+        &mut self,
+        position: Position,
+        world: &World,
+        inventory: &Inventory,
+        entity: &mut Entity,
+        onground: bool,
+    ) -> Vec<Packet> {
+        let mut packets = self.cancel();
+        let ticks = match self.break_ticks(position, world, inventory, entity, onground) {
+            Some(ticks) => ticks,
+            None => return packets,
+        };
+        let center = (
+            position.x as f64 + 0.5,
+            position.y as f64 + 0.5,
+            position.z as f64 + 0.5,
+        );
+        entity.look_at(center.0, center.1, center.2);
+        let (x, y, z) = entity.eye_position();
+        let face = Face::toward(position, x, y, z);
+
+        packets.push(digging(STARTED_DIGGING, position, face));
+        packets.push(Packet::ClientAnimation { hand: MAIN_HAND });
+        self.current = Some(Dig {
+            position,
+            face,
+            ticks_left: ticks,
+            finished: ticks == 0,
+            waited: 0,
+        });
+        packets
+    }
+
+    /// Stops digging, the block stays as it is
+    pub fn cancel(&mut self) -> Vec<Packet> {
+        match self.current.take() {
+            Some(dig) if !dig.finished => vec![digging(CANCELLED_DIGGING, dig.position, dig.face)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Swings the arm while digging and sends Finish once enough ticks have passed. Gives up
+    /// on the dig when the server doesn't answer Finish in time
+    pub fn tick(&mut self) -> Vec<Packet> {
+        let dig = match &mut self.current {
+            Some(dig) if dig.finished => {
+                dig.waited += 1;
+                if dig.waited >= ACK_TIMEOUT_TICKS {
+                    self.current = None;
+                }
+                return Vec::new();
+            }
+            Some(dig) => dig,
+            None => return Vec::new(),
+        };
+        dig.ticks_left = dig.ticks_left.saturating_sub(1);
+        if dig.ticks_left > 0 {
+            return vec![Packet::ClientAnimation { hand: MAIN_HAND }];
+        }
+        dig.finished = true;
+        vec![
+            Packet::ClientAnimation { hand: MAIN_HAND },
+            digging(FINISHED_DIGGING, dig.position, dig.face),
+        ]
+    }
+}
+
+fn digging(status: i32, location: Position, face: Face) -> Packet {
+    Packet::ClientPlayerDigging {
+        status,
+        location,
+        face: face as i8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{BlockClass, BlockRegistry};
+    use crate::nbt::NbtCompound;
+    use crate::world::ChunkColumn;
+
+    const REPORT: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:dirt": {"states": [{"id": 1, "default": true}]}
+    }"#;
+    const DIRT: Position = Position { x: 1, y: 64, z: 0 };
+
+    fn block(name: &str) -> BlockState {
+        BlockState {
+            id: ChunkBlockID(1),
+            name: format!("minecraft:{}", name),
+            properties: Default::default(),
+            class: BlockClass::Solid,
+        }
+    }
+
+    fn enchanted(enchantment: &str, level: i16) -> ItemStack {
+        let mut entry = NbtCompound::new();
+        entry.insert(
+            "id".into(),
+            NbtTag::String(format!("minecraft:{}", enchantment)),
+        );
+        entry.insert("lvl".into(), NbtTag::Short(level));
+        let mut nbt = NbtCompound::new();
+        nbt.insert(
+            "Enchantments".into(),
+            NbtTag::List(vec![NbtTag::Compound(entry)]),
+        );
+        ItemStack {
+            nbt: Some(NbtTag::Compound(nbt)),
+            ..ItemStack::new(1, 1)
+        }
+    }
+
+    /// Ticks on the ground, out of water and without effects
+    fn ticks(block_name: &str, tool: Option<(&ItemStack, &str)>) -> Option<u32> {
+        let held = tool.map(|(item, name)| (item, Tool::from_name(name).unwrap()));
+        break_ticks(&block(block_name), held, &HashMap::new(), false, true)
+    }
+
+    #[test]
+    fn vanilla_break_times() {
+        let plain = ItemStack::new(1, 1);
+        // 7.5 seconds, stone doesn't drop by hand
+        assert_eq!(ticks("stone", None), Some(150));
+        assert_eq!(ticks("stone", Some((&plain, "wooden_pickaxe"))), Some(23));
+        assert_eq!(
+            ticks("obsidian", Some((&plain, "diamond_pickaxe"))),
+            Some(188)
+        );
+        let efficiency = enchanted("efficiency", 5);
+        assert_eq!(
+            ticks("obsidian", Some((&efficiency, "diamond_pickaxe"))),
+            Some(45)
+        );
+        assert_eq!(ticks("dirt", None), Some(15));
+        assert_eq!(ticks("bedrock", None), None);
+        // cobwebs only drop for swords and shears
+        assert_eq!(ticks("cobweb", None), Some(400));
+        assert_eq!(ticks("cobweb", Some((&plain, "iron_sword"))), Some(8));
+        assert_eq!(ticks("cobweb", Some((&plain, "shears"))), Some(8));
+        assert_eq!(
+            ticks("cobweb", Some((&plain, "diamond_pickaxe"))),
+            Some(400)
+        );
+    }
+
+    #[test]
+    fn instant_breaks() {
+        let plain = ItemStack::new(1, 1);
+        assert_eq!(ticks("poppy", None), Some(0));
+        // exactly one break's worth of damage in the first tick
+        assert_eq!(
+            ticks("netherrack", Some((&plain, "golden_pickaxe"))),
+            Some(0)
+        );
+        assert_eq!(ticks("netherrack", Some((&plain, "iron_pickaxe"))), Some(2));
+    }
+
+    #[test]
+    fn water_and_air_are_slower() {
+        let plain = ItemStack::new(1, 1);
+        let held = Some((&plain, Tool::from_name("wooden_pickaxe").unwrap()));
+        let effects = HashMap::new();
+        let stone = block("stone");
+        assert_eq!(break_ticks(&stone, held, &effects, true, true), Some(113));
+        assert_eq!(break_ticks(&stone, held, &effects, false, false), Some(113));
+        assert_eq!(break_ticks(&stone, held, &effects, true, false), Some(563));
+    }
+
+    #[test]
+    fn effects_change_the_speed() {
+        let stone = block("stone");
+        let mut effects = HashMap::new();
+        effects.insert(HASTE, 1);
+        assert_eq!(break_ticks(&stone, None, &effects, false, true), Some(108));
+        effects.clear();
+        effects.insert(MINING_FATIGUE, 0);
+        assert_eq!(break_ticks(&stone, None, &effects, false, true), Some(500));
+    }
+
+    #[test]
+    fn hardness_tables() {
+        assert_eq!(hardness("minecraft:stone"), Some(1.5));
+        assert_eq!(hardness("oak_log"), Some(2.0));
+        assert_eq!(hardness("stone_brick_stairs"), Some(1.5));
+        assert_eq!(hardness("white_wool"), Some(0.8));
+        assert_eq!(hardness("barrier"), None);
+        assert!(is_known("oak_planks"));
+        assert!(!is_known("mystery_block"));
+        assert_eq!(hardness("mystery_block"), Some(DEFAULT_HARDNESS));
+    }
+
+    #[test]
+    fn tools_by_name() {
+        let tool = Tool::from_name("minecraft:iron_shovel").unwrap();
+        assert_eq!(
+            (tool.kind, tool.speed, tool.tier),
+            (ToolKind::Shovel, 6.0, 2)
+        );
+        assert_eq!(Tool::from_name("shears").unwrap().kind, ToolKind::Shears);
+        assert!(Tool::from_name("stick").is_none());
+        assert!(Tool::from_name("netherite_pickaxe").is_none());
+        assert_eq!(
+            enchantment_level(&enchanted("efficiency", 3), "efficiency"),
+            3
+        );
+        assert_eq!(enchantment_level(&ItemStack::new(1, 1), "efficiency"), 0);
+    }
+
+    /// Starts digging a lone dirt block next to the bot and ticks until Finish went out
+    fn dug_dirt() -> Digger {
+        let mut world = World::new(BlockRegistry::from_json(REPORT).unwrap());
+        world.columns.insert((0, 0), ChunkColumn::new(0, 0));
+        world.set_block(DIRT, ChunkBlockID(1));
+        let mut entity = Entity {
+            entityid: 1,
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        let mut digger = Digger::new(Registry::default());
+        let started = digger.dig(DIRT, &world, &Inventory::new(), &mut entity, true);
+        assert!(matches!(
+            started[0],
+            Packet::ClientPlayerDigging {
+                status: STARTED_DIGGING,
+                ..
+            }
+        ));
+        // 15 ticks by hand
+        for _ in 0..14 {
+            assert_eq!(digger.tick().len(), 1);
+        }
+        assert!(matches!(
+            digger.tick()[1],
+            Packet::ClientPlayerDigging {
+                status: FINISHED_DIGGING,
+                ..
+            }
+        ));
+        digger
+    }
+
+    #[test]
+    fn unanswered_digs_time_out() {
+        let mut digger = dug_dirt();
+        for _ in 1..ACK_TIMEOUT_TICKS {
+            assert!(digger.tick().is_empty());
+        }
+        assert_eq!(digger.target(), Some(DIRT));
+        digger.tick();
+        assert!(!digger.is_digging());
+    }
+
+    #[test]
+    fn the_block_turning_into_air_finishes_the_dig() {
+        let mut digger = dug_dirt();
+        let change = |location, block_id| Packet::ServerBlockChange { location, block_id };
+        assert_eq!(digger.apply(&change(DIRT, 1)), None);
+        assert_eq!(digger.apply(&change(Position { x: 2, ..DIRT }, 0)), None);
+        assert_eq!(
+            digger.apply(&change(DIRT, 0)),
+            Some(DigResult::Finished(DIRT))
+        );
+        assert!(!digger.is_digging());
+    }
+}
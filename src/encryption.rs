@@ -0,0 +1,267 @@
+use aes::Aes128;
+use cfb8::cipher::generic_array::GenericArray;
+use cfb8::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use num_bigint::BigUint;
+use rand::Rng;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+type Encryptor = cfb8::Encryptor<Aes128>;
+type Decryptor = cfb8::Decryptor<Aes128>;
+
+/// The AES/CFB8 cipher pair of a connection, the shared secret is both key and IV
+pub struct Cipher {
+    encryptor: Encryptor,
+    decryptor: Decryptor,
+}
+
+impl Cipher {
+    pub fn new(shared_secret: &[u8]) -> Result<Cipher> {
+        let invalid = |_| Error::new(ErrorKind::InvalidInput, "invalid shared secret length");
+        Ok(Cipher {
+            encryptor: Encryptor::new_from_slices(shared_secret, shared_secret).map_err(invalid)?,
+            decryptor: Decryptor::new_from_slices(shared_secret, shared_secret).map_err(invalid)?,
+        })
+    }
+
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        // CFB8 works on single byte blocks
+        for byte in data.chunks_mut(1) {
+            self.encryptor
+                .encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.decryptor
+                .decrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+/// Wraps a stream and en-/decrypts everything passing through it once `enable` was called.
+/// Writes never block: what a non-blocking stream doesn't take right away is kept and sent
+/// by the next write or `write_pending`
+pub struct CipherStream<S> {
+    inner: S,
+    cipher: Option<Cipher>,
+    /// Bytes written to us but not yet to `inner`, already encrypted
+    pending: Vec<u8>,
+}
+
+impl<S> CipherStream<S> {
+    pub fn new(inner: S) -> CipherStream<S> {
+        CipherStream {
+            inner,
+            cipher: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Every byte after this call is AES/CFB8 encrypted
+    pub fn enable(&mut self, shared_secret: &[u8]) -> Result<()> {
+        self.cipher = Some(Cipher::new(shared_secret)?);
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Whether some written bytes still wait for `inner` to take them
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+impl<S: Write> CipherStream<S> {
+    /// Sends as much of the buffered bytes as `inner` takes without blocking
+    pub fn write_pending(&mut self) -> Result<()> {
+        while !self.pending.is_empty() {
+            match self.inner.write(&self.pending) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(n) => {
+                    self.pending.drain(..n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read> Read for CipherStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(&mut buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl<S: Write> Write for CipherStream<S> {
+    /// Takes all of `buf`, the cipher advances over every byte of it
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let start = self.pending.len();
+        self.pending.extend_from_slice(buf);
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut self.pending[start..]);
+        }
+        self.write_pending()?;
+        Ok(buf.len())
+    }
+
+    /// Fails with `WouldBlock` while buffered bytes are left
+    fn flush(&mut self) -> Result<()> {
+        self.write_pending()?;
+        if self.has_pending() {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+        self.inner.flush()
+    }
+}
+
+pub fn generate_shared_secret() -> [u8; 16] {
+    rand::thread_rng().gen()
+}
+
+/// Encrypts `data` with the DER encoded public key of the server (RSA, PKCS#1 v1.5 padding)
+pub fn rsa_encrypt(public_key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let (n, e) = rsa_der::public_key_from_der(public_key)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid public key: {}", e)))?;
+    let n = BigUint::from_bytes_be(&n);
+    let e = BigUint::from_bytes_be(&e);
+
+    let key_len = n.bits().div_ceil(8);
+    if data.len() + 11 > key_len {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "data too long for public key",
+        ));
+    }
+
+    // 0x00 0x02 [non-zero random padding] 0x00 [data]
+    let mut rng = rand::thread_rng();
+    let mut block = vec![0u8; key_len];
+    block[1] = 0x02;
+    let data_start = key_len - data.len();
+    for byte in &mut block[2..data_start - 1] {
+        *byte = rng.gen_range(1, 256) as u8;
+    }
+    block[data_start..].copy_from_slice(data);
+
+    let encrypted = BigUint::from_bytes_be(&block).modpow(&e, &n).to_bytes_be();
+    let mut out = vec![0u8; key_len - encrypted.len()];
+    out.extend_from_slice(&encrypted);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Takes at most `capacity` bytes per call, like a full socket buffer
+    struct Throttled {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Write for Throttled {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            if self.capacity == 0 {
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            let n = buf.len().min(self.capacity);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cipher_roundtrip() {
+        let secret = [7u8; 16];
+        let mut data = b"hello world".to_vec();
+        Cipher::new(&secret).unwrap().encrypt(&mut data);
+        assert_ne!(&data[..], b"hello world");
+        Cipher::new(&secret).unwrap().decrypt(&mut data);
+        assert_eq!(&data[..], b"hello world");
+    }
+
+    #[test]
+    fn blocked_writes_are_buffered() {
+        let secret = [3u8; 16];
+        let mut stream = CipherStream::new(Throttled {
+            written: Vec::new(),
+            capacity: 0,
+        });
+        stream.enable(&secret).unwrap();
+        assert_eq!(stream.write(b"first ").unwrap(), 6);
+        assert_eq!(stream.write(b"second").unwrap(), 6);
+        assert!(stream.has_pending());
+        assert_eq!(stream.flush().unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        stream.get_mut().capacity = 4;
+        stream.write_pending().unwrap();
+        assert!(!stream.has_pending());
+        stream.flush().unwrap();
+
+        let mut sent = stream.get_ref().written.clone();
+        Cipher::new(&secret).unwrap().decrypt(&mut sent);
+        assert_eq!(&sent[..], b"first second");
+    }
+
+    /// A 512 bit test key, small enough to keep the test fast
+    const MODULUS: &[u8] = b"9e32a67b7d9861617da21af2fee80eb39bb08c1855465ec775281eda712e339c6f74682b3a520b32cd7d902a1b852198a6a9c76883b793108f601fbea10bc819";
+    const PRIVATE_EXPONENT: &[u8] = b"c6980f2737a3f39bcf7b91193ba755cd63c55e19adf389481b6b22505785088377ff07a6f27ab5f4c301b804671008cac25ff6e4b76198bc9ac874620f821e1";
+
+    fn public_key() -> Vec<u8> {
+        // the bytes are read as signed, the zero keeps the modulus positive
+        let mut n = vec![0];
+        n.extend(BigUint::parse_bytes(MODULUS, 16).unwrap().to_bytes_be());
+        rsa_der::public_key_to_der(&n, &[0x01, 0x00, 0x01])
+    }
+
+    #[test]
+    fn rsa_pads_with_pkcs1_v1_5() {
+        let secret = [7u8; 16];
+        let encrypted = rsa_encrypt(&public_key(), &secret).unwrap();
+        assert_eq!(encrypted.len(), 64);
+
+        let n = BigUint::parse_bytes(MODULUS, 16).unwrap();
+        let d = BigUint::parse_bytes(PRIVATE_EXPONENT, 16).unwrap();
+        let decrypted = BigUint::from_bytes_be(&encrypted)
+            .modpow(&d, &n)
+            .to_bytes_be();
+        // the leading zero byte is lost in the number
+        assert_eq!(decrypted.len(), 63);
+        assert_eq!(decrypted[0], 0x02);
+        let padding = &decrypted[1..63 - 16 - 1];
+        assert!(padding.iter().all(|byte| *byte != 0));
+        assert_eq!(decrypted[63 - 16 - 1], 0x00);
+        assert_eq!(&decrypted[63 - 16..], &secret);
+    }
+
+    #[test]
+    fn rsa_rejects_data_longer_than_the_key() {
+        // 11 bytes of the 64 go to the padding
+        assert!(rsa_encrypt(&public_key(), &[1; 53]).is_ok());
+        let err = rsa_encrypt(&public_key(), &[1; 54]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(rsa_encrypt(&[0x30, 0x00], &[0; 16]).is_err());
+    }
+}
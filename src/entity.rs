@@ -0,0 +1,554 @@
+use crate::chat::TextComponent;
+use crate::item::ItemStack;
+use crate::protocol::{EntityMetadata, MetadataValue, Packet, Pose};
+use crate::registry::Registry;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Velocities are sent in 1/8000 blocks per tick
+const VELOCITY_UNIT: f64 = 8000.0;
+/// Relative moves are sent in 1/4096 blocks
+const DELTA_UNIT: f64 = 4096.0;
+
+/// Metadata indices of 1.14.4
+const METADATA_FLAGS: u8 = 0;
+const METADATA_CUSTOM_NAME: u8 = 2;
+const METADATA_POSE: u8 = 6;
+/// Living entities keep their hand state and item entities their item at 7
+const METADATA_ITEM: u8 = 7;
+const METADATA_HEALTH: u8 = 8;
+
+const FLAG_ON_FIRE: i8 = 0x01;
+const FLAG_SNEAKING: i8 = 0x02;
+
+/// An entity the server told us about
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedEntity {
+    pub id: i32,
+    pub uuid: i128,
+    /// Id in the `minecraft:entity_type` registry
+    pub kind: i32,
+    pub player: bool,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub head_yaw: f32,
+    /// Blocks per tick
+    pub velocity: (f64, f64, f64),
+    pub onground: bool,
+    /// The data field of Spawn Object, its meaning depends on the kind
+    pub object_data: i32,
+    /// Everything Entity Metadata sent so far, newer values replace older ones
+    pub metadata: EntityMetadata,
+}
+
+impl TrackedEntity {
+    pub fn new(id: i32, uuid: i128, kind: i32, x: f64, y: f64, z: f64) -> TrackedEntity {
+        TrackedEntity {
+            id,
+            uuid,
+            kind,
+            player: false,
+            x,
+            y,
+            z,
+            yaw: 0.0,
+            pitch: 0.0,
+            head_yaw: 0.0,
+            velocity: (0.0, 0.0, 0.0),
+            onground: false,
+            object_data: 0,
+            metadata: EntityMetadata::new(),
+        }
+    }
+
+    pub fn custom_name(&self) -> Option<TextComponent> {
+        match self.metadata.get(&METADATA_CUSTOM_NAME) {
+            Some(MetadataValue::OptChat(Some(name))) => TextComponent::from_json(name).ok(),
+            _ => None,
+        }
+    }
+
+    /// Only living entities have health
+    pub fn health(&self) -> Option<f32> {
+        match self.metadata.get(&METADATA_HEALTH) {
+            Some(MetadataValue::Float(health)) => Some(*health),
+            _ => None,
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        match self.metadata.get(&METADATA_POSE) {
+            Some(MetadataValue::Pose(pose)) => *pose,
+            _ => Pose::Standing,
+        }
+    }
+
+    fn flags(&self) -> i8 {
+        match self.metadata.get(&METADATA_FLAGS) {
+            Some(MetadataValue::Byte(flags)) => *flags,
+            _ => 0,
+        }
+    }
+
+    pub fn is_on_fire(&self) -> bool {
+        self.flags() & FLAG_ON_FIRE != 0
+    }
+
+    pub fn is_sneaking(&self) -> bool {
+        self.flags() & FLAG_SNEAKING != 0 || self.pose() == Pose::Sneaking
+    }
+
+    /// The item a dropped item entity stands for
+    pub fn dropped_item(&self) -> Option<&ItemStack> {
+        match self.metadata.get(&METADATA_ITEM) {
+            Some(MetadataValue::Slot(item)) => item.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn distance_to(&self, x: f64, y: f64, z: f64) -> f64 {
+        ((self.x - x).powi(2) + (self.y - y).powi(2) + (self.z - z).powi(2)).sqrt()
+    }
+}
+
+/// All entities around the bot, filled from spawn, movement and destroy packets
+pub struct Entities {
+    entities: HashMap<i32, TrackedEntity>,
+    /// `minecraft:entity_type`, to look entities up by name
+    pub types: Registry,
+}
+
+impl Entities {
+    pub fn new(types: Registry) -> Entities {
+        Entities {
+            entities: HashMap::new(),
+            types,
+        }
+    }
+
+    /// Updates the table from an entity packet, everything else is ignored
+    pub fn apply(&mut self, packet: &Packet) {
+        match *packet {
+            Packet::ServerEntityMetadata {
+                entity_id,
+                ref metadata,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.metadata.extend(metadata.clone());
+                }
+            }
+            Packet::ServerSpawnObject {
+                entity_id,
+                uuid,
+                kind,
+                x,
+                y,
+                z,
+                pitch,
+                yaw,
+                data,
+                velocity_x,
+                velocity_y,
+                velocity_z,
+            } => {
+                let mut entity = TrackedEntity::new(entity_id, uuid, kind, x, y, z);
+                entity.yaw = yaw;
+                entity.pitch = pitch;
+                entity.object_data = data;
+                entity.velocity = velocity(velocity_x, velocity_y, velocity_z);
+                self.entities.insert(entity_id, entity);
+            }
+            Packet::ServerSpawnMob {
+                entity_id,
+                uuid,
+                kind,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                head_yaw,
+                velocity_x,
+                velocity_y,
+                velocity_z,
+                ref metadata,
+            } => {
+                let mut entity = TrackedEntity::new(entity_id, uuid, kind, x, y, z);
+                entity.yaw = yaw;
+                entity.pitch = pitch;
+                entity.head_yaw = head_yaw;
+                entity.metadata = metadata.clone();
+                entity.velocity = velocity(velocity_x, velocity_y, velocity_z);
+                self.entities.insert(entity_id, entity);
+            }
+            Packet::ServerSpawnPlayer {
+                entity_id,
+                uuid,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                ref metadata,
+            } => {
+                let kind = self.types.id("minecraft:player").unwrap_or(-1);
+                let mut entity = TrackedEntity::new(entity_id, uuid, kind, x, y, z);
+                entity.player = true;
+                entity.metadata = metadata.clone();
+                entity.yaw = yaw;
+                entity.pitch = pitch;
+                entity.head_yaw = yaw;
+                self.entities.insert(entity_id, entity);
+            }
+            Packet::ServerEntityRelativeMove {
+                entity_id,
+                delta_x,
+                delta_y,
+                delta_z,
+                onground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.x += delta_x as f64 / DELTA_UNIT;
+                    entity.y += delta_y as f64 / DELTA_UNIT;
+                    entity.z += delta_z as f64 / DELTA_UNIT;
+                    entity.onground = onground;
+                }
+            }
+            Packet::ServerEntityLookAndRelativeMove {
+                entity_id,
+                delta_x,
+                delta_y,
+                delta_z,
+                yaw,
+                pitch,
+                onground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.x += delta_x as f64 / DELTA_UNIT;
+                    entity.y += delta_y as f64 / DELTA_UNIT;
+                    entity.z += delta_z as f64 / DELTA_UNIT;
+                    entity.yaw = yaw;
+                    entity.pitch = pitch;
+                    entity.onground = onground;
+                }
+            }
+            Packet::ServerEntityLook {
+                entity_id,
+                yaw,
+                pitch,
+                onground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.yaw = yaw;
+                    entity.pitch = pitch;
+                    entity.onground = onground;
+                }
+            }
+            Packet::ServerEntityTeleport {
+                entity_id,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                onground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.x = x;
+                    entity.y = y;
+                    entity.z = z;
+                    entity.yaw = yaw;
+                    entity.pitch = pitch;
+                    entity.onground = onground;
+                }
+            }
+            Packet::ServerEntityVelocity {
+                entity_id,
+                velocity_x,
+                velocity_y,
+                velocity_z,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.velocity = velocity(velocity_x, velocity_y, velocity_z);
+                }
+            }
+            Packet::ServerEntityHeadLook {
+                entity_id,
+                head_yaw,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.head_yaw = head_yaw;
+                }
+            }
+            Packet::ServerDestroyEntities { ref entity_ids } => {
+                for entity_id in entity_ids {
+                    self.entities.remove(entity_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, id: i32) -> Option<&TrackedEntity> {
+        self.entities.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: i32) -> Option<&mut TrackedEntity> {
+        self.entities.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrackedEntity> {
+        self.entities.values()
+    }
+
+    pub fn by_uuid(&self, uuid: i128) -> Option<&TrackedEntity> {
+        self.iter().find(|entity| entity.uuid == uuid)
+    }
+
+    pub fn of_kind(&self, kind: i32) -> impl Iterator<Item = &TrackedEntity> {
+        self.iter().filter(move |entity| entity.kind == kind)
+    }
+
+    /// By registry name like `zombie` or `minecraft:item`, nothing if the name is unknown
+    pub fn of_kind_name<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a TrackedEntity> {
+        let kind = self.types.id(name);
+        self.iter().filter(move |entity| Some(entity.kind) == kind)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &TrackedEntity> {
+        self.iter().filter(|entity| entity.player)
+    }
+
+    /// All entities within `radius` blocks, nearest first
+    pub fn within(&self, x: f64, y: f64, z: f64, radius: f64) -> Vec<&TrackedEntity> {
+        let mut near: Vec<(f64, &TrackedEntity)> = self
+            .iter()
+            .map(|entity| (entity.distance_to(x, y, z), entity))
+            .filter(|(distance, _)| *distance <= radius)
+            .collect();
+        near.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        near.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    pub fn nearest<F>(&self, x: f64, y: f64, z: f64, filter: F) -> Option<&TrackedEntity>
+    where
+        F: Fn(&TrackedEntity) -> bool,
+    {
+        self.iter().filter(|entity| filter(entity)).min_by(|a, b| {
+            a.distance_to(x, y, z)
+                .partial_cmp(&b.distance_to(x, y, z))
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+
+    /// Forgets every entity, e.g. after a respawn into another dimension
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// The id of the entity a spawn packet adds
+pub fn spawned_entity(packet: &Packet) -> Option<i32> {
+    match *packet {
+        Packet::ServerSpawnObject { entity_id, .. }
+        | Packet::ServerSpawnMob { entity_id, .. }
+        | Packet::ServerSpawnPlayer { entity_id, .. } => Some(entity_id),
+        _ => None,
+    }
+}
+
+fn velocity(x: i16, y: i16, z: i16) -> (f64, f64, f64) {
+    (
+        x as f64 / VELOCITY_UNIT,
+        y as f64 / VELOCITY_UNIT,
+        z as f64 / VELOCITY_UNIT,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPES: &str = r#"{"minecraft:entity_type": {"entries": {
+        "minecraft:item": {"protocol_id": 0},
+        "minecraft:player": {"protocol_id": 1},
+        "minecraft:zombie": {"protocol_id": 2}
+    }}}"#;
+
+    fn entities() -> Entities {
+        Entities::new(Registry::from_json(TYPES, "minecraft:entity_type").unwrap())
+    }
+
+    fn spawn_player(entity_id: i32, x: f64, z: f64) -> Packet {
+        Packet::ServerSpawnPlayer {
+            entity_id,
+            uuid: entity_id as i128,
+            x,
+            y: 64.0,
+            z,
+            yaw: 90.0,
+            pitch: 0.0,
+            metadata: EntityMetadata::new(),
+        }
+    }
+
+    fn spawn_object(entity_id: i32, kind: i32, x: f64) -> Packet {
+        Packet::ServerSpawnObject {
+            entity_id,
+            uuid: 100 + entity_id as i128,
+            kind,
+            x,
+            y: 64.0,
+            z: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            data: 1,
+            velocity_x: 8000,
+            velocity_y: -4000,
+            velocity_z: 0,
+        }
+    }
+
+    #[test]
+    fn spawns_are_tracked_until_destroyed() {
+        let mut entities = entities();
+        entities.apply(&spawn_player(1, 0.0, 0.0));
+        entities.apply(&spawn_object(2, 0, 3.0));
+        assert_eq!(spawned_entity(&spawn_object(2, 0, 3.0)), Some(2));
+        assert_eq!(entities.len(), 2);
+
+        let player = entities.get(1).unwrap();
+        assert!(player.player);
+        assert_eq!(player.kind, 1);
+        assert_eq!(player.head_yaw, 90.0);
+        let item = entities.by_uuid(102).unwrap();
+        assert_eq!(item.velocity, (1.0, -0.5, 0.0));
+        assert_eq!(entities.of_kind_name("item").count(), 1);
+        assert_eq!(entities.of_kind_name("creeper").count(), 0);
+        assert_eq!(entities.players().count(), 1);
+
+        entities.apply(&Packet::ServerDestroyEntities {
+            entity_ids: vec![2, 7],
+        });
+        assert!(entities.get(2).is_none());
+        assert_eq!(entities.len(), 1);
+    }
+
+    #[test]
+    fn moves_are_relative_and_teleports_absolute() {
+        let mut entities = entities();
+        entities.apply(&spawn_player(1, 10.0, -5.0));
+        entities.apply(&Packet::ServerEntityRelativeMove {
+            entity_id: 1,
+            delta_x: 4096,
+            delta_y: -2048,
+            delta_z: 1024,
+            onground: true,
+        });
+        let player = entities.get(1).unwrap();
+        assert_eq!((player.x, player.y, player.z), (11.0, 63.5, -4.75));
+        assert!(player.onground);
+
+        entities.apply(&Packet::ServerEntityTeleport {
+            entity_id: 1,
+            x: 0.5,
+            y: 70.0,
+            z: 0.5,
+            yaw: 180.0,
+            pitch: 45.0,
+            onground: false,
+        });
+        let player = entities.get(1).unwrap();
+        assert_eq!((player.x, player.y, player.z), (0.5, 70.0, 0.5));
+        assert_eq!((player.yaw, player.pitch), (180.0, 45.0));
+
+        // unknown entities are ignored
+        entities.apply(&Packet::ServerEntityRelativeMove {
+            entity_id: 9,
+            delta_x: 1,
+            delta_y: 1,
+            delta_z: 1,
+            onground: true,
+        });
+        assert_eq!(entities.len(), 1);
+    }
+
+    #[test]
+    fn nearest_first() {
+        let mut entities = entities();
+        entities.apply(&spawn_object(1, 2, 5.0));
+        entities.apply(&spawn_object(2, 2, -2.0));
+        entities.apply(&spawn_object(3, 0, 1.0));
+        let near: Vec<i32> = entities
+            .within(0.0, 64.0, 0.0, 3.0)
+            .iter()
+            .map(|entity| entity.id)
+            .collect();
+        assert_eq!(near, vec![3, 2]);
+        let zombie = entities.nearest(4.0, 64.0, 0.0, |entity| entity.kind == 2);
+        assert_eq!(zombie.map(|entity| entity.id), Some(1));
+
+        entities.clear();
+        assert_eq!(entities.len(), 0);
+    }
+
+    #[test]
+    fn metadata_accessors() {
+        let mut entities = entities();
+        entities.apply(&spawn_player(1, 0.0, 0.0));
+        entities.apply(&spawn_object(2, 0, 0.0));
+        let player = entities.get(1).unwrap();
+        assert_eq!(player.health(), None);
+        assert_eq!(player.pose(), Pose::Standing);
+        assert!(!player.is_on_fire() && !player.is_sneaking());
+
+        let mut metadata = EntityMetadata::new();
+        metadata.insert(METADATA_FLAGS, MetadataValue::Byte(FLAG_ON_FIRE));
+        metadata.insert(
+            METADATA_CUSTOM_NAME,
+            MetadataValue::OptChat(Some(r#"{"text":"Steve"}"#.into())),
+        );
+        metadata.insert(METADATA_HEALTH, MetadataValue::Float(20.0));
+        entities.apply(&Packet::ServerEntityMetadata {
+            entity_id: 1,
+            metadata,
+        });
+        // later packets only carry what changed
+        let mut metadata = EntityMetadata::new();
+        metadata.insert(METADATA_HEALTH, MetadataValue::Float(13.5));
+        metadata.insert(METADATA_POSE, MetadataValue::Pose(Pose::Sneaking));
+        entities.apply(&Packet::ServerEntityMetadata {
+            entity_id: 1,
+            metadata,
+        });
+        let player = entities.get(1).unwrap();
+        assert_eq!(player.health(), Some(13.5));
+        assert!(player.is_on_fire() && player.is_sneaking());
+        assert_eq!(player.custom_name().unwrap().to_plain(), "Steve");
+
+        let mut metadata = EntityMetadata::new();
+        metadata.insert(
+            METADATA_ITEM,
+            MetadataValue::Slot(Some(ItemStack::new(5, 3))),
+        );
+        entities.apply(&Packet::ServerEntityMetadata {
+            entity_id: 2,
+            metadata,
+        });
+        assert_eq!(
+            entities.get(2).unwrap().dropped_item(),
+            Some(&ItemStack::new(5, 3))
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::chat::ChatMessage;
+use crate::entity::TrackedEntity;
+use crate::protocol::Packet;
+use crate::world::BlockChange;
+use crossbeam_channel::{Receiver, Sender};
+
+/// Something that happened to the bot
+#[derive(Debug, Clone)]
+pub enum Event {
+    PacketReceived(Packet),
+    PacketSent(Packet),
+    /// A player talked in public chat or whispered to us
+    Chat(ChatMessage),
+    /// The server placed us in the world, after joining and after every respawn
+    Spawned,
+    Died,
+    EntityAppeared(TrackedEntity),
+    BlockChanged(BlockChange),
+    /// Every game tick, after the movement was sent
+    Tick,
+    /// The server kicked us or the connection broke, with the reason
+    Disconnected(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    PacketReceived,
+    PacketSent,
+    Chat,
+    Spawned,
+    Died,
+    EntityAppeared,
+    BlockChanged,
+    Tick,
+    Disconnected,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 9] = [
+        EventKind::PacketReceived,
+        EventKind::PacketSent,
+        EventKind::Chat,
+        EventKind::Spawned,
+        EventKind::Died,
+        EventKind::EntityAppeared,
+        EventKind::BlockChanged,
+        EventKind::Tick,
+        EventKind::Disconnected,
+    ];
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::PacketReceived(_) => EventKind::PacketReceived,
+            Event::PacketSent(_) => EventKind::PacketSent,
+            Event::Chat(_) => EventKind::Chat,
+            Event::Spawned => EventKind::Spawned,
+            Event::Died => EventKind::Died,
+            Event::EntityAppeared(_) => EventKind::EntityAppeared,
+            Event::BlockChanged(_) => EventKind::BlockChanged,
+            Event::Tick => EventKind::Tick,
+            Event::Disconnected(_) => EventKind::Disconnected,
+        }
+    }
+}
+
+/// What a plugin can do in response to an event
+pub struct Actions<'a> {
+    outbound: &'a Sender<Packet>,
+}
+
+impl<'a> Actions<'a> {
+    pub fn send(&self, packet: Packet) {
+        let _ = self.outbound.send(packet);
+    }
+
+    pub fn chat(&self, message: &str) {
+        self.send(Packet::ClientChat {
+            message: message.to_string(),
+        });
+    }
+}
+
+/// A feature that reacts to events. Plugins that need the world, entities or the navigator
+/// keep clones of the shared state they were built with
+pub trait Plugin {
+    fn name(&self) -> &str;
+
+    /// The events `on_event` is called for, all of them unless overridden
+    fn subscriptions(&self) -> Vec<EventKind> {
+        EventKind::ALL.to_vec()
+    }
+
+    fn on_event(&mut self, event: &Event, actions: &mut Actions);
+}
+
+struct Registered {
+    plugin: Box<dyn Plugin>,
+    subscriptions: Vec<EventKind>,
+}
+
+/// Hands events to the plugins subscribed to them, in the order they were registered.
+/// Plugins only run on the thread that owns the bus, other threads queue their events
+/// through `sender`
+pub struct EventBus {
+    plugins: Vec<Registered>,
+    outbound: Sender<Packet>,
+    queue_sender: Sender<Event>,
+    queue: Receiver<Event>,
+}
+
+impl EventBus {
+    pub fn new(outbound: Sender<Packet>) -> EventBus {
+        let (queue_sender, queue) = crossbeam_channel::unbounded();
+        EventBus {
+            plugins: Vec::new(),
+            outbound,
+            queue_sender,
+            queue,
+        }
+    }
+
+    pub fn register<P: Plugin + 'static>(&mut self, plugin: P) {
+        let subscriptions = plugin.subscriptions();
+        self.plugins.push(Registered {
+            plugin: Box::new(plugin),
+            subscriptions,
+        });
+    }
+
+    /// Queues events from other threads, `take_queued` collects them
+    pub fn sender(&self) -> Sender<Event> {
+        self.queue_sender.clone()
+    }
+
+    pub fn take_queued(&self) -> Vec<Event> {
+        self.queue.try_iter().collect()
+    }
+
+    /// Whether any plugin listens to `kind`, to skip building events nobody wants
+    pub fn wants(&self, kind: EventKind) -> bool {
+        self.plugins
+            .iter()
+            .any(|registered| registered.subscriptions.contains(&kind))
+    }
+
+    pub fn emit(&mut self, event: &Event) {
+        let kind = event.kind();
+        let mut actions = Actions {
+            outbound: &self.outbound,
+        };
+        for registered in &mut self.plugins {
+            if registered.subscriptions.contains(&kind) {
+                registered.plugin.on_event(event, &mut actions);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the events it sees into a shared log and greets on spawn
+    struct Recorder {
+        name: &'static str,
+        subscriptions: Vec<EventKind>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Plugin for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn subscriptions(&self) -> Vec<EventKind> {
+            self.subscriptions.clone()
+        }
+
+        fn on_event(&mut self, event: &Event, actions: &mut Actions) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {:?}", self.name, event.kind()));
+            if let Event::Spawned = event {
+                actions.chat("hello");
+            }
+        }
+    }
+
+    #[test]
+    fn plugins_get_their_subscriptions_in_order() {
+        let (outbound, sent) = crossbeam_channel::unbounded();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = EventBus::new(outbound);
+        assert!(!bus.wants(EventKind::Tick));
+        bus.register(Recorder {
+            name: "first",
+            subscriptions: vec![EventKind::Spawned],
+            log: log.clone(),
+        });
+        bus.register(Recorder {
+            name: "second",
+            subscriptions: EventKind::ALL.to_vec(),
+            log: log.clone(),
+        });
+        assert!(bus.wants(EventKind::Tick));
+
+        bus.emit(&Event::Tick);
+        bus.emit(&Event::Spawned);
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["second Tick", "first Spawned", "second Spawned"]
+        );
+        let greetings: Vec<Packet> = sent.try_iter().collect();
+        assert_eq!(greetings.len(), 2);
+        assert!(matches!(&greetings[0], Packet::ClientChat { message } if message == "hello"));
+    }
+
+    #[test]
+    fn events_from_other_threads_are_queued() {
+        let (outbound, _sent) = crossbeam_channel::unbounded();
+        let bus = EventBus::new(outbound);
+        let sender = bus.sender();
+        std::thread::spawn(move || {
+            sender.send(Event::Died).unwrap();
+            sender.send(Event::Disconnected("gone".into())).unwrap();
+        })
+        .join()
+        .unwrap();
+        let kinds: Vec<EventKind> = bus.take_queued().iter().map(Event::kind).collect();
+        assert_eq!(kinds, [EventKind::Died, EventKind::Disconnected]);
+        assert!(bus.take_queued().is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::protocol::PacketError;
+use std::io::{Error, ErrorKind, Read, Result};
+
+/// The protocol never sends frames longer than 2^21 - 1 bytes
+pub const MAX_FRAME_LENGTH: i32 = 2_097_151;
+
+/// Collects bytes from a (non-blocking) stream and hands out complete frames only,
+/// partial frames stay buffered until the rest arrives
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> FrameDecoder {
+        FrameDecoder::new()
+    }
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder { buf: Vec::new() }
+    }
+
+    /// Reads everything that is available right now, returns the number of bytes read.
+    /// A closed connection is reported as `UnexpectedEof`
+    pub fn fill<R>(&mut self, reader: &mut R) -> Result<usize>
+    where
+        R: Read,
+    {
+        let mut chunk = [0u8; 4096];
+        let mut total = 0;
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "connection closed by the server",
+                    ))
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    total += n;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(total),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Takes the next complete frame (without its length prefix) out of the buffer.
+    /// An error means the stream is out of sync and can't be read any further
+    pub fn next_frame(&mut self) -> std::result::Result<Option<Vec<u8>>, PacketError> {
+        let (frame_len, header_len) = match read_frame_length(&self.buf)? {
+            Some(length) => length,
+            None => return Ok(None),
+        };
+        if self.buf.len() < header_len + frame_len {
+            return Ok(None);
+        }
+
+        let frame = self.buf[header_len..header_len + frame_len].to_vec();
+        self.buf.drain(..header_len + frame_len);
+        Ok(Some(frame))
+    }
+}
+
+/// Parses the VarInt length prefix, `None` if not all of its bytes arrived yet
+pub fn read_frame_length(buf: &[u8]) -> std::result::Result<Option<(usize, usize)>, PacketError> {
+    let mut value = 0i32;
+    for (i, byte) in buf.iter().take(5).enumerate() {
+        value |= ((byte & 0x7F) as i32) << (7 * i);
+        if byte & 0x80 == 0 {
+            if !(0..=MAX_FRAME_LENGTH).contains(&value) {
+                return Err(PacketError::MalformedPacket("invalid frame length"));
+            }
+            return Ok(Some((value as usize, i + 1)));
+        }
+    }
+    if buf.len() >= 5 {
+        Err(PacketError::MalformedPacket("frame length VarInt too long"))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{ConnectionState, MinecraftConnection};
+    use crate::protocol::Packet;
+    use std::io::Cursor;
+    use std::net::SocketAddrV4;
+
+    fn connection(state: ConnectionState) -> MinecraftConnection {
+        let mut connection = MinecraftConnection::new(
+            SocketAddrV4::new([127, 0, 0, 1].into(), 25565),
+            "bot".into(),
+        );
+        connection.state = state;
+        connection
+    }
+
+    /// Hands out at most `chunk` bytes per read, then blocks
+    struct Trickle {
+        data: Cursor<Vec<u8>>,
+        chunk: usize,
+        reads: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.reads > 0 {
+                self.reads = 0;
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            self.reads += 1;
+            let len = buf.len().min(self.chunk);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn partial_frames_wait_for_the_rest() {
+        let login = connection(ConnectionState::Login);
+        let bytes = Packet::ServerLoginSuccess {
+            uuid: "0-0-0-0-0".into(),
+            name: "bot".into(),
+        }
+        .serialize(&login)
+        .unwrap();
+        let mut stream = Trickle {
+            data: Cursor::new(bytes.clone()),
+            chunk: 3,
+            reads: 0,
+        };
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        while frames.is_empty() {
+            decoder.fill(&mut stream).unwrap();
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0], &bytes[1..]);
+    }
+
+    #[test]
+    fn play_packets_in_the_login_success_read_decode_as_play() {
+        let mut bytes = Packet::ServerLoginSuccess {
+            uuid: "0-0-0-0-0".into(),
+            name: "bot".into(),
+        }
+        .serialize(&connection(ConnectionState::Login))
+        .unwrap();
+        bytes.extend(
+            Packet::ServerKeepAlive { magic: 42 }
+                .serialize(&connection(ConnectionState::Play))
+                .unwrap(),
+        );
+
+        let mut connection = connection(ConnectionState::Login);
+        let mut decoder = FrameDecoder::new();
+        decoder
+            .fill(&mut Trickle {
+                data: Cursor::new(bytes),
+                chunk: usize::MAX,
+                reads: 0,
+            })
+            .unwrap();
+        let mut packets = Vec::new();
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            let packet = Packet::from_frame(frame, &connection).unwrap();
+            connection.apply_state_change(&packet);
+            packets.push(packet);
+        }
+        assert!(matches!(packets[0], Packet::ServerLoginSuccess { .. }));
+        assert!(matches!(packets[1], Packet::ServerKeepAlive { magic: 42 }));
+    }
+
+    #[test]
+    fn oversized_frame_length_is_rejected() {
+        assert!(read_frame_length(&[0xFF, 0xFF, 0xFF, 0x7F]).is_err());
+        assert_eq!(read_frame_length(&[0x80]).unwrap(), None);
+    }
+}
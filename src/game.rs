@@ -1,6 +1,16 @@
-use std::io::Result;
+use crate::physics::EYE_HEIGHT;
+use crate::protocol::Packet;
+use crate::session::{self, Account, SessionError, SessionService};
+use crate::status::ServerStatus;
+use crate::version::ProtocolVersion;
+use std::io::{Error, ErrorKind, Result, Write};
 use std::net::{SocketAddrV4, TcpStream};
-#[derive(Debug, Copy, Clone)]
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The default of `MinecraftConnection::ping_timeout`
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ConnectionState {
     None,
     Status,
@@ -20,6 +30,13 @@ pub struct MinecraftConnection {
     pub player_name: String,
     pub state: ConnectionState,
     pub compression: CompressionStatus,
+    pub account: Option<Account>,
+    pub protocol_version: ProtocolVersion,
+    /// The exact number the server reported, sent in the handshake. Several numbers share
+    /// one `protocol_version`
+    pub protocol_number: i32,
+    /// How long `ping` waits for the connection and for every read and write
+    pub ping_timeout: Duration,
 }
 
 impl MinecraftConnection {
@@ -30,20 +47,133 @@ impl MinecraftConnection {
             player_name,
             state: ConnectionState::Login,
             compression: CompressionStatus::None,
+            account: None,
+            protocol_version: ProtocolVersion::default(),
+            protocol_number: ProtocolVersion::default().protocol_number(),
+            ping_timeout: PING_TIMEOUT,
         }
     }
 
     pub fn connect(&mut self) -> Result<()> {
-        self.socket = Some(TcpStream::connect(&self.server_address)?);
+        self.socket = Some(TcpStream::connect(self.server_address)?);
         Ok(())
     }
 
-    pub fn ping(&mut self) -> Result<String> {
-        unimplemented!();
-        Ok("no".to_owned())
+    /// Does a server list ping on a separate socket and returns the server status
+    /// together with the measured round-trip latency
+    pub fn ping(&mut self) -> Result<(ServerStatus, Duration)> {
+        let mut stream =
+            TcpStream::connect_timeout(&self.server_address.into(), self.ping_timeout)?;
+        stream.set_read_timeout(Some(self.ping_timeout))?;
+        stream.set_write_timeout(Some(self.ping_timeout))?;
+        let status_connection = MinecraftConnection {
+            server_address: self.server_address,
+            socket: None,
+            player_name: self.player_name.clone(),
+            state: ConnectionState::Status,
+            compression: CompressionStatus::None,
+            account: None,
+            protocol_version: ProtocolVersion::default(),
+            protocol_number: self.protocol_number,
+            ping_timeout: self.ping_timeout,
+        };
+
+        let handshake = self.handshake(ConnectionState::Status);
+        stream.write_all(&handshake.serialize(&status_connection)?)?;
+        stream.write_all(&Packet::ClientStatusRequest {}.serialize(&status_connection)?)?;
+
+        let status = match Packet::deserialize(&mut stream, &status_connection)? {
+            Packet::ServerStatusResponse { response } => ServerStatus::from_json(&response)?,
+            p => return Err(unexpected_packet(p)),
+        };
+
+        let payload = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis() as i64)
+            .unwrap_or(0);
+        let sent = Instant::now();
+        stream.write_all(&Packet::ClientStatusPing { payload }.serialize(&status_connection)?)?;
+
+        match Packet::deserialize(&mut stream, &status_connection)? {
+            Packet::ServerStatusPong { payload: pong } if pong == payload => {
+                Ok((status, sent.elapsed()))
+            }
+            p => Err(unexpected_packet(p)),
+        }
+    }
+
+    /// Pings the server and switches to the protocol version it runs, refuses versions the
+    /// bot can only ping and log in with
+    pub fn detect_version(&mut self) -> Result<ProtocolVersion> {
+        let (status, _) = self.ping()?;
+        let version =
+            ProtocolVersion::from_protocol_number(status.version.protocol).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "unsupported protocol version {} ({})",
+                        status.version.protocol, status.version.name
+                    ),
+                )
+            })?;
+        if !version.is_playable() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the bot can't play on {} yet", version.name()),
+            ));
+        }
+        self.protocol_version = version;
+        self.protocol_number = status.version.protocol;
+        Ok(version)
+    }
+
+    /// Switches to the state or compression a received packet announces. Has to happen before
+    /// the next frame is decoded, one read often holds Login Success and the first Play packets
+    pub fn apply_state_change(&mut self, packet: &Packet) {
+        match packet {
+            Packet::ServerLoginSuccess { .. } => self.state = ConnectionState::Play,
+            Packet::ServerCompressionLevelSet { compression_level } => {
+                self.compression = CompressionStatus::Enabled(*compression_level)
+            }
+            _ => {}
+        }
+    }
+
+    /// The first packet of every connection, announcing our protocol and what comes next
+    pub fn handshake(&self, next_state: ConnectionState) -> Packet {
+        Packet::ClientHandshake {
+            protocol_version: self.protocol_number,
+            host_address: self.server_address.ip().to_string(),
+            port: self.server_address.port(),
+            next_state,
+        }
     }
 
     pub fn login(&mut self) {}
+
+    /// Announces the upcoming join of an online-mode server to the session server
+    pub fn join_session(
+        &self,
+        session: &dyn SessionService,
+        server_id: &str,
+        shared_secret: &[u8],
+        public_key: &[u8],
+    ) -> std::result::Result<(), SessionError> {
+        session::join_session(
+            session,
+            self.account.as_ref(),
+            server_id,
+            shared_secret,
+            public_key,
+        )
+    }
+}
+
+fn unexpected_packet(packet: Packet) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("unexpected packet: {:?}", packet),
+    )
 }
 #[derive(Copy, Clone)]
 pub struct Entity {
@@ -54,3 +184,139 @@ pub struct Entity {
     pub yaw: f32,
     pub pitch: f32,
 }
+
+impl Entity {
+    /// Turns the head so the eyes look at a point
+    pub fn look_at(&mut self, x: f64, y: f64, z: f64) {
+        let (dx, dy, dz) = (x - self.x, y - (self.y + EYE_HEIGHT), z - self.z);
+        let horizontal = (dx * dx + dz * dz).sqrt();
+        self.yaw = (-dx).atan2(dz).to_degrees() as f32;
+        self.pitch = (-dy).atan2(horizontal).to_degrees() as f32;
+    }
+
+    /// Where the eyes of a standing player are
+    pub fn eye_position(&self) -> (f64, f64, f64) {
+        (self.x, self.y + EYE_HEIGHT, self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, ReadBytesExt};
+    use mc_varint::VarIntRead;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+        let len = stream.read_var_i32().unwrap();
+        let mut frame = vec![0u8; len as usize];
+        stream.read_exact(&mut frame).unwrap();
+        frame
+    }
+
+    fn listen() -> (TcpListener, MinecraftConnection) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = match listener.local_addr().unwrap() {
+            std::net::SocketAddr::V4(address) => address,
+            _ => unreachable!(),
+        };
+        (listener, MinecraftConnection::new(address, "bot".into()))
+    }
+
+    /// Answers one server list ping as a server reporting `protocol`, hands back the
+    /// handshake it got
+    fn serve_status(
+        listener: TcpListener,
+        status: MinecraftConnection,
+        protocol: i32,
+    ) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let handshake = read_frame(&mut stream);
+            read_frame(&mut stream); // status request
+            let response = Packet::ServerStatusResponse {
+                response: format!(
+                    r#"{{"version":{{"name":"1.16.3","protocol":{}}},"players":{{"max":20,"online":1}},"description":{{"text":"hi"}}}}"#,
+                    protocol
+                ),
+            };
+            stream
+                .write_all(&response.serialize(&status).unwrap())
+                .unwrap();
+            let ping = read_frame(&mut stream);
+            let payload = (&ping[1..]).read_i64::<BigEndian>().unwrap();
+            let pong = Packet::ServerStatusPong { payload };
+            stream.write_all(&pong.serialize(&status).unwrap()).unwrap();
+            handshake
+        })
+    }
+
+    #[test]
+    fn ping_reads_status_and_pong() {
+        let (listener, mut connection) = listen();
+        let mut status = MinecraftConnection::new(connection.server_address, String::new());
+        status.state = ConnectionState::Status;
+        let server = serve_status(listener, status, 753);
+
+        let (status, _) = connection.ping().unwrap();
+        assert_eq!(status.version.protocol, 753);
+        assert_eq!(status.players.online, 1);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn detected_protocol_number_is_sent_in_the_handshake() {
+        let (listener, mut connection) = listen();
+        connection.protocol_number = 0;
+        let mut status = MinecraftConnection::new(connection.server_address, String::new());
+        status.state = ConnectionState::Status;
+        let server = serve_status(listener, status, 498);
+
+        assert_eq!(
+            connection.detect_version().unwrap(),
+            ProtocolVersion::V1_14_4
+        );
+        server.join().unwrap();
+        let login = connection
+            .handshake(ConnectionState::Login)
+            .serialize(&connection)
+            .unwrap();
+        let mut login = &login[..];
+        login.read_var_i32().unwrap(); // length
+        assert_eq!(login.read_var_i32().unwrap(), 0x00);
+        assert_eq!(login.read_var_i32().unwrap(), 498);
+    }
+
+    #[test]
+    fn versions_the_bot_cant_play_are_refused() {
+        let (listener, mut connection) = listen();
+        let mut status = MinecraftConnection::new(connection.server_address, String::new());
+        status.state = ConnectionState::Status;
+        let server = serve_status(listener, status, 753);
+
+        let err = connection.detect_version().unwrap_err();
+        assert_eq!(err.to_string(), "the bot can't play on 1.16.5 yet");
+        assert_eq!(connection.protocol_version, ProtocolVersion::V1_14_4);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn ping_times_out_on_a_silent_server() {
+        let (listener, mut connection) = listen();
+        connection.ping_timeout = Duration::from_millis(200);
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_millis(500));
+            drop(stream);
+        });
+
+        let err = connection.ping().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WouldBlock | ErrorKind::TimedOut
+        ));
+        server.join().unwrap();
+    }
+}
@@ -1,33 +1,30 @@
 use sha1::{Digest, Sha1};
 
-use std::iter;
-
 use regex::Regex;
 use rustc_serialize::hex::ToHex;
-use sha1::digest::DynDigest;
 
 const LEADING_ZERO_REGEX: &str = r#"^0+"#;
 
-fn calc_hash(name: &str) -> String {
+/// The hash the client and the session server agree on for joining an online-mode server
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key: &[u8]) -> String {
+    calc_hash(&[server_id.as_bytes(), shared_secret, public_key])
+}
+
+/// Minecraft's SHA-1 digest: the hash is read as a signed big integer and printed as hex
+fn calc_hash(parts: &[&[u8]]) -> String {
     let mut hasher = Sha1::new();
-    sha1::Digest::input(&mut hasher, name.as_bytes());
-    //  let mut hex: Vec<u8> = iter::repeat(0)
-    //      .take((hasher.output_size() + 7) / 8)
-    //      .collect();
-    let mut hex = hasher.result();
+    for part in parts {
+        sha1::Digest::input(&mut hasher, part);
+    }
+    let mut hex = hasher.result().to_vec();
 
     let negative = (hex[0] & 0x80) == 0x80;
 
     let regex = Regex::new(LEADING_ZERO_REGEX).unwrap();
 
     if negative {
-        two_complement(&mut hex.to_vec());
-        format!(
-            "-{}",
-            regex
-                .replace(hex.as_slice().to_hex().as_str(), "")
-                .to_string()
-        )
+        two_complement(&mut hex);
+        format!("-{}", regex.replace(hex.as_slice().to_hex().as_str(), ""))
     } else {
         regex
             .replace(hex.as_slice().to_hex().as_str(), "")
@@ -35,13 +32,40 @@ fn calc_hash(name: &str) -> String {
     }
 }
 
-fn two_complement(bytes: &mut Vec<u8>) {
+fn two_complement(bytes: &mut [u8]) {
     let mut carry = true;
-    for i in (0..bytes.len()).rev() {
-        bytes[i] = !bytes[i] & 0xff;
+    for byte in bytes.iter_mut().rev() {
+        *byte = !*byte;
         if carry {
-            carry = bytes[i] == 0xff;
-            bytes[i] = bytes[i] + 1;
+            *byte = byte.wrapping_add(1);
+            carry = *byte == 0;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_digests() {
+        assert_eq!(
+            calc_hash(&[b"Notch"]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            calc_hash(&[b"jeb_"]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            calc_hash(&[b"simon"]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+    }
+
+    #[test]
+    fn server_hash_covers_all_parts() {
+        assert_eq!(server_hash("", b"Notch", b""), calc_hash(&[b"Notch"]));
+        assert_eq!(server_hash("No", b"tc", b"h"), calc_hash(&[b"Notch"]));
+    }
+}
@@ -0,0 +1,436 @@
+use crate::block::{BlockClass, BlockState};
+use crate::game::Entity;
+use crate::physics::{self, Aabb, PLAYER_HEIGHT, PLAYER_WIDTH};
+use crate::protocol::{Face, Packet, PacketType, Position};
+use crate::version::ProtocolVersion;
+use crate::world::World;
+use err_derive::Error;
+
+/// How far a survival player reaches, measured from the eyes
+pub const REACH: f64 = 4.5;
+
+/// Hand values of Player Block Placement, Use Item and Animation
+pub const MAIN_HAND: i32 = 0;
+pub const OFF_HAND: i32 = 1;
+
+/// Blocks a placed block simply replaces
+const REPLACEABLE: &[&str] = &[
+    "grass",
+    "tall_grass",
+    "fern",
+    "large_fern",
+    "dead_bush",
+    "seagrass",
+    "tall_seagrass",
+    "vine",
+    "fire",
+    "structure_void",
+];
+
+#[derive(Debug, Error)]
+pub enum InteractError {
+    #[error(display = "block at {:?} is not loaded", 0)]
+    NotLoaded(Position),
+    #[error(display = "there is nothing to place against at {:?}", 0)]
+    NothingToPlaceAgainst(Position),
+    #[error(display = "{:?} would be used instead of placed against", 0)]
+    WouldActivate(Position),
+    #[error(display = "{:?} is taken by {}", 0, 1)]
+    Occupied(Position, String),
+    #[error(display = "a block at {:?} would be inside the bot", 0)]
+    InsideBot(Position),
+    #[error(display = "{:?} is out of reach", 0)]
+    OutOfReach(Position),
+    #[error(display = "{:?} can't be seen from here", 0)]
+    NotVisible(Position),
+    #[error(display = "{} can't be activated", 0)]
+    NotActivatable(String),
+    #[error(display = "clicking blocks isn't supported on {}", 0)]
+    Unsupported(&'static str),
+}
+
+/// Places the held block on `face` of the block at `against`, looking at the middle of that
+/// face first
+pub fn place_block(
+    world: &World,
+    entity: &mut Entity,
+    against: Position,
+    face: Face,
+    version: ProtocolVersion,
+    onground: bool,
+) -> Result<Vec<Packet>, InteractError> {
+    supported(version)?;
+    let state = loaded(world, against)?;
+    if state.is_air() || state.is_liquid() {
+        return Err(InteractError::NothingToPlaceAgainst(against));
+    }
+    if activatable(state) {
+        return Err(InteractError::WouldActivate(against));
+    }
+
+    let (nx, ny, nz) = face.normal();
+    let target = against.offset(nx, ny, nz);
+    let occupant = loaded(world, target)?;
+    if !replaceable(occupant) {
+        return Err(InteractError::Occupied(target, occupant.name.clone()));
+    }
+    let bot = Aabb::entity(entity.x, entity.y, entity.z, PLAYER_WIDTH, PLAYER_HEIGHT);
+    if bot.intersects(&cell(target)) {
+        return Err(InteractError::InsideBot(target));
+    }
+
+    let (x, y, z) = aim(world, entity, against, face)?;
+    entity.look_at(x, y, z);
+    Ok(click(entity, against, face, onground))
+}
+
+/// Places the held block at `position` against whichever neighbour can be clicked, preferring
+/// the one below
+pub fn place_at(
+    world: &World,
+    entity: &mut Entity,
+    position: Position,
+    version: ProtocolVersion,
+    onground: bool,
+) -> Result<Vec<Packet>, InteractError> {
+    let mut error = InteractError::NothingToPlaceAgainst(position);
+    let faces = [
+        Face::Top,
+        Face::North,
+        Face::South,
+        Face::West,
+        Face::East,
+        Face::Bottom,
+    ];
+    for face in faces.iter() {
+        let (nx, ny, nz) = face.normal();
+        let against = position.offset(-nx, -ny, -nz);
+        match place_block(world, entity, against, *face, version, onground) {
+            Ok(packets) => return Ok(packets),
+            // the more specific reason is more useful than an empty neighbour
+            Err(InteractError::NothingToPlaceAgainst(_)) | Err(InteractError::NotLoaded(_)) => {}
+            Err(err) => error = err,
+        }
+    }
+    Err(error)
+}
+
+/// Right clicks a button, lever, door, trapdoor, fence gate, chest or bed, on the side facing
+/// the bot if it can be seen and on any other visible side otherwise
+pub fn activate_block(
+    world: &World,
+    entity: &mut Entity,
+    position: Position,
+    version: ProtocolVersion,
+    onground: bool,
+) -> Result<Vec<Packet>, InteractError> {
+    supported(version)?;
+    let state = loaded(world, position)?;
+    if !activatable(state) {
+        return Err(InteractError::NotActivatable(state.name.clone()));
+    }
+    let (x, y, z) = entity.eye_position();
+    let facing = Face::toward(position, x, y, z);
+    let mut error = None;
+    for face in std::iter::once(facing).chain(Face::ALL.iter().copied()) {
+        match aim(world, entity, position, face) {
+            Ok((x, y, z)) => {
+                entity.look_at(x, y, z);
+                return Ok(click(entity, position, face, onground));
+            }
+            // why the side facing the bot failed explains the most
+            Err(err) => {
+                error.get_or_insert(err);
+            }
+        }
+    }
+    Err(error.unwrap_or(InteractError::NotVisible(position)))
+}
+
+/// Right clicks the air, eating, drinking, throwing or drawing a bow with the item in `hand`
+pub fn use_item(hand: i32) -> Packet {
+    Packet::ClientUseItem { hand }
+}
+
+pub fn activatable(state: &BlockState) -> bool {
+    let name = state.name.trim_start_matches("minecraft:");
+    match name {
+        "lever" | "chest" | "trapped_chest" | "ender_chest" => true,
+        // iron doors only open with redstone
+        "iron_door" | "iron_trapdoor" => false,
+        _ => {
+            name.ends_with("_button")
+                || name.ends_with("_door")
+                || name.ends_with("_trapdoor")
+                || name.ends_with("_fence_gate")
+                || name.ends_with("_bed")
+        }
+    }
+}
+
+fn replaceable(state: &BlockState) -> bool {
+    let name = state.name.trim_start_matches("minecraft:");
+    state.is_air()
+        || state.class == BlockClass::Liquid
+        || REPLACEABLE.contains(&name)
+        || (name == "snow" && state.property("layers") == Some("1"))
+}
+
+/// 1.12 orders the fields of Player Block Placement differently, it isn't mapped
+fn supported(version: ProtocolVersion) -> Result<(), InteractError> {
+    match PacketType::ClientPlayerBlockPlacement.id(version) {
+        Some(_) => Ok(()),
+        None => Err(InteractError::Unsupported(version.name())),
+    }
+}
+
+fn loaded(world: &World, position: Position) -> Result<&BlockState, InteractError> {
+    world
+        .block_state_at(position.x, position.y, position.z)
+        .ok_or(InteractError::NotLoaded(position))
+}
+
+fn cell(position: Position) -> Aabb {
+    let (x, y, z) = (position.x as f64, position.y as f64, position.z as f64);
+    Aabb::new(x, y, z, x + 1.0, y + 1.0, z + 1.0)
+}
+
+/// Where the middle of a face is, relative to the block's corner
+fn face_center(face: Face) -> (f32, f32, f32) {
+    let (nx, ny, nz) = face.normal();
+    (
+        0.5 + nx as f32 * 0.5,
+        0.5 + ny as f32 * 0.5,
+        0.5 + nz as f32 * 0.5,
+    )
+}
+
+/// The world point of the middle of `face` of the block at `position`, if it is within reach
+/// and no other block is in the way
+fn aim(
+    world: &World,
+    entity: &Entity,
+    position: Position,
+    face: Face,
+) -> Result<(f64, f64, f64), InteractError> {
+    let cursor = face_center(face);
+    let to = (
+        position.x as f64 + cursor.0 as f64,
+        position.y as f64 + cursor.1 as f64,
+        position.z as f64 + cursor.2 as f64,
+    );
+    let from = entity.eye_position();
+    let (dx, dy, dz) = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+    if (dx * dx + dy * dy + dz * dz).sqrt() > REACH {
+        return Err(InteractError::OutOfReach(position));
+    }
+    // the back of a face is hidden by the block itself
+    let (nx, ny, nz) = face.normal();
+    if dx * nx as f64 + dy * ny as f64 + dz * nz as f64 >= 0.0 {
+        return Err(InteractError::NotVisible(position));
+    }
+
+    let clicked = cell(position);
+    // a ray has no volume, so widen its box a bit to catch the blocks it grazes
+    let ray = Aabb::new(
+        from.0.min(to.0) - 0.01,
+        from.1.min(to.1) - 0.01,
+        from.2.min(to.2) - 0.01,
+        from.0.max(to.0) + 0.01,
+        from.1.max(to.1) + 0.01,
+        from.2.max(to.2) + 0.01,
+    );
+    let blocked = physics::collision_boxes(world, &ray)
+        .iter()
+        .filter(|part| !inside(part, &clicked))
+        .any(|part| part.intersects_segment(from, to));
+    if blocked {
+        return Err(InteractError::NotVisible(position));
+    }
+    Ok(to)
+}
+
+fn inside(part: &Aabb, cell: &Aabb) -> bool {
+    part.min_x >= cell.min_x
+        && part.min_y >= cell.min_y
+        && part.min_z >= cell.min_z
+        && part.max_x <= cell.max_x
+        && part.max_y <= cell.max_y
+        && part.max_z <= cell.max_z
+}
+
+/// The look goes first, the server checks the placement against where we face
+fn click(entity: &Entity, location: Position, face: Face, onground: bool) -> Vec<Packet> {
+    let cursor = face_center(face);
+    vec![
+        Packet::ClientPlayerLook {
+            yaw: entity.yaw,
+            pitch: entity.pitch,
+            onground,
+        },
+        Packet::ClientPlayerBlockPlacement {
+            hand: MAIN_HAND,
+            location,
+            face: face as i32,
+            cursor_x: cursor.0,
+            cursor_y: cursor.1,
+            cursor_z: cursor.2,
+            inside_block: false,
+        },
+        Packet::ClientAnimation { hand: MAIN_HAND },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRegistry;
+    use crate::world::{ChunkBlockID, ChunkColumn};
+
+    const V1_14_4: ProtocolVersion = ProtocolVersion::V1_14_4;
+
+    const REPORT: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+        "minecraft:grass": {"states": [{"id": 2, "default": true}]},
+        "minecraft:lever": {"states": [{"id": 3, "default": true}]},
+        "minecraft:iron_door": {"states": [{"id": 4, "default": true}]}
+    }"#;
+    const STONE: ChunkBlockID = ChunkBlockID(1);
+    const GRASS: ChunkBlockID = ChunkBlockID(2);
+    const LEVER: ChunkBlockID = ChunkBlockID(3);
+    const IRON_DOOR: ChunkBlockID = ChunkBlockID(4);
+
+    /// A stone floor whose top is at y 64 around the origin
+    fn world() -> World {
+        let mut world = World::new(BlockRegistry::from_json(REPORT).unwrap());
+        world.columns.insert((0, 0), ChunkColumn::new(0, 0));
+        for x in 0..16 {
+            for z in 0..16 {
+                world.set_block(Position { x, y: 63, z }, STONE);
+            }
+        }
+        world
+    }
+
+    fn bot() -> Entity {
+        Entity {
+            entityid: 1,
+            x: 0.5,
+            y: 64.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+
+    fn at(x: i32, y: i32, z: i32) -> Position {
+        Position { x, y, z }
+    }
+
+    #[test]
+    fn places_on_the_middle_of_the_face() {
+        let world = world();
+        let mut entity = bot();
+        let packets = place_at(&world, &mut entity, at(2, 64, 0), V1_14_4, true).unwrap();
+        match &packets[..] {
+            [Packet::ClientPlayerLook {
+                yaw,
+                pitch,
+                onground: true,
+            }, Packet::ClientPlayerBlockPlacement {
+                hand: MAIN_HAND,
+                location,
+                face,
+                cursor_x,
+                cursor_y,
+                cursor_z,
+                ..
+            }, Packet::ClientAnimation { hand: MAIN_HAND }] => {
+                assert_eq!(*location, at(2, 63, 0));
+                assert_eq!(*face, Face::Top as i32);
+                assert_eq!((*cursor_x, *cursor_y, *cursor_z), (0.5, 1.0, 0.5));
+                assert_eq!((*yaw, *pitch), (entity.yaw, entity.pitch));
+            }
+            packets => panic!("unexpected {:?}", packets),
+        }
+        // looking down and to the east
+        assert!(entity.pitch > 0.0);
+        assert_eq!(entity.yaw, -90.0);
+    }
+
+    #[test]
+    fn placing_checks_the_target() {
+        let mut world = world();
+        let mut entity = bot();
+        assert!(matches!(
+            place_block(&world, &mut entity, at(0, 63, 0), Face::Top, V1_14_4, true),
+            Err(InteractError::InsideBot(_))
+        ));
+        assert!(matches!(
+            place_block(&world, &mut entity, at(6, 63, 0), Face::Top, V1_14_4, true),
+            Err(InteractError::OutOfReach(_))
+        ));
+        assert!(matches!(
+            place_at(&world, &mut entity, at(2, 70, 0), V1_14_4, true),
+            Err(InteractError::NothingToPlaceAgainst(_))
+        ));
+        assert!(matches!(
+            place_block(&world, &mut entity, at(2, 63, 16), Face::Top, V1_14_4, true),
+            Err(InteractError::NotLoaded(_))
+        ));
+        assert!(matches!(
+            place_at(
+                &world,
+                &mut entity,
+                at(2, 64, 0),
+                ProtocolVersion::V1_12_2,
+                true
+            ),
+            Err(InteractError::Unsupported("1.12.2"))
+        ));
+
+        world.set_block(at(2, 64, 0), GRASS);
+        assert!(place_block(&world, &mut entity, at(2, 63, 0), Face::Top, V1_14_4, true).is_ok());
+        world.set_block(at(2, 64, 0), STONE);
+        assert!(matches!(
+            place_block(&world, &mut entity, at(2, 63, 0), Face::Top, V1_14_4, true),
+            Err(InteractError::Occupied(_, _))
+        ));
+        world.set_block(at(2, 64, 0), LEVER);
+        assert!(matches!(
+            place_block(&world, &mut entity, at(2, 64, 0), Face::Top, V1_14_4, true),
+            Err(InteractError::WouldActivate(_))
+        ));
+    }
+
+    #[test]
+    fn activates_visible_blocks() {
+        let mut world = world();
+        let mut entity = bot();
+        world.set_block(at(2, 64, 0), LEVER);
+        let packets = activate_block(&world, &mut entity, at(2, 64, 0), V1_14_4, true).unwrap();
+        assert!(matches!(
+            packets[1],
+            Packet::ClientPlayerBlockPlacement { face, .. } if face == Face::West as i32
+        ));
+
+        world.set_block(at(2, 64, 1), IRON_DOOR);
+        assert!(matches!(
+            activate_block(&world, &mut entity, at(2, 64, 1), V1_14_4, true),
+            Err(InteractError::NotActivatable(_))
+        ));
+
+        // a wall between the bot and a lever
+        world.set_block(at(3, 65, 0), LEVER);
+        for y in 64..68 {
+            for z in 0..3 {
+                world.set_block(at(2, y, z), STONE);
+            }
+        }
+        assert!(matches!(
+            activate_block(&world, &mut entity, at(3, 65, 0), V1_14_4, true),
+            Err(InteractError::NotVisible(_))
+        ));
+    }
+}
@@ -0,0 +1,575 @@
+use crate::chat::TextComponent;
+use crate::item::{ItemStack, Slot};
+use crate::protocol::Packet;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Window id of the player's own inventory
+pub const PLAYER_WINDOW: u8 = 0;
+/// Clicking this slot clicks outside the window, which drops the cursor
+pub const OUTSIDE: i16 = -999;
+
+/// Crafting output and grid, armor, 27 main slots, hotbar and offhand
+const PLAYER_SLOTS: usize = 46;
+const PLAYER_MAIN: Range<usize> = 9..36;
+const PLAYER_HOTBAR: Range<usize> = 36..45;
+/// Real stack sizes depend on the item, the server corrects us if this is wrong
+const MAX_STACK: i8 = 64;
+
+/// The player inventory or an open container
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub id: u8,
+    /// Id in the `minecraft:menu` registry, -1 for the player inventory
+    pub kind: i32,
+    pub title: TextComponent,
+    /// Container windows end with the 27 main and 9 hotbar slots of the player
+    pub slots: Vec<Slot>,
+    /// Furnace progress, enchantment costs...
+    pub properties: HashMap<i16, i16>,
+}
+
+impl Window {
+    fn player() -> Window {
+        Window {
+            id: PLAYER_WINDOW,
+            kind: -1,
+            title: TextComponent::from("Inventory"),
+            slots: vec![None; PLAYER_SLOTS],
+            properties: HashMap::new(),
+        }
+    }
+
+    /// The window index of a hotbar slot (0-8)
+    pub fn hotbar_slot(&self, hotbar: u8) -> usize {
+        if self.id == PLAYER_WINDOW {
+            PLAYER_HOTBAR.start + hotbar as usize
+        } else {
+            self.slots.len().saturating_sub(9) + hotbar as usize
+        }
+    }
+
+    /// Where shift-clicking `index` moves the stack to, and whether it fills from the back
+    fn shift_target(&self, index: usize) -> (Range<usize>, bool) {
+        if self.id == PLAYER_WINDOW {
+            if PLAYER_HOTBAR.contains(&index) {
+                (PLAYER_MAIN, false)
+            } else if PLAYER_MAIN.contains(&index) {
+                (PLAYER_HOTBAR, false)
+            } else {
+                (PLAYER_MAIN.start..PLAYER_HOTBAR.end, false)
+            }
+        } else {
+            let player_start = self.slots.len().saturating_sub(36);
+            if index < player_start {
+                (player_start..self.slots.len(), true)
+            } else {
+                (0..player_start, false)
+            }
+        }
+    }
+}
+
+/// What a click does, mapped to the mode and button of Click Window
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Click {
+    /// Left click: pick up, place or swap the whole stack
+    Pickup,
+    /// Right click: pick up half or place one
+    PickupHalf,
+    /// Move the stack between the container and the player inventory
+    Shift,
+    /// Swap with a hotbar slot (0-8), like pressing its number key
+    Swap(u8),
+    /// Drop one item, Q
+    Drop,
+    /// Drop the whole stack, Ctrl+Q
+    DropStack,
+}
+
+impl Click {
+    fn mode_and_button(self) -> (i32, i8) {
+        match self {
+            Click::Pickup => (0, 0),
+            Click::PickupHalf => (0, 1),
+            Click::Shift => (1, 0),
+            Click::Swap(hotbar) => (2, hotbar as i8),
+            Click::Drop => (4, 0),
+            Click::DropStack => (4, 1),
+        }
+    }
+}
+
+/// The state before a click, restored if the server rejects it
+struct PendingClick {
+    slots: Vec<Slot>,
+    cursor: Slot,
+}
+
+pub struct Inventory {
+    pub player: Window,
+    /// A chest, furnace... opened by the server, if any
+    pub container: Option<Window>,
+    /// The stack held by the mouse
+    pub cursor: Slot,
+    /// Selected hotbar slot, 0-8
+    pub held_slot: u8,
+    next_action: i16,
+    pending: HashMap<(u8, i16), PendingClick>,
+}
+
+impl Default for Inventory {
+    fn default() -> Inventory {
+        Inventory::new()
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory {
+            player: Window::player(),
+            container: None,
+            cursor: None,
+            held_slot: 0,
+            next_action: 1,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self, id: u8) -> Option<&Window> {
+        if id == PLAYER_WINDOW {
+            return Some(&self.player);
+        }
+        self.container.as_ref().filter(|window| window.id == id)
+    }
+
+    fn window_mut(&mut self, id: u8) -> Option<&mut Window> {
+        if id == PLAYER_WINDOW {
+            return Some(&mut self.player);
+        }
+        self.container.as_mut().filter(|window| window.id == id)
+    }
+
+    pub fn held_item(&self) -> Option<&ItemStack> {
+        self.player.slots[self.player.hotbar_slot(self.held_slot)].as_ref()
+    }
+
+    /// The first player inventory slot (hotbar first) holding `item_id`
+    pub fn find_item(&self, item_id: i32) -> Option<usize> {
+        PLAYER_HOTBAR
+            .chain(PLAYER_MAIN)
+            .find(|slot| match &self.player.slots[*slot] {
+                Some(item) => item.item_id == item_id,
+                None => false,
+            })
+    }
+
+    pub fn select_hotbar(&mut self, slot: u8) -> Packet {
+        self.held_slot = slot.min(8);
+        Packet::ClientHeldItemChange {
+            slot: self.held_slot as i16,
+        }
+    }
+
+    /// Updates windows from inventory packets. Returns the packet to answer with, if any
+    pub fn apply(&mut self, packet: &Packet) -> Option<Packet> {
+        match packet {
+            Packet::ServerOpenWindow {
+                window_id,
+                kind,
+                title,
+            } => {
+                self.container = Some(Window {
+                    id: *window_id as u8,
+                    kind: *kind,
+                    title: TextComponent::from_json(title).unwrap_or_default(),
+                    slots: Vec::new(),
+                    properties: HashMap::new(),
+                });
+            }
+            Packet::ServerWindowItems { window_id, slots } => {
+                if let Some(window) = self.window_mut(*window_id) {
+                    window.slots = slots.clone();
+                }
+            }
+            Packet::ServerSetSlot {
+                window_id,
+                slot,
+                item,
+            } => match (*window_id, *slot) {
+                (-1, -1) => self.cursor = item.clone(),
+                (window_id, slot) if slot >= 0 => {
+                    // -2 updates the player inventory without an animation
+                    let window_id = if window_id == -2 {
+                        PLAYER_WINDOW
+                    } else {
+                        window_id as u8
+                    };
+                    if let Some(window) = self.window_mut(window_id) {
+                        let slot = slot as usize;
+                        if window.slots.len() <= slot {
+                            window.slots.resize(slot + 1, None);
+                        }
+                        window.slots[slot] = item.clone();
+                    }
+                }
+                _ => {}
+            },
+            Packet::ServerWindowProperty {
+                window_id,
+                property,
+                value,
+            } => {
+                if let Some(window) = self.window_mut(*window_id) {
+                    window.properties.insert(*property, *value);
+                }
+            }
+            Packet::ServerCloseWindow { window_id }
+                if self.container.as_ref().map(|window| window.id) == Some(*window_id) =>
+            {
+                self.container = None;
+            }
+            Packet::ServerConfirmTransaction {
+                window_id,
+                action_number,
+                accepted,
+            } => {
+                let pending = self.pending.remove(&(*window_id as u8, *action_number));
+                if !accepted {
+                    if let Some(pending) = pending {
+                        self.cursor = pending.cursor;
+                        if let Some(window) = self.window_mut(*window_id as u8) {
+                            window.slots = pending.slots;
+                        }
+                    }
+                    // the server ignores further clicks until we apologize
+                    return Some(Packet::ClientConfirmTransaction {
+                        window_id: *window_id,
+                        action_number: *action_number,
+                        accepted: false,
+                    });
+                }
+            }
+            // the hotbar has 9 slots, anything else would index past it
+            Packet::ServerHeldItemChange { slot } if (0..=8).contains(slot) => {
+                self.held_slot = *slot as u8
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Clicks a slot of an open window and predicts the result like the vanilla client.
+    /// `None` if the window isn't open
+    pub fn click(&mut self, window_id: u8, slot: i16, click: Click) -> Option<Packet> {
+        let action_number = self.next_action;
+        let mut cursor = self.cursor.take();
+        let window = match self.window_mut(window_id) {
+            Some(window) => window,
+            None => {
+                self.cursor = cursor;
+                return None;
+            }
+        };
+        let pending = PendingClick {
+            slots: window.slots.clone(),
+            cursor: cursor.clone(),
+        };
+        let clicked_item = if slot >= 0 {
+            window.slots.get(slot as usize).cloned().flatten()
+        } else {
+            None
+        };
+        predict_click(window, &mut cursor, slot, click);
+        self.cursor = cursor;
+
+        self.pending.insert((window_id, action_number), pending);
+        self.next_action = self.next_action.wrapping_add(1).max(1);
+        let (mode, button) = click.mode_and_button();
+        Some(Packet::ClientClickWindow {
+            window_id,
+            slot,
+            button,
+            action_number,
+            mode,
+            clicked_item,
+        })
+    }
+
+    /// Closes the open container, or the player inventory if none is open
+    pub fn close_window(&mut self) -> Packet {
+        let window_id = match self.container.take() {
+            Some(window) => window.id,
+            None => PLAYER_WINDOW,
+        };
+        Packet::ClientCloseWindow { window_id }
+    }
+
+    /// Forgets everything, the server resends the inventory after a respawn
+    pub fn clear(&mut self) {
+        *self = Inventory::new();
+    }
+}
+
+fn stackable(a: &ItemStack, b: &ItemStack) -> bool {
+    a.item_id == b.item_id && a.damage == b.damage && a.nbt == b.nbt
+}
+
+/// Removes `count` items from a slot, emptying it when nothing is left
+fn shrink(slot: &mut Slot, count: i8) {
+    if let Some(item) = slot {
+        item.count -= count;
+        if item.count <= 0 {
+            *slot = None;
+        }
+    }
+}
+
+fn predict_click(window: &mut Window, cursor: &mut Slot, slot: i16, click: Click) {
+    if slot == OUTSIDE {
+        match click {
+            Click::Pickup => *cursor = None,
+            Click::PickupHalf => shrink(cursor, 1),
+            _ => {}
+        }
+        return;
+    }
+    let index = slot as usize;
+    if slot < 0 || index >= window.slots.len() {
+        return;
+    }
+
+    match click {
+        Click::Pickup => match (cursor.as_mut(), window.slots[index].as_mut()) {
+            (Some(held), Some(item)) if stackable(held, item) => {
+                let moved = held.count.min(MAX_STACK - item.count).max(0);
+                item.count += moved;
+                shrink(cursor, moved);
+            }
+            _ => std::mem::swap(cursor, &mut window.slots[index]),
+        },
+        Click::PickupHalf => match (cursor.as_mut(), window.slots[index].as_mut()) {
+            (None, Some(item)) => {
+                let half = (item.count + 1) / 2;
+                *cursor = Some(ItemStack {
+                    count: half,
+                    ..item.clone()
+                });
+                shrink(&mut window.slots[index], half);
+            }
+            (Some(held), None) => {
+                window.slots[index] = Some(ItemStack {
+                    count: 1,
+                    ..held.clone()
+                });
+                shrink(cursor, 1);
+            }
+            (Some(held), Some(item)) if stackable(held, item) => {
+                if item.count < MAX_STACK {
+                    item.count += 1;
+                    shrink(cursor, 1);
+                }
+            }
+            (Some(_), Some(_)) => std::mem::swap(cursor, &mut window.slots[index]),
+            (None, None) => {}
+        },
+        Click::Shift => {
+            let (target, reverse) = window.shift_target(index);
+            move_stack(window, index, target, reverse);
+        }
+        Click::Swap(hotbar) => {
+            let hotbar = window.hotbar_slot(hotbar);
+            if hotbar < window.slots.len() {
+                window.slots.swap(index, hotbar);
+            }
+        }
+        Click::Drop if cursor.is_none() => shrink(&mut window.slots[index], 1),
+        Click::DropStack if cursor.is_none() => window.slots[index] = None,
+        Click::Drop | Click::DropStack => {}
+    }
+}
+
+/// Merges the stack at `index` into matching stacks of `target`, the rest goes to empty slots
+fn move_stack(window: &mut Window, index: usize, target: Range<usize>, reverse: bool) {
+    let mut moving = match window.slots[index].take() {
+        Some(item) => item,
+        None => return,
+    };
+    let end = target.end.min(window.slots.len());
+    let mut targets: Vec<usize> = (target.start..end).filter(|i| *i != index).collect();
+    if reverse {
+        targets.reverse();
+    }
+
+    for i in &targets {
+        if let Some(item) = &mut window.slots[*i] {
+            if stackable(item, &moving) && item.count < MAX_STACK {
+                let moved = moving.count.min(MAX_STACK - item.count);
+                item.count += moved;
+                moving.count -= moved;
+                if moving.count == 0 {
+                    return;
+                }
+            }
+        }
+    }
+    for i in &targets {
+        if window.slots[*i].is_none() {
+            window.slots[*i] = Some(moving);
+            return;
+        }
+    }
+    window.slots[index] = Some(moving);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STONE: i32 = 1;
+    const DIRT: i32 = 10;
+
+    fn stack(item_id: i32, count: i8) -> Slot {
+        Some(ItemStack::new(item_id, count))
+    }
+
+    fn count(slot: &Slot) -> i8 {
+        slot.as_ref().map_or(0, |item| item.count)
+    }
+
+    /// A chest with 27 slots, opened as window 1
+    fn open_chest(inventory: &mut Inventory) {
+        inventory.apply(&Packet::ServerOpenWindow {
+            window_id: 1,
+            kind: 2,
+            title: r#"{"text":"Chest"}"#.to_string(),
+        });
+        inventory.apply(&Packet::ServerWindowItems {
+            window_id: 1,
+            slots: vec![None; 27 + 36],
+        });
+    }
+
+    #[test]
+    fn pickup_merges_into_matching_stacks() {
+        let mut inventory = Inventory::new();
+        inventory.player.slots[36] = stack(STONE, 40);
+        inventory.cursor = stack(STONE, 30);
+        let packet = inventory.click(PLAYER_WINDOW, 36, Click::Pickup).unwrap();
+        assert_eq!(count(&inventory.player.slots[36]), 64);
+        assert_eq!(count(&inventory.cursor), 6);
+        match packet {
+            Packet::ClientClickWindow {
+                slot,
+                button,
+                mode,
+                clicked_item,
+                ..
+            } => {
+                assert_eq!((slot, button, mode), (36, 0, 0));
+                assert_eq!(count(&clicked_item), 40);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // different items swap
+        inventory.player.slots[37] = stack(DIRT, 5);
+        inventory.click(PLAYER_WINDOW, 37, Click::Pickup);
+        assert_eq!(inventory.player.slots[37], stack(STONE, 6));
+        assert_eq!(inventory.cursor, stack(DIRT, 5));
+    }
+
+    #[test]
+    fn right_click_splits_in_half() {
+        let mut inventory = Inventory::new();
+        inventory.player.slots[9] = stack(STONE, 7);
+        inventory.click(PLAYER_WINDOW, 9, Click::PickupHalf);
+        assert_eq!(count(&inventory.cursor), 4);
+        assert_eq!(count(&inventory.player.slots[9]), 3);
+
+        // placing puts down one at a time
+        inventory.click(PLAYER_WINDOW, 10, Click::PickupHalf);
+        inventory.click(PLAYER_WINDOW, 9, Click::PickupHalf);
+        assert_eq!(count(&inventory.player.slots[10]), 1);
+        assert_eq!(count(&inventory.player.slots[9]), 4);
+        assert_eq!(count(&inventory.cursor), 2);
+    }
+
+    #[test]
+    fn shift_click_moves_between_chest_and_player() {
+        let mut inventory = Inventory::new();
+        open_chest(&mut inventory);
+        {
+            let chest = inventory.container.as_mut().unwrap();
+            chest.slots[0] = stack(STONE, 20);
+            chest.slots[30] = stack(STONE, 60);
+            chest.slots[62] = stack(DIRT, 1);
+        }
+        inventory.click(1, 0, Click::Shift);
+        let chest = inventory.container.as_ref().unwrap();
+        // tops up the matching stack first, the rest goes to the last free hotbar slot
+        assert_eq!(chest.slots[0], None);
+        assert_eq!(count(&chest.slots[30]), 64);
+        assert_eq!(chest.slots[61], stack(STONE, 16));
+        assert_eq!(chest.slots[62], stack(DIRT, 1));
+
+        inventory.click(1, 62, Click::Shift);
+        let chest = inventory.container.as_ref().unwrap();
+        assert_eq!(chest.slots[0], stack(DIRT, 1));
+        assert_eq!(chest.slots[62], None);
+    }
+
+    #[test]
+    fn rejected_clicks_are_rolled_back() {
+        let mut inventory = Inventory::new();
+        inventory.player.slots[36] = stack(STONE, 8);
+        let action_number = match inventory.click(PLAYER_WINDOW, 36, Click::Pickup) {
+            Some(Packet::ClientClickWindow { action_number, .. }) => action_number,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(inventory.player.slots[36], None);
+        assert_eq!(inventory.cursor, stack(STONE, 8));
+
+        let answer = inventory.apply(&Packet::ServerConfirmTransaction {
+            window_id: 0,
+            action_number,
+            accepted: false,
+        });
+        assert_eq!(inventory.player.slots[36], stack(STONE, 8));
+        assert_eq!(inventory.cursor, None);
+        match answer {
+            Some(Packet::ClientConfirmTransaction {
+                window_id: 0,
+                action_number: answered,
+                accepted: false,
+            }) => assert_eq!(answered, action_number),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accepted_clicks_are_kept() {
+        let mut inventory = Inventory::new();
+        inventory.player.slots[36] = stack(STONE, 8);
+        inventory.click(PLAYER_WINDOW, 36, Click::Pickup);
+        let answer = inventory.apply(&Packet::ServerConfirmTransaction {
+            window_id: 0,
+            action_number: 1,
+            accepted: true,
+        });
+        assert!(answer.is_none());
+        assert_eq!(inventory.cursor, stack(STONE, 8));
+        assert!(inventory.click(5, 0, Click::Pickup).is_none());
+        assert_eq!(inventory.cursor, stack(STONE, 8));
+    }
+
+    #[test]
+    fn held_slots_outside_the_hotbar_are_ignored() {
+        let mut inventory = Inventory::new();
+        inventory.apply(&Packet::ServerHeldItemChange { slot: 4 });
+        assert_eq!(inventory.held_slot, 4);
+        inventory.apply(&Packet::ServerHeldItemChange { slot: 9 });
+        inventory.apply(&Packet::ServerHeldItemChange { slot: -1 });
+        assert_eq!(inventory.held_slot, 4);
+    }
+}
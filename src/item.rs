@@ -0,0 +1,47 @@
+use crate::nbt::NbtTag;
+use crate::registry::Registry;
+
+/// The contents of a slot, empty slots are `None`
+pub type Slot = Option<ItemStack>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub item_id: i32,
+    pub count: i8,
+    /// Only sent before 1.13, later versions keep it in the `Damage` tag
+    pub damage: i16,
+    pub nbt: Option<NbtTag>,
+}
+
+impl ItemStack {
+    pub fn new(item_id: i32, count: i8) -> ItemStack {
+        ItemStack {
+            item_id,
+            count,
+            damage: 0,
+            nbt: None,
+        }
+    }
+
+    /// Looked up in the `minecraft:item` registry
+    pub fn name<'a>(&self, items: &'a Registry) -> Option<&'a str> {
+        items.name(self.item_id)
+    }
+
+    /// Durability used up, from the `Damage` tag or the 1.12 damage field
+    pub fn damage(&self) -> i32 {
+        self.tag("Damage")
+            .and_then(NbtTag::as_i64)
+            .map(|damage| damage as i32)
+            .unwrap_or(self.damage as i32)
+    }
+
+    /// The custom name set in an anvil, still as JSON text
+    pub fn display_name(&self) -> Option<&str> {
+        self.tag("display")?.get("Name")?.as_str()
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&NbtTag> {
+        self.nbt.as_ref()?.get(key)
+    }
+}
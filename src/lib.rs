@@ -0,0 +1,32 @@
+// err-derive expands its Display impls inside a const block
+#![allow(non_local_definitions)]
+
+#[macro_use]
+mod macros;
+#[cfg(feature = "async")]
+pub mod async_connection;
+pub mod block;
+pub mod chat;
+pub mod combat;
+pub mod commands;
+pub mod digging;
+pub mod encryption;
+pub mod entity;
+pub mod events;
+pub mod framing;
+pub mod game;
+pub mod hash;
+pub mod interaction;
+pub mod inventory;
+pub mod item;
+pub mod nbt;
+pub mod packets;
+pub mod pathfinder;
+pub mod physics;
+pub mod players;
+pub mod protocol;
+pub mod registry;
+pub mod session;
+pub mod status;
+pub mod version;
+pub mod world;
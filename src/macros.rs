@@ -1,8 +1,24 @@
-use crate::game::ConnectionState;
+/// Resolves a packet id column of `impl_packets!`: either one id for every version
+/// or a `[Version: id, ..]` list, versions missing from the list don't know the packet
+macro_rules! packet_id {
+    ($version:expr, [$($id_version:ident: $id:expr),* $(,)?]) => {
+        match $version {
+            $(ProtocolVersion::$id_version => Some($id),)*
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    };
+    ($version:expr, $id:expr) => {
+        Some($id)
+    };
+}
 
+/// Declares all packets. Every field is read and written by the `FieldCodec` of its type,
+/// `field: i32 as VarInt` picks another codec, `#[since(V1_14_4)]` / `#[until(V1_12_2)]`
+/// limit a field to some protocol versions
 macro_rules! impl_packets {
-    ($enum_name:ident, $type_enum_name:ident, $($state:ident, $bound_to:ident, $id:expr, $packet_name:ident { $($variant_body_field_name:ident: $variant_body_field_type:ty,)* },)*) => {
-        #[derive(Debug, Clone, Copy)]
+    ($enum_name:ident, $type_enum_name:ident, $($state:ident, $bound_to:ident, $id:tt, $packet_name:ident { $($(#[$gate:ident($gate_version:ident)])* $variant_body_field_name:ident: $variant_body_field_type:ty $(as $codec:ty)?,)* },)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
         pub enum BoundTo {
             Server,
             Client,
@@ -23,17 +39,52 @@ macro_rules! impl_packets {
                      $($enum_name::$packet_name {..} => $type_enum_name::$packet_name,)*
                 }
             }
+
+            /// Reads the fields of a packet of the given type, the packet id is already consumed
+            pub fn read_fields<R: Read>(ty: $type_enum_name, buf: &mut R, version: ProtocolVersion) -> IOResult<$enum_name> {
+                Ok(match ty {
+                    $($type_enum_name::$packet_name => $enum_name::$packet_name {
+                        $($variant_body_field_name: read_packet_field!(
+                            buf,
+                            version,
+                            $variant_body_field_type,
+                            field_codec!($variant_body_field_type $(, $codec)?),
+                            [$($gate($gate_version))*]
+                        ),)*
+                    },)*
+                })
+            }
+
+            /// Writes the fields of the packet, without the packet id
+            pub fn write_fields<W: Write>(&self, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+                match self {
+                    $($enum_name::$packet_name { $($variant_body_field_name,)* } => {
+                        $(write_packet_field!(
+                            buf,
+                            version,
+                            $variant_body_field_name,
+                            $variant_body_field_type,
+                            field_codec!($variant_body_field_type $(, $codec)?),
+                            [$($gate($gate_version))*]
+                        );)*
+                    })*
+                }
+                Ok(())
+            }
         }
 
-        #[derive(Copy, Clone, Debug)]
+        #[derive(Copy, Clone, Debug, PartialEq)]
         pub enum $type_enum_name {
             $($packet_name,)*
         }
 
         impl $type_enum_name {
-            pub fn id(&self) -> i32 {
+            pub const ALL: &'static [$type_enum_name] = &[$($type_enum_name::$packet_name,)*];
+
+            /// The id of the packet in the given protocol version, `None` if that version doesn't have it
+            pub fn id(&self, version: ProtocolVersion) -> Option<i32> {
                 match self {
-                     $($type_enum_name::$packet_name => $id,)*
+                     $($type_enum_name::$packet_name => packet_id!(version, $id),)*
                 }
             }
 
@@ -43,12 +94,77 @@ macro_rules! impl_packets {
                 }
             }
 
-            pub fn from_state_and_id_and_direction(state: ConnectionState, id: i32, bound_to: BoundTo) -> Option<$type_enum_name> {
-                match (state, bound_to, id) {
-                     $((ConnectionState::$state, BoundTo::$bound_to, $id) => Some($type_enum_name::$packet_name),)*
-                     _ => None,
+            pub fn bound_to(&self) -> BoundTo {
+                match self {
+                     $($type_enum_name::$packet_name => BoundTo::$bound_to,)*
                 }
             }
+
+            pub fn from_state_and_id_and_direction(version: ProtocolVersion, state: ConnectionState, id: i32, bound_to: BoundTo) -> Option<$type_enum_name> {
+                $type_enum_name::ALL
+                    .iter()
+                    .find(|ty| ty.state() == state && ty.bound_to() == bound_to && ty.id(version) == Some(id))
+                    .copied()
+            }
         }
     }
 }
+
+/// The codec of a packet field: the field type itself unless one is given with `as`
+macro_rules! field_codec {
+    ($field_type:ty) => {
+        $field_type
+    };
+    ($field_type:ty, $codec:ty) => {
+        $codec
+    };
+}
+
+macro_rules! version_gate {
+    (since, $version:expr, $gate_version:ident) => {
+        $version >= ProtocolVersion::$gate_version
+    };
+    (until, $version:expr, $gate_version:ident) => {
+        $version <= ProtocolVersion::$gate_version
+    };
+}
+
+/// Reads one packet field, fields the protocol version doesn't send get their default value
+macro_rules! read_packet_field {
+    ($buf:expr, $version:expr, $field_type:ty, $codec:ty, []) => {
+        <$codec as FieldCodec<$field_type>>::read($buf, $version)?
+    };
+    ($buf:expr, $version:expr, $field_type:ty, $codec:ty, [$($gate:ident($gate_version:ident))+]) => {
+        if $(version_gate!($gate, $version, $gate_version))&&+ {
+            <$codec as FieldCodec<$field_type>>::read($buf, $version)?
+        } else {
+            <$field_type as Default>::default()
+        }
+    };
+}
+
+macro_rules! write_packet_field {
+    ($buf:expr, $version:expr, $value:expr, $field_type:ty, $codec:ty, [$($gate:ident($gate_version:ident))*]) => {
+        if true $(&& version_gate!($gate, $version, $gate_version))* {
+            <$codec as FieldCodec<$field_type>>::write($value, $buf, $version)?;
+        }
+    };
+}
+
+/// Implements `FieldCodec` for types that map directly onto a `RawPacketValue`
+macro_rules! impl_raw_field_codecs {
+    ($($field_type:ty => $value_type:ident,)*) => {
+        $(impl FieldCodec<$field_type> for $field_type {
+            fn read<R: Read>(buf: &mut R, _: ProtocolVersion) -> IOResult<$field_type> {
+                match RawPacketValueType::$value_type.from_buf(buf)? {
+                    RawPacketValue::$value_type(value) => Ok(value),
+                    _ => unreachable!(),
+                }
+            }
+
+            fn write<W: Write>(value: &$field_type, buf: &mut W, _: ProtocolVersion) -> IOResult<()> {
+                RawPacketValue::$value_type(value.clone()).serialize(buf)
+            }
+        })*
+    };
+}
@@ -1,44 +1,166 @@
-#[macro_use]
-extern crate approx;
-use crate::game::{CompressionStatus, ConnectionState, Entity, MinecraftConnection};
-use crate::packets::{player, pos};
-use crate::protocol::PacketError::{DeserializeIOError, UnknownPacketIdentifier};
-use crate::protocol::{Packet, PacketError};
-use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TryRecvError};
-use mc_varint::{VarIntRead, VarIntWrite};
-use serde_json::json;
-use std::collections::LinkedList;
-use std::io::{Cursor, Read, Write};
-use std::io::{ErrorKind, Result};
-use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, TcpStream, ToSocketAddrs};
-use std::sync::{Arc, Barrier, Mutex, RwLock};
+use std::env;
+use std::io::Write;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, SocketAddrV4, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::thread::sleep;
 use std::time::Duration;
+use tcp_test::block::BlockRegistry;
+use tcp_test::chat::TextComponent;
+use tcp_test::combat::Combat;
+use tcp_test::commands::{ArgKind, Args, Command, CommandError, Commands};
+use tcp_test::digging::{self, Digger};
+use tcp_test::encryption::{generate_shared_secret, rsa_encrypt, CipherStream};
+use tcp_test::entity::{spawned_entity, Entities};
+use tcp_test::events::{Event, EventBus, EventKind};
+use tcp_test::framing::FrameDecoder;
+use tcp_test::game::{CompressionStatus, ConnectionState, Entity, MinecraftConnection};
+use tcp_test::interaction;
+use tcp_test::inventory::Inventory;
+use tcp_test::pathfinder::{Goal, Navigation, Navigator};
+use tcp_test::physics::{Controls, Physics};
+use tcp_test::players::PlayerList;
+use tcp_test::protocol::{Packet, PacketError, Position};
+use tcp_test::registry::Registry;
+use tcp_test::session::{join_session, Account, HttpSessionService, MOJANG_SESSION_SERVER};
+use tcp_test::world::{dimension_name, ChunkBlockID, World};
 
-#[macro_use]
-mod macros;
-mod compression;
-mod game;
-mod hash;
-mod packets;
-mod protocol;
-//mod world;
+/// The server simulates 20 ticks per second
+const TICK: Duration = Duration::from_millis(50);
+/// How long "#walk" walks, about a block
+const WALK_TICKS: u32 = 5;
+/// How close "#follow" stays to the player
+const FOLLOW_DISTANCE: f64 = 2.0;
+/// Vanilla waits this long between two right clicks
+const PLACE_TICKS: u32 = 4;
+/// Used when the server address has no port
+const DEFAULT_PORT: u16 = 25565;
 
 fn main() {
-    bot("owow".parse().unwrap());
+    let address = match server_address() {
+        Ok(address) => address,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+    #[cfg(feature = "async")]
+    {
+        if let Some(count) = env::var("BOT_COUNT").ok().and_then(|c| c.parse().ok()) {
+            swarm(count, address);
+            return;
+        }
+    }
+    bot("owow".parse().unwrap(), address);
+}
+
+/// The server to join, `host[:port]` from the first argument or `MINECRAFT_SERVER`
+fn server_address() -> Result<SocketAddrV4> {
+    let address = env::args()
+        .nth(1)
+        .or_else(|| env::var("MINECRAFT_SERVER").ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "usage: tcp-test <host[:port]>, or set MINECRAFT_SERVER",
+            )
+        })?;
+    resolve_address(&address)
+}
+
+/// Looks up the IPv4 address of `host[:port]`
+fn resolve_address(address: &str) -> Result<SocketAddrV4> {
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    address
+        .to_socket_addrs()?
+        .find_map(|address| match address {
+            SocketAddr::V4(address) => Some(address),
+            SocketAddr::V6(_) => None,
+        })
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("{} has no IPv4 address", address),
+            )
+        })
 }
 
-fn bot(name: String) {
+/// Runs `count` bots on one tokio runtime, they only log in and answer keep alives
+#[cfg(feature = "async")]
+fn swarm(count: usize, address: SocketAddrV4) {
+    use futures::{SinkExt, StreamExt};
+    use tcp_test::async_connection::AsyncConnection;
+
+    async fn idle_bot(name: String, address: SocketAddrV4) -> Result<()> {
+        // the server list ping uses a blocking socket
+        let connection = tokio::task::spawn_blocking(move || {
+            let mut connection = MinecraftConnection::new(address, name);
+            connection.account = Account::from_env();
+            connection.detect_version().map(|_| connection)
+        })
+        .await
+        .map_err(Error::other)??;
+        let session = HttpSessionService::new(
+            &env::var("MINECRAFT_SESSION_SERVER").unwrap_or(MOJANG_SESSION_SERVER.to_owned()),
+        );
+
+        let mut connection = AsyncConnection::connect(connection).await?;
+        let (name, uuid) = connection.login(&session).await?;
+        println!("Logged in as {} with UUID: {:?}", name, uuid);
+
+        let (mut writer, mut reader) = connection.split();
+        while let Some(packet) = reader.next().await {
+            match packet? {
+                Packet::ServerKeepAlive { magic } => {
+                    writer.send(Packet::ClientKeepAlive { magic }).await?;
+                }
+                Packet::ServerDisconnectPacket { reason } => {
+                    let reason = TextComponent::from_json(&reason)
+                        .map(|reason| reason.to_plain())
+                        .unwrap_or(reason);
+                    println!("{} was disconnected: {}", name, reason);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let bots = (0..count).map(|i| {
+            tokio::spawn(async move {
+                if let Err(err) = idle_bot(format!("owow{}", i), address).await {
+                    println!("Bot {} stopped: {}", i, err);
+                }
+            })
+        });
+        futures::future::join_all(bots).await;
+    });
+}
+
+fn bot(name: String, address: SocketAddrV4) {
     //  let mut loggedin = false;
 
-    let ipadress = "5.181.151.65";
-    let port = 25565;
-    let mut connection = Arc::new(RwLock::new(MinecraftConnection::new(
-        format!("{}:{}", ipadress, port).parse().unwrap(),
-        "gay".parse().unwrap(),
-    )));
-    let mut entity = Entity {
+    let connection = Arc::new(RwLock::new(MinecraftConnection::new(address, name.clone())));
+    connection.write().unwrap().account = Account::from_env();
+    let session = HttpSessionService::new(
+        &env::var("MINECRAFT_SESSION_SERVER").unwrap_or(MOJANG_SESSION_SERVER.to_owned()),
+    );
+    let detected = connection.write().unwrap().detect_version();
+    match detected {
+        Ok(version) => println!("Server runs {}", version.name()),
+        Err(err) => {
+            println!("Can't join the server: {}", err);
+            return;
+        }
+    }
+    let entity = Entity {
         entityid: 0,
         x: 0.0,
         y: 0.0,
@@ -47,70 +169,166 @@ fn bot(name: String) {
         pitch: 0.0,
     };
 
-    match TcpStream::connect(format!("{}:{}", "dev.blohnung.de", "25565")) {
-        Ok(mut stream) => {
-            stream.set_nonblocking(true);
-            let barrier = Arc::new(Barrier::new(2));
+    match TcpStream::connect(address) {
+        Ok(stream) => {
+            if let Err(err) = stream.set_nonblocking(true) {
+                println!("Could not make the socket non-blocking: {}", err);
+                return;
+            }
+            let mut stream = CipherStream::new(stream);
             let (outbound_sender, outbound_receiver) = crossbeam_channel::unbounded::<Packet>();
             let (inbound_sender, inbound_receiver) = crossbeam_channel::unbounded::<Packet>();
 
             let entity = Arc::new(Mutex::new(entity));
+            let world = Arc::new(Mutex::new(World::new(load_block_registry())));
+            let entities = Arc::new(Mutex::new(Entities::new(load_registry(
+                "minecraft:entity_type",
+            ))));
+            let players = Arc::new(Mutex::new(PlayerList::new()));
+            let inventory = Arc::new(Mutex::new(Inventory::new()));
+            let physics = Arc::new(Mutex::new(Physics::new()));
+            let navigator = Arc::new(Mutex::new(Navigator::new(&mut world.lock().unwrap())));
+            let digger = Arc::new(Mutex::new(Digger::new(load_registry("minecraft:item"))));
+            let combat = Arc::new(Mutex::new(Combat::new(load_registry("minecraft:item"))));
+            let mut bus = EventBus::new(outbound_sender.clone());
+            let block_changes = world.lock().unwrap().subscribe();
             {
                 thread::spawn({
                     let connection = connection.clone();
-                    let barrier = barrier.clone();
+                    let events = bus.sender();
+                    let mut frame_decoder = FrameDecoder::new();
+                    let (encryption_sender, encryption_receiver) =
+                        crossbeam_channel::bounded::<(Packet, [u8; 16])>(1);
                     move || loop {
-                        // barrier.wait();
-
                         let mut connection_state = { connection.write().unwrap() };
-                        match outbound_receiver.recv_timeout(Duration::from_millis(1)) {
-                            Ok(mut msg) => {
-                                println!("-> {:X?}", msg);
-                                match msg {
-                                    p => {
-                                        stream.write_all(&p.serialize(&connection_state).unwrap());
+                        if let Ok(p) = outbound_receiver.recv_timeout(Duration::from_millis(1)) {
+                            println!("-> {:X?}", p);
+                            // packets the server's version doesn't know are dropped
+                            match p.clone().serialize(&connection_state) {
+                                Ok(bytes) => {
+                                    let _ = events.send(Event::PacketSent(p));
+                                    if let Err(err) = stream.write_all(&bytes) {
+                                        println!("Connection lost: {}", err);
+                                        let _ = events.send(Event::Disconnected(err.to_string()));
+                                        return;
                                     }
                                 }
+                                Err(err) => println!("Dropped a packet: {}", err),
                             }
-                            Err(_) => {}
-                            _ => {}
                         }
                         thread::sleep(Duration::from_millis(1));
-                        match Packet::deserialize(&mut stream, &connection_state) {
-                            Ok(received_packet) => match received_packet.clone() {
-                                Packet::ServerCompressionLevelSet { compression_level } => {
-                                    if let CompressionStatus::Enabled(i) =
-                                        connection_state.compression
-                                    {
-                                        println!("Compression threshold wants to be set to {} but already was set to {}", compression_level, i);
-                                    } else {
-                                        connection_state.compression =
-                                            CompressionStatus::Enabled(compression_level);
-                                        println!(
-                                            "Compression threshold set to {}",
-                                            compression_level
-                                        );
-                                    }
+                        // the response is the last packet sent in plain text
+                        if let Ok((response, shared_secret)) = encryption_receiver.try_recv() {
+                            println!("-> {:X?}", response);
+                            if let Err(err) = response
+                                .serialize(&connection_state)
+                                .and_then(|bytes| stream.write_all(&bytes))
+                            {
+                                println!("Connection lost: {}", err);
+                                let _ = events.send(Event::Disconnected(err.to_string()));
+                                return;
+                            }
+                            stream.enable(&shared_secret).unwrap();
+                            println!("Encryption enabled");
+                        }
+                        if let Err(err) = stream.write_pending() {
+                            println!("Connection lost: {}", err);
+                            let _ = events.send(Event::Disconnected(err.to_string()));
+                            return;
+                        }
+                        if let Err(err) = frame_decoder.fill(&mut stream) {
+                            println!("Connection lost: {}", err);
+                            let _ = events.send(Event::Disconnected(err.to_string()));
+                            return;
+                        }
+                        loop {
+                            let frame = match frame_decoder.next_frame() {
+                                Ok(Some(frame)) => frame,
+                                Ok(None) => break,
+                                Err(err) => {
+                                    println!("Stream out of sync: {}", err);
+                                    let _ = events.send(Event::Disconnected(err.to_string()));
+                                    return;
                                 }
-                                Packet::ServerLoginSuccess { uuid, name } => {
-                                    inbound_sender.send(received_packet);
-                                    // thread::sleep(Duration::from_millis(1));
+                            };
+                            match Packet::from_frame(frame, &connection_state) {
+                                Ok(received_packet) => match received_packet.clone() {
+                                    Packet::ServerCompressionLevelSet { compression_level } => {
+                                        if let CompressionStatus::Enabled(i) =
+                                            connection_state.compression
+                                        {
+                                            println!("Compression threshold wants to be set to {} but already was set to {}", compression_level, i);
+                                        } else {
+                                            connection_state.apply_state_change(&received_packet);
+                                            println!(
+                                                "Compression threshold set to {}",
+                                                compression_level
+                                            );
+                                        }
+                                    }
+                                    Packet::ServerEncryptionRequest {
+                                        serverid,
+                                        pubkey,
+                                        verifytoken,
+                                    } => {
+                                        // the session server answers slowly, the network
+                                        // thread keeps going while it is asked
+                                        let account = connection_state.account.clone();
+                                        let session = session.clone();
+                                        let encryption_sender = encryption_sender.clone();
+                                        thread::spawn(move || {
+                                            let shared_secret = generate_shared_secret();
+                                            if let Err(err) = join_session(
+                                                &session,
+                                                account.as_ref(),
+                                                &serverid,
+                                                &shared_secret,
+                                                &pubkey,
+                                            ) {
+                                                println!("Could not join session: {}", err);
+                                            }
+                                            match (
+                                                rsa_encrypt(&pubkey, &shared_secret),
+                                                rsa_encrypt(&pubkey, &verifytoken),
+                                            ) {
+                                                (
+                                                    Ok(shared_secret_encrypted),
+                                                    Ok(verify_token_encrypted),
+                                                ) => {
+                                                    let response =
+                                                        Packet::ClientEncryptionResponse {
+                                                            shared_secret: shared_secret_encrypted,
+                                                            verify_token: verify_token_encrypted,
+                                                        };
+                                                    let _ = encryption_sender
+                                                        .send((response, shared_secret));
+                                                }
+                                                (Err(err), _) | (_, Err(err)) => {
+                                                    println!(
+                                                        "Could not answer encryption request: {}",
+                                                        err
+                                                    );
+                                                }
+                                            }
+                                        });
+                                    }
+                                    // the rest of this read already is in Play state
+                                    Packet::ServerLoginSuccess { .. } => {
+                                        connection_state.apply_state_change(&received_packet);
+                                        let _ = inbound_sender.send(received_packet);
+                                    }
+                                    p => {
+                                        let _ = inbound_sender.send(p.clone());
+                                        println!(" <- {:02X?}", p);
+                                    }
+                                },
+                                Err(err @ PacketError::UnknownPacketIdentifier { .. }) => {
+                                    println!("Skipped {}", err);
                                 }
-                                p => {
-                                    inbound_sender.send(p.clone());
-                                    println!(" <- {:02X?}", p);
+                                Err(err) => {
+                                    dbg!(err);
                                 }
-                            },
-                            Err(DeserializeIOError(err)) => {
-                                //dbg!(err);
-                            }
-                            Err(PacketError::UnknownPacketIdentifier { .. }) => {
-                                //dbg!(err);
-                            }
-                            Err(err) => {
-                                dbg!(err);
                             }
-                            _ => (),
                         }
 
                         //  println!("etron");
@@ -118,155 +336,521 @@ fn bot(name: String) {
                 });
             }
 
-            println!("Successfully connected to server {}:{}", ipadress, port);
+            println!("Successfully connected to server {}", address);
             // handshake, join
-            outbound_sender.send(Packet::ClientHandshake {
-                host_address: ipadress.to_string(),
-                port,
-            });
-            outbound_sender.send(Packet::ClientJoin {
+            let _ =
+                outbound_sender.send(connection.read().unwrap().handshake(ConnectionState::Login));
+            let _ = outbound_sender.send(Packet::ClientJoin {
                 player_name: name.to_string(),
             });
 
             thread::spawn({
                 let mut enabled = false;
+                let mut place_cooldown: u32 = 0;
                 let entity = entity.clone();
+                let physics = physics.clone();
+                let navigator = navigator.clone();
+                let digger = digger.clone();
+                let combat = combat.clone();
+                let inventory = inventory.clone();
+                let world = world.clone();
+                let entities = entities.clone();
                 let outbound_sender = outbound_sender.clone();
                 let connection = connection.clone();
-                let mut serverentity = Entity {
-                    entityid: 0,
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                    yaw: 0.0,
-                    pitch: 0.0,
-                };
+                let events = bus.sender();
 
                 move || loop {
-                    {
-                        //  dbg!("kot");
-                        thread::sleep(Duration::from_millis(20));
-                        //   barrier.wait();
-                        //   barrier.wait();
-                        // println!("as");
-                        {
-                            if !enabled {
-                                let state = connection.read().unwrap().state;
-
-                                match state {
-                                    ConnectionState::Play => {
-                                        enabled = true;
+                    thread::sleep(TICK);
+                    if !enabled {
+                        enabled = connection.read().unwrap().state == ConnectionState::Play;
+                        continue;
+                    }
+                    let mut lockedentity = entity.lock().unwrap();
+                    let mut physics = physics.lock().unwrap();
+                    let world = world.lock().unwrap();
+                    let navigation = navigator.lock().unwrap().tick(
+                        &mut lockedentity,
+                        &mut physics,
+                        &world,
+                        &entities.lock().unwrap(),
+                    );
+                    if navigation == Navigation::Unreachable {
+                        println!("No path to the goal");
+                    }
+                    place_cooldown = place_cooldown.saturating_sub(1);
+                    if let Navigation::Place(position) = navigation {
+                        let holding = inventory.lock().unwrap().held_item().is_some();
+                        if holding && place_cooldown == 0 {
+                            place_cooldown = PLACE_TICKS;
+                            let version = connection.read().unwrap().protocol_version;
+                            match interaction::place_at(
+                                &world,
+                                &mut lockedentity,
+                                position,
+                                version,
+                                physics.onground,
+                            ) {
+                                Ok(packets) => {
+                                    for packet in packets {
+                                        outbound_sender.send(packet).unwrap();
                                     }
-
-                                    _ => {}
-                                }
-                            } else {
-                                let lockedentity = entity.lock().unwrap();
-                                if !compareLoc(&lockedentity, &serverentity) {
-                                    outbound_sender
-                                        .send(Packet::ClientPlayerPositionAndLook {
-                                            x: lockedentity.x,
-                                            y: lockedentity.y,
-                                            z: lockedentity.z,
-                                            yaw: lockedentity.yaw,
-                                            pitch: lockedentity.pitch,
-                                            onground: false,
-                                        })
-                                        .unwrap();
-                                    serverentity = **&lockedentity;
                                 }
+                                Err(err) => println!("Can't place a block: {}", err),
                             }
                         }
                     }
-
-                    //   println!("tick !");
+                    let packets = combat.lock().unwrap().tick(
+                        &mut lockedentity,
+                        &entities.lock().unwrap(),
+                        &inventory.lock().unwrap(),
+                    );
+                    for packet in packets {
+                        outbound_sender.send(packet).unwrap();
+                    }
+                    let mut digger = digger.lock().unwrap();
+                    if let Navigation::Break(position) = navigation {
+                        if digger.target() != Some(position) {
+                            let packets = digger.dig(
+                                position,
+                                &world,
+                                &inventory.lock().unwrap(),
+                                &mut lockedentity,
+                                physics.onground,
+                            );
+                            for packet in packets {
+                                outbound_sender.send(packet).unwrap();
+                            }
+                        }
+                    }
+                    for packet in digger.tick() {
+                        outbound_sender.send(packet).unwrap();
+                    }
+                    physics.tick(&mut lockedentity, &world);
+                    for packet in physics.movement_packets(&lockedentity) {
+                        outbound_sender.send(packet).unwrap();
+                    }
+                    // the bus is gone once the main loop ended
+                    if events.send(Event::Tick).is_err() {
+                        return;
+                    }
                 }
             });
+            let own_name = connection.read().unwrap().player_name.clone();
+            let mut commands = Commands::new("#", &own_name, outbound_sender.clone());
+            if let Ok(operators) = env::var("BOT_OPERATORS") {
+                commands.allowed = operators
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+            }
+            commands.register(
+                Command::new("walk", "walks about a block, keys are any of wasd", {
+                    let physics = physics.clone();
+                    move |_, args| {
+                        let keys = args.text("keys").unwrap_or_default();
+                        let mut controls = Controls::default();
+                        if keys.contains('w') {
+                            controls.forward += 1.0;
+                        }
+                        if keys.contains('a') {
+                            controls.strafe += 1.0;
+                        }
+                        if keys.contains('d') {
+                            controls.strafe -= 1.0;
+                        }
+                        if keys.contains('s') {
+                            controls.forward -= 1.0;
+                        }
+                        physics.lock().unwrap().hold(controls, WALK_TICKS);
+                        Ok(())
+                    }
+                })
+                .arg("keys", ArgKind::Word),
+            );
+            commands.register(
+                Command::new("setslot", "selects a hotbar slot, 0 to 8", {
+                    let inventory = inventory.clone();
+                    move |context, args| {
+                        let slot = args.int("slot").unwrap_or_default();
+                        if !(0..=8).contains(&slot) {
+                            return Err(CommandError::Failed(format!("no hotbar slot {}", slot)));
+                        }
+                        context.send(inventory.lock().unwrap().select_hotbar(slot as u8));
+                        Ok(())
+                    }
+                })
+                .arg("slot", ArgKind::Int),
+            );
+            commands.register(
+                Command::new("goto", "walks to x y z, or to x z at any height", {
+                    let navigator = navigator.clone();
+                    move |_, args| {
+                        let x = args.int("x").unwrap_or_default();
+                        let y = args.int("y").unwrap_or_default();
+                        let goal = match args.int("z") {
+                            Some(z) => Goal::Block(Position::new(x, y, z)),
+                            None => Goal::XZ(x, y),
+                        };
+                        navigator.lock().unwrap().set_goal(goal);
+                        Ok(())
+                    }
+                })
+                .arg("x", ArgKind::Int)
+                .arg("y", ArgKind::Int)
+                .optional("z", ArgKind::Int),
+            );
+            commands.register(
+                Command::new("follow", "follows a player, or whoever asked", {
+                    let players = players.clone();
+                    let entities = entities.clone();
+                    let navigator = navigator.clone();
+                    move |context, args| {
+                        let name = args.text("player").unwrap_or(context.sender);
+                        let id = find_player(&players, &entities, name)?;
+                        navigator
+                            .lock()
+                            .unwrap()
+                            .set_goal(Goal::Follow(id, FOLLOW_DISTANCE));
+                        Ok(())
+                    }
+                })
+                .optional("player", ArgKind::Word),
+            );
+            commands.register(
+                Command::new(
+                    "attack",
+                    "follows and attacks a player, or whoever asked",
+                    {
+                        let players = players.clone();
+                        let entities = entities.clone();
+                        let navigator = navigator.clone();
+                        let combat = combat.clone();
+                        move |context, args| {
+                            let name = args.text("player").unwrap_or(context.sender);
+                            let id = find_player(&players, &entities, name)?;
+                            navigator
+                                .lock()
+                                .unwrap()
+                                .set_goal(Goal::Follow(id, FOLLOW_DISTANCE));
+                            combat.lock().unwrap().set_target(Some(id));
+                            Ok(())
+                        }
+                    },
+                )
+                .optional("player", ArgKind::Word),
+            );
+            commands.register(Command::new(
+                "stop",
+                "stops walking, digging and fighting",
+                {
+                    let physics = physics.clone();
+                    let navigator = navigator.clone();
+                    let combat = combat.clone();
+                    let digger = digger.clone();
+                    move |context, _| {
+                        // same lock order as the tick thread
+                        let mut physics = physics.lock().unwrap();
+                        navigator.lock().unwrap().stop(&mut physics);
+                        combat.lock().unwrap().set_target(None);
+                        for packet in digger.lock().unwrap().cancel() {
+                            context.send(packet);
+                        }
+                        Ok(())
+                    }
+                },
+            ));
+            commands.register(
+                Command::new("dig", "digs the block at x y z", {
+                    let entity = entity.clone();
+                    let physics = physics.clone();
+                    let world = world.clone();
+                    let digger = digger.clone();
+                    let inventory = inventory.clone();
+                    move |context, args| {
+                        let mut lockedentity = entity.lock().unwrap();
+                        let onground = physics.lock().unwrap().onground;
+                        let world = world.lock().unwrap();
+                        let position = position_arg(args);
+                        if let Some(state) =
+                            world.block_state_at(position.x, position.y, position.z)
+                        {
+                            if !digging::is_known(&state.name) {
+                                println!(
+                                    "Unknown hardness of {}, digging it like stone",
+                                    state.name
+                                );
+                            }
+                        }
+                        let packets = digger.lock().unwrap().dig(
+                            position,
+                            &world,
+                            &inventory.lock().unwrap(),
+                            &mut lockedentity,
+                            onground,
+                        );
+                        for packet in packets {
+                            context.send(packet);
+                        }
+                        Ok(())
+                    }
+                })
+                .arg("x", ArgKind::Int)
+                .arg("y", ArgKind::Int)
+                .arg("z", ArgKind::Int),
+            );
+            commands.register(
+                Command::new("place", "places the held block at x y z", {
+                    let entity = entity.clone();
+                    let world = world.clone();
+                    let connection = connection.clone();
+                    let physics = physics.clone();
+                    move |context, args| {
+                        let version = connection.read().unwrap().protocol_version;
+                        let onground = physics.lock().unwrap().onground;
+                        let mut lockedentity = entity.lock().unwrap();
+                        let world = world.lock().unwrap();
+                        let packets = interaction::place_at(
+                            &world,
+                            &mut lockedentity,
+                            position_arg(args),
+                            version,
+                            onground,
+                        )
+                        .map_err(|err| CommandError::Failed(err.to_string()))?;
+                        for packet in packets {
+                            context.send(packet);
+                        }
+                        Ok(())
+                    }
+                })
+                .arg("x", ArgKind::Int)
+                .arg("y", ArgKind::Int)
+                .arg("z", ArgKind::Int),
+            );
+            commands.register(
+                Command::new("use", "right clicks the door, button, chest... at x y z", {
+                    let entity = entity.clone();
+                    let world = world.clone();
+                    let connection = connection.clone();
+                    let physics = physics.clone();
+                    move |context, args| {
+                        let version = connection.read().unwrap().protocol_version;
+                        let onground = physics.lock().unwrap().onground;
+                        let mut lockedentity = entity.lock().unwrap();
+                        let world = world.lock().unwrap();
+                        let packets = interaction::activate_block(
+                            &world,
+                            &mut lockedentity,
+                            position_arg(args),
+                            version,
+                            onground,
+                        )
+                        .map_err(|err| CommandError::Failed(err.to_string()))?;
+                        for packet in packets {
+                            context.send(packet);
+                        }
+                        Ok(())
+                    }
+                })
+                .arg("x", ArgKind::Int)
+                .arg("y", ArgKind::Int)
+                .arg("z", ArgKind::Int),
+            );
+            commands.register(
+                Command::new("say", "says something in chat", |context, args| {
+                    let message = args.text("text").unwrap_or_default().to_string();
+                    // the server would run it as one of its commands, with our permissions
+                    if message.starts_with('/') {
+                        return Err(CommandError::Failed("won't run server commands".into()));
+                    }
+                    context.send(Packet::ClientChat { message });
+                    Ok(())
+                })
+                .arg("text", ArgKind::Text),
+            );
+            commands.register(Command::new(
+                "useitem",
+                "uses the held item",
+                |context, _| {
+                    context.send(interaction::use_item(interaction::MAIN_HAND));
+                    Ok(())
+                },
+            ));
+            bus.register(commands);
             'outer: loop {
                 //thread::sleep(Duration::from_millis(5));
                 //
                 // barrier.wait();
+                for event in bus.take_queued() {
+                    bus.emit(&event);
+                    if let Event::Disconnected(_) = event {
+                        break 'outer;
+                    }
+                }
+                for change in block_changes.try_iter() {
+                    bus.emit(&Event::BlockChanged(change));
+                }
                 if let Ok(packet) = inbound_receiver.try_recv() {
                     println!(".");
+                    if bus.wants(EventKind::PacketReceived) {
+                        bus.emit(&Event::PacketReceived(packet.clone()));
+                    }
+                    entities.lock().unwrap().apply(&packet);
+                    if let Some(id) = spawned_entity(&packet) {
+                        let appeared = entities.lock().unwrap().get(id).cloned();
+                        if let Some(appeared) = appeared {
+                            bus.emit(&Event::EntityAppeared(appeared));
+                        }
+                    }
+                    players.lock().unwrap().apply(&packet);
+                    if let Some(reply) = inventory.lock().unwrap().apply(&packet) {
+                        let _ = outbound_sender.send(reply);
+                    }
+                    let (reply, died) = {
+                        let mut combat = combat.lock().unwrap();
+                        let was_dead = combat.is_dead();
+                        (combat.apply(&packet), !was_dead && combat.is_dead())
+                    };
+                    if died {
+                        bus.emit(&Event::Died);
+                    }
+                    if let Some(reply) = reply {
+                        println!("Died, respawning");
+                        let _ = outbound_sender.send(reply);
+                    }
+                    if let Some(result) = digger.lock().unwrap().apply(&packet) {
+                        println!("{:?}", result);
+                    }
                     match packet {
                         Packet::ServerKeepAlive { magic: moom } => {
-                            outbound_sender.send(Packet::ClientKeepAlive { magic: moom });
+                            let _ = outbound_sender.send(Packet::ClientKeepAlive { magic: moom });
 
                             // entity.lock().unwrap().z += 1.0;
                             // entity.lock().unwrap().pitch += 1.0;
                             //outbound_sender.send(chat("Koop Eliv"));
                         }
 
-                        Packet::ServerChatPacket {
-                            message: msg,
-                            position: displayposition,
-                        } => {
-                            let mut gay = msg.as_str();
-                            if !gay.contains("!") {
-                                let message = msg.split("\\u003e").nth(1).unwrap_or("> gay");
-                                dbg!(message);
-                                let mut bot = entity.lock().unwrap();
-                                if message.contains("w") {
-                                    bot.z += 1.0;
-                                }
-                                if message.contains("a") {
-                                    bot.x += 1.0;
-                                }
-                                if message.contains("d") {
-                                    bot.x -= 1.0;
-                                }
-                                if message.contains("s") {
-                                    bot.z -= 1.0;
-                                }
-                                bot.yaw += 10.0;
-                                //  bot.pitch = -90.0;
-                                dbg!(&msg);
-                            }
-                            if gay.contains("#") {
-                                let mut bot = entity.lock().unwrap();
-                                let message: Vec<&str> = gay
-                                    .split("\\u003e")
-                                    .nth(1)
-                                    .unwrap_or("\"]")
-                                    .split("\"")
-                                    .nth(0)
-                                    .unwrap()
-                                    .split("\"")
-                                    .collect();
-                                let command = message[0];
-                                dbg!(message);
-                                if command.contains("setslot") {
-                                    outbound_sender.send(Packet::ClientHeldItemChange { slot: 5 });
+                        Packet::ServerChatPacket { message: msg, .. } => {
+                            let component = match TextComponent::from_json(&msg) {
+                                Ok(component) => component,
+                                Err(err) => {
+                                    dbg!(err, msg);
+                                    continue;
                                 }
+                            };
+                            println!("{}", component.to_plain());
+                            if let Some(chat) = component.chat_message() {
+                                bus.emit(&Event::Chat(chat));
                             }
                         }
 
-                        Packet::ServerPlayerPositionAndLook {
-                            x,
-                            y,
-                            z,
-                            yaw,
-                            pitch,
-                            flags,
-                            teleportid,
-                        } => {
+                        Packet::ServerPlayerPositionAndLook { .. } => {
                             /* outbound_sender.send(Packet::ClientChat {
                                 message: "!da is ne wand".to_string(),
                             });*/
                             println!("packet position bekommen");
                             let mut lockedentity = entity.lock().unwrap();
-                            lockedentity.x = x;
-                            lockedentity.y = y;
-                            lockedentity.z = z;
-                            lockedentity.yaw = yaw;
-                            lockedentity.pitch = pitch;
+                            let (replies, spawned) = {
+                                let mut physics = physics.lock().unwrap();
+                                let was_spawned = physics.spawned();
+                                (physics.teleport(&mut lockedentity, &packet), !was_spawned)
+                            };
+                            drop(lockedentity);
+                            for reply in replies {
+                                let _ = outbound_sender.send(reply);
+                            }
+                            if spawned {
+                                bus.emit(&Event::Spawned);
+                            }
                             println!("eigene position angepasst bro");
                         }
 
+                        Packet::ServerJoinGame {
+                            entity_id,
+                            view_distance,
+                            dimension,
+                            world_name,
+                            ..
+                        } => {
+                            entity.lock().unwrap().entityid = entity_id;
+                            let mut world = world.lock().unwrap();
+                            world.view_distance = view_distance;
+                            world.set_dimension(dimension_name(dimension, &world_name));
+                        }
+
+                        Packet::ServerRespawn {
+                            dimension,
+                            world_name,
+                            ..
+                        } => {
+                            // same lock order as the tick thread
+                            let mut physics = physics.lock().unwrap();
+                            physics.clear();
+                            if world
+                                .lock()
+                                .unwrap()
+                                .set_dimension(dimension_name(dimension, &world_name))
+                            {
+                                println!("Changed dimension");
+                            }
+                            navigator.lock().unwrap().stop(&mut physics);
+                            // the server spawns everything again around the new player
+                            entities.lock().unwrap().clear();
+                            inventory.lock().unwrap().clear();
+                        }
+
+                        Packet::ServerBlockChange { location, block_id } => {
+                            world
+                                .lock()
+                                .unwrap()
+                                .set_block(location, ChunkBlockID(block_id as u16));
+                        }
+
+                        Packet::ServerMultiBlockChange {
+                            chunk_x,
+                            chunk_z,
+                            records,
+                        } => {
+                            world
+                                .lock()
+                                .unwrap()
+                                .multi_block_change(chunk_x, chunk_z, &records);
+                        }
+
+                        Packet::ServerUnloadChunk { chunk_x, chunk_z } => {
+                            world.lock().unwrap().unload_chunk(chunk_x, chunk_z);
+                        }
+
+                        Packet::ServerUpdateViewPosition { chunk_x, chunk_z } => {
+                            world.lock().unwrap().set_view_center(chunk_x, chunk_z);
+                        }
+
+                        Packet::ServerExplosion {
+                            x, y, z, records, ..
+                        } => {
+                            world.lock().unwrap().explode(x, y, z, &records);
+                        }
+
+                        chunk @ Packet::ServerChunkData { .. } => {
+                            if let Err(err) = world.lock().unwrap().load_chunk(&chunk) {
+                                println!("Could not load {}", err);
+                            }
+                        }
+
                         Packet::ServerLoginSuccess { name, uuid } => {
-                            connection.write().unwrap().state = ConnectionState::Play;
                             println!("Logged in as {} with UUID: {:?}", name, uuid);
                         }
+
+                        Packet::ServerLoginDisconnect { reason }
+                        | Packet::ServerDisconnectPacket { reason } => {
+                            let reason = TextComponent::from_json(&reason)
+                                .map(|reason| reason.to_plain())
+                                .unwrap_or(reason);
+                            println!("Disconnected: {}", reason);
+                            bus.emit(&Event::Disconnected(reason));
+                            break 'outer;
+                        }
                         p => {
                             dbg!(p);
                         }
@@ -274,27 +858,79 @@ fn bot(name: String) {
                 }
             }
         }
-        _ => {}
+        Err(err) => println!("Could not connect to {}: {}", address, err),
     }
 
     println!("Terminated.");
 }
 
-fn compareLoc(entity1: &Entity, entity2: &Entity) -> bool {
-    let mut out = false;
-    out = eq(entity1.x, entity2.x, 0.5);
-    if (out) {
-        out = eq(entity1.y, entity2.y, 0.5);
-    }
-    if (out) {
-        out = eq(entity1.z, entity2.z, 0.5);
-    }
+fn reports_dir() -> String {
+    env::var("MINECRAFT_REPORTS").unwrap_or("reports".to_owned())
+}
 
-    //kann auch mit && gemacht werden bro
+/// The entity id of a player we can see
+fn find_player(
+    players: &Mutex<PlayerList>,
+    entities: &Mutex<Entities>,
+    name: &str,
+) -> std::result::Result<i32, CommandError> {
+    let uuid = players
+        .lock()
+        .unwrap()
+        .by_name(name)
+        .map(|player| player.uuid);
+    uuid.and_then(|uuid| entities.lock().unwrap().by_uuid(uuid).map(|e| e.id))
+        .ok_or_else(|| CommandError::Failed(format!("Can't see {}", name)))
+}
 
-    out
+/// The block position of the `x`, `y` and `z` arguments
+fn position_arg(args: &Args) -> Position {
+    Position::new(
+        args.int("x").unwrap_or_default(),
+        args.int("y").unwrap_or_default(),
+        args.int("z").unwrap_or_default(),
+    )
 }
 
-fn eq(a: f64, b: f64, range: f64) -> bool {
-    (a - b).abs() <= range
+/// Reads one registry out of registries.json in `MINECRAFT_REPORTS`
+fn load_registry(name: &str) -> Registry {
+    let reports = reports_dir();
+    match Registry::from_report(format!("{}/registries.json", reports), name) {
+        Ok(registry) => registry,
+        Err(err) => {
+            println!("No {} registry in {}: {}", name, reports, err);
+            Registry::default()
+        }
+    }
+}
+
+/// Reads blocks.json from the report directory in `MINECRAFT_REPORTS`, without it the bot
+/// only knows air from not air
+fn load_block_registry() -> BlockRegistry {
+    let reports = reports_dir();
+    match BlockRegistry::from_report(format!("{}/blocks.json", reports)) {
+        Ok(blocks) => blocks,
+        Err(err) => {
+            println!("No block registry in {}: {}", reports, err);
+            BlockRegistry::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_default_to_the_vanilla_port() {
+        assert_eq!(
+            resolve_address("127.0.0.1").unwrap(),
+            "127.0.0.1:25565".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_address("127.0.0.1:1234").unwrap(),
+            "127.0.0.1:1234".parse().unwrap()
+        );
+        assert!(resolve_address("127.0.0.1:port").is_err());
+    }
 }
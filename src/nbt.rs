@@ -0,0 +1,1194 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use err_derive::Error;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, SerializeMap, SerializeSeq};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// Compounds nested deeper than this are rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 512;
+
+pub type NbtCompound = BTreeMap<String, NbtTag>;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum NbtTag {
+    #[default]
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<NbtTag>),
+    Compound(NbtCompound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl NbtTag {
+    pub fn id(&self) -> u8 {
+        match self {
+            NbtTag::End => 0,
+            NbtTag::Byte(_) => 1,
+            NbtTag::Short(_) => 2,
+            NbtTag::Int(_) => 3,
+            NbtTag::Long(_) => 4,
+            NbtTag::Float(_) => 5,
+            NbtTag::Double(_) => 6,
+            NbtTag::ByteArray(_) => 7,
+            NbtTag::String(_) => 8,
+            NbtTag::List(_) => 9,
+            NbtTag::Compound(_) => 10,
+            NbtTag::IntArray(_) => 11,
+            NbtTag::LongArray(_) => 12,
+        }
+    }
+
+    /// Looks up a key of a compound
+    pub fn get(&self, key: &str) -> Option<&NbtTag> {
+        match self {
+            NbtTag::Compound(compound) => compound.get(key),
+            _ => None,
+        }
+    }
+
+    /// Any integer tag widened to i64
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            NbtTag::Byte(v) => Some(*v as i64),
+            NbtTag::Short(v) => Some(*v as i64),
+            NbtTag::Int(v) => Some(*v as i64),
+            NbtTag::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            NbtTag::Float(v) => Some(*v as f64),
+            NbtTag::Double(v) => Some(*v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            NbtTag::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[NbtTag]> {
+        match self {
+            NbtTag::List(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&NbtCompound> {
+        match self {
+            NbtTag::Compound(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a root tag: type id, name and payload. A lone TAG_End (no data) is returned as `End`
+pub fn read_named<R: Read>(buf: &mut R) -> io::Result<(String, NbtTag)> {
+    let id = buf.read_u8()?;
+    if id == 0 {
+        return Ok((String::new(), NbtTag::End));
+    }
+    let name = read_string(buf)?;
+    let tag = read_payload(buf, id, 0)?;
+    Ok((name, tag))
+}
+
+pub fn write_named<W: Write>(buf: &mut W, name: &str, tag: &NbtTag) -> io::Result<()> {
+    buf.write_u8(tag.id())?;
+    if let NbtTag::End = tag {
+        return Ok(());
+    }
+    write_string(buf, name)?;
+    write_payload(buf, tag)
+}
+
+/// Reads a gzip or zlib compressed root tag (e.g. level.dat), plain NBT is read as is
+pub fn read_compressed<R: Read>(buf: &mut R) -> io::Result<(String, NbtTag)> {
+    let mut data = Vec::new();
+    buf.read_to_end(&mut data)?;
+    match data.get(..2) {
+        Some([0x1F, 0x8B]) => read_named(&mut GzDecoder::new(&data[..])),
+        Some([0x78, _]) => read_named(&mut ZlibDecoder::new(&data[..])),
+        _ => read_named(&mut &data[..]),
+    }
+}
+
+pub fn write_gzip<W: Write>(buf: &mut W, name: &str, tag: &NbtTag) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(buf, Compression::default());
+    write_named(&mut encoder, name, tag)?;
+    encoder.finish().map(drop)
+}
+
+pub fn write_zlib<W: Write>(buf: &mut W, name: &str, tag: &NbtTag) -> io::Result<()> {
+    let mut encoder = ZlibEncoder::new(buf, Compression::default());
+    write_named(&mut encoder, name, tag)?;
+    encoder.finish().map(drop)
+}
+
+fn read_payload<R: Read>(buf: &mut R, id: u8, depth: usize) -> io::Result<NbtTag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("NBT nested too deep"));
+    }
+    Ok(match id {
+        0 => NbtTag::End,
+        1 => NbtTag::Byte(buf.read_i8()?),
+        2 => NbtTag::Short(buf.read_i16::<BigEndian>()?),
+        3 => NbtTag::Int(buf.read_i32::<BigEndian>()?),
+        4 => NbtTag::Long(buf.read_i64::<BigEndian>()?),
+        5 => NbtTag::Float(buf.read_f32::<BigEndian>()?),
+        6 => NbtTag::Double(buf.read_f64::<BigEndian>()?),
+        7 => {
+            let bytes = read_array(buf, 1)?;
+            NbtTag::ByteArray(bytes.iter().map(|byte| *byte as i8).collect())
+        }
+        8 => NbtTag::String(read_string(buf)?),
+        9 => {
+            let element_id = buf.read_u8()?;
+            let len = read_length(buf)?;
+            // every other element takes at least a byte, so the data bounds the length
+            if element_id == 0 && len > 0 {
+                return Err(invalid_data("NBT list of TAG_End with elements"));
+            }
+            let mut list = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                list.push(read_payload(buf, element_id, depth + 1)?);
+            }
+            NbtTag::List(list)
+        }
+        10 => {
+            let mut compound = NbtCompound::new();
+            loop {
+                let id = buf.read_u8()?;
+                if id == 0 {
+                    break;
+                }
+                let name = read_string(buf)?;
+                compound.insert(name, read_payload(buf, id, depth + 1)?);
+            }
+            NbtTag::Compound(compound)
+        }
+        11 => {
+            let bytes = read_array(buf, 4)?;
+            let mut ints = vec![0i32; bytes.len() / 4];
+            (&bytes[..]).read_i32_into::<BigEndian>(&mut ints)?;
+            NbtTag::IntArray(ints)
+        }
+        12 => {
+            let bytes = read_array(buf, 8)?;
+            let mut longs = vec![0i64; bytes.len() / 8];
+            (&bytes[..]).read_i64_into::<BigEndian>(&mut longs)?;
+            NbtTag::LongArray(longs)
+        }
+        id => return Err(invalid_data(&format!("invalid NBT tag type {}", id))),
+    })
+}
+
+fn write_payload<W: Write>(buf: &mut W, tag: &NbtTag) -> io::Result<()> {
+    match tag {
+        NbtTag::End => {}
+        NbtTag::Byte(v) => buf.write_i8(*v)?,
+        NbtTag::Short(v) => buf.write_i16::<BigEndian>(*v)?,
+        NbtTag::Int(v) => buf.write_i32::<BigEndian>(*v)?,
+        NbtTag::Long(v) => buf.write_i64::<BigEndian>(*v)?,
+        NbtTag::Float(v) => buf.write_f32::<BigEndian>(*v)?,
+        NbtTag::Double(v) => buf.write_f64::<BigEndian>(*v)?,
+        NbtTag::ByteArray(v) => {
+            buf.write_i32::<BigEndian>(v.len() as i32)?;
+            for byte in v {
+                buf.write_i8(*byte)?;
+            }
+        }
+        NbtTag::String(v) => write_string(buf, v)?,
+        NbtTag::List(v) => {
+            let element_id = v.first().map(NbtTag::id).unwrap_or(0);
+            if v.iter().any(|element| element.id() != element_id) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "NBT list elements must all have the same type",
+                ));
+            }
+            buf.write_u8(element_id)?;
+            buf.write_i32::<BigEndian>(v.len() as i32)?;
+            for element in v {
+                write_payload(buf, element)?;
+            }
+        }
+        NbtTag::Compound(v) => {
+            for (name, element) in v {
+                write_named(buf, name, element)?;
+            }
+            buf.write_u8(0)?;
+        }
+        NbtTag::IntArray(v) => {
+            buf.write_i32::<BigEndian>(v.len() as i32)?;
+            for int in v {
+                buf.write_i32::<BigEndian>(*int)?;
+            }
+        }
+        NbtTag::LongArray(v) => {
+            buf.write_i32::<BigEndian>(v.len() as i32)?;
+            for long in v {
+                buf.write_i64::<BigEndian>(*long)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_length<R: Read>(buf: &mut R) -> io::Result<usize> {
+    let len = buf.read_i32::<BigEndian>()?;
+    if len < 0 {
+        return Err(invalid_data("negative NBT length"));
+    }
+    Ok(len as usize)
+}
+
+/// Reads the length and the raw data of an array of `width` byte elements. The data only
+/// grows with the bytes that arrive, a bogus length fails instead of allocating it upfront
+fn read_array<R: Read>(buf: &mut R, width: usize) -> io::Result<Vec<u8>> {
+    let len = read_length(buf)? * width;
+    let mut bytes = Vec::new();
+    buf.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "NBT array longer than the data",
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Strings are Java's "modified UTF-8": NUL is two bytes and
+/// characters outside the BMP are written as two encoded surrogates
+fn read_string<R: Read>(buf: &mut R) -> io::Result<String> {
+    let len = buf.read_u16::<BigEndian>()?;
+    let mut bytes = vec![0u8; len as usize];
+    buf.read_exact(&mut bytes)?;
+    if let Ok(string) = std::str::from_utf8(&bytes) {
+        if !bytes.contains(&0xC0) && !bytes.contains(&0xED) {
+            return Ok(string.to_owned());
+        }
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i] as u16;
+        if byte & 0x80 == 0 {
+            units.push(byte);
+            i += 1;
+        } else if byte & 0xE0 == 0xC0 && i + 1 < bytes.len() {
+            units.push((byte & 0x1F) << 6 | (bytes[i + 1] as u16 & 0x3F));
+            i += 2;
+        } else if byte & 0xF0 == 0xE0 && i + 2 < bytes.len() {
+            units.push(
+                (byte & 0x0F) << 12
+                    | (bytes[i + 1] as u16 & 0x3F) << 6
+                    | (bytes[i + 2] as u16 & 0x3F),
+            );
+            i += 3;
+        } else {
+            return Err(invalid_data("invalid modified UTF-8 in NBT string"));
+        }
+    }
+    Ok(String::from_utf16_lossy(&units))
+}
+
+fn write_string<W: Write>(buf: &mut W, string: &str) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(string.len());
+    for unit in string.encode_utf16() {
+        match unit {
+            0x01..=0x7F => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    if bytes.len() > u16::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "NBT string too long",
+        ));
+    }
+    buf.write_u16::<BigEndian>(bytes.len() as u16)?;
+    buf.write_all(&bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/////////// serde
+
+#[derive(Debug, Error)]
+pub enum NbtError {
+    #[error(display = "{}", 0)]
+    Message(String),
+    #[error(display = "map keys must be strings in NBT")]
+    KeyMustBeString,
+    #[error(display = "value does not fit into NBT: {}", 0)]
+    Unsupported(&'static str),
+}
+
+impl ser::Error for NbtError {
+    fn custom<T: fmt::Display>(msg: T) -> NbtError {
+        NbtError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for NbtError {
+    fn custom<T: fmt::Display>(msg: T) -> NbtError {
+        NbtError::Message(msg.to_string())
+    }
+}
+
+/// Newtype names that carry the array tags through serde, which only knows sequences
+const BYTE_ARRAY_TOKEN: &str = "__nbt_byte_array";
+const INT_ARRAY_TOKEN: &str = "__nbt_int_array";
+const LONG_ARRAY_TOKEN: &str = "__nbt_long_array";
+/// Asks a tag for its arrays as `{token: [elements]}`, so they come back as arrays
+const TAG_TOKEN: &str = "__nbt_tag";
+
+/// Turns the list of an array token back into that array, other tags pass through
+fn into_array(token: &str, tag: NbtTag) -> Result<NbtTag, NbtError> {
+    let list = match tag {
+        NbtTag::List(list) => list,
+        tag => return Ok(tag),
+    };
+    let mixed = || NbtError::Unsupported("array elements of another type");
+    match token {
+        BYTE_ARRAY_TOKEN => list
+            .into_iter()
+            .map(|tag| match tag {
+                NbtTag::Byte(v) => Ok(v),
+                _ => Err(mixed()),
+            })
+            .collect::<Result<_, _>>()
+            .map(NbtTag::ByteArray),
+        INT_ARRAY_TOKEN => list
+            .into_iter()
+            .map(|tag| match tag {
+                NbtTag::Int(v) => Ok(v),
+                _ => Err(mixed()),
+            })
+            .collect::<Result<_, _>>()
+            .map(NbtTag::IntArray),
+        LONG_ARRAY_TOKEN => list
+            .into_iter()
+            .map(|tag| match tag {
+                NbtTag::Long(v) => Ok(v),
+                _ => Err(mixed()),
+            })
+            .collect::<Result<_, _>>()
+            .map(NbtTag::LongArray),
+        _ => Ok(NbtTag::List(list)),
+    }
+}
+
+/// Turns any serializable value into a tag; `None` fields are left out of compounds
+pub fn to_nbt<T: Serialize>(value: &T) -> Result<NbtTag, NbtError> {
+    value.serialize(TagSerializer)
+}
+
+pub fn from_nbt<T: DeserializeOwned>(tag: NbtTag) -> Result<T, NbtError> {
+    T::deserialize(tag)
+}
+
+impl Serialize for NbtTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            NbtTag::End => serializer.serialize_unit(),
+            NbtTag::Byte(v) => serializer.serialize_i8(*v),
+            NbtTag::Short(v) => serializer.serialize_i16(*v),
+            NbtTag::Int(v) => serializer.serialize_i32(*v),
+            NbtTag::Long(v) => serializer.serialize_i64(*v),
+            NbtTag::Float(v) => serializer.serialize_f32(*v),
+            NbtTag::Double(v) => serializer.serialize_f64(*v),
+            NbtTag::ByteArray(v) => serializer.serialize_newtype_struct(BYTE_ARRAY_TOKEN, v),
+            NbtTag::String(v) => serializer.serialize_str(v),
+            NbtTag::List(v) => v.serialize(serializer),
+            NbtTag::Compound(v) => v.serialize(serializer),
+            NbtTag::IntArray(v) => serializer.serialize_newtype_struct(INT_ARRAY_TOKEN, v),
+            NbtTag::LongArray(v) => serializer.serialize_newtype_struct(LONG_ARRAY_TOKEN, v),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NbtTag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NbtTag, D::Error> {
+        deserializer.deserialize_newtype_struct(TAG_TOKEN, TagVisitor)
+    }
+}
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = NbtTag;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a value representable as NBT")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<NbtTag, E> {
+        Ok(NbtTag::Byte(v as i8))
+    }
+
+    fn visit_i8<E: de::Error>(self, v: i8) -> Result<NbtTag, E> {
+        Ok(NbtTag::Byte(v))
+    }
+
+    fn visit_i16<E: de::Error>(self, v: i16) -> Result<NbtTag, E> {
+        Ok(NbtTag::Short(v))
+    }
+
+    fn visit_i32<E: de::Error>(self, v: i32) -> Result<NbtTag, E> {
+        Ok(NbtTag::Int(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<NbtTag, E> {
+        Ok(NbtTag::Long(v))
+    }
+
+    fn visit_u8<E: de::Error>(self, v: u8) -> Result<NbtTag, E> {
+        Ok(NbtTag::Short(v as i16))
+    }
+
+    fn visit_u16<E: de::Error>(self, v: u16) -> Result<NbtTag, E> {
+        Ok(NbtTag::Int(v as i32))
+    }
+
+    fn visit_u32<E: de::Error>(self, v: u32) -> Result<NbtTag, E> {
+        Ok(NbtTag::Long(v as i64))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<NbtTag, E> {
+        Ok(NbtTag::Long(v as i64))
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<NbtTag, E> {
+        Ok(NbtTag::Float(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<NbtTag, E> {
+        Ok(NbtTag::Double(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<NbtTag, E> {
+        Ok(NbtTag::String(v.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<NbtTag, E> {
+        Ok(NbtTag::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<NbtTag, E> {
+        Ok(NbtTag::ByteArray(v.iter().map(|b| *b as i8).collect()))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<NbtTag, E> {
+        Ok(NbtTag::End)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<NbtTag, D::Error> {
+        deserializer.deserialize_any(TagVisitor)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<NbtTag, E> {
+        Ok(NbtTag::End)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<NbtTag, D::Error> {
+        deserializer.deserialize_any(TagVisitor)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<NbtTag, A::Error> {
+        let mut list = Vec::new();
+        while let Some(element) = seq.next_element()? {
+            list.push(element);
+        }
+        Ok(NbtTag::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<NbtTag, A::Error> {
+        let mut compound = NbtCompound::new();
+        while let Some((key, value)) = map.next_entry()? {
+            compound.insert(key, value);
+        }
+        if compound.len() == 1 {
+            let (key, value) = compound.iter().next().unwrap();
+            if [BYTE_ARRAY_TOKEN, INT_ARRAY_TOKEN, LONG_ARRAY_TOKEN].contains(&key.as_str()) {
+                return into_array(key, value.clone()).map_err(de::Error::custom);
+            }
+        }
+        Ok(NbtTag::Compound(compound))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, NbtError> for NbtTag {
+    type Deserializer = NbtTag;
+
+    fn into_deserializer(self) -> NbtTag {
+        self
+    }
+}
+
+/// Deserializes Rust values straight out of a tag
+impl<'de> Deserializer<'de> for NbtTag {
+    type Error = NbtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self {
+            NbtTag::End => visitor.visit_unit(),
+            NbtTag::Byte(v) => visitor.visit_i8(v),
+            NbtTag::Short(v) => visitor.visit_i16(v),
+            NbtTag::Int(v) => visitor.visit_i32(v),
+            NbtTag::Long(v) => visitor.visit_i64(v),
+            NbtTag::Float(v) => visitor.visit_f32(v),
+            NbtTag::Double(v) => visitor.visit_f64(v),
+            NbtTag::ByteArray(v) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter()))
+            }
+            NbtTag::String(v) => visitor.visit_string(v),
+            NbtTag::List(v) => visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter())),
+            NbtTag::Compound(v) => {
+                visitor.visit_map(de::value::MapDeserializer::new(v.into_iter()))
+            }
+            NbtTag::IntArray(v) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter()))
+            }
+            NbtTag::LongArray(v) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter()))
+            }
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self {
+            NbtTag::Byte(v) => visitor.visit_bool(v != 0),
+            tag => tag.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NbtError> {
+        match self {
+            NbtTag::End => visitor.visit_none(),
+            tag => visitor.visit_some(tag),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        if name != TAG_TOKEN {
+            return visitor.visit_newtype_struct(self);
+        }
+        let (token, list) = match self {
+            NbtTag::ByteArray(v) => (BYTE_ARRAY_TOKEN, v.into_iter().map(NbtTag::Byte).collect()),
+            NbtTag::IntArray(v) => (INT_ARRAY_TOKEN, v.into_iter().map(NbtTag::Int).collect()),
+            NbtTag::LongArray(v) => (LONG_ARRAY_TOKEN, v.into_iter().map(NbtTag::Long).collect()),
+            tag => return tag.deserialize_any(visitor),
+        };
+        let entry = std::iter::once((token, NbtTag::List(list)));
+        visitor.visit_map(de::value::MapDeserializer::new(entry))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        match self {
+            NbtTag::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            NbtTag::Compound(compound) if compound.len() == 1 => {
+                let (variant, value) = compound.into_iter().next().unwrap();
+                visitor.visit_enum(TagEnum { variant, value })
+            }
+            _ => Err(NbtError::Unsupported(
+                "enums need a string or a compound with one entry",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct TagEnum {
+    variant: String,
+    value: NbtTag,
+}
+
+impl<'de> EnumAccess<'de> for TagEnum {
+    type Error = NbtError;
+    type Variant = NbtTag;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, NbtTag), NbtError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for NbtTag {
+    type Error = NbtError;
+
+    fn unit_variant(self) -> Result<(), NbtError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, NbtError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, NbtError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, NbtError> {
+        self.deserialize_any(visitor)
+    }
+}
+
+/// Serializes Rust values into tags
+struct TagSerializer;
+
+/// Collects the elements of sequences and tuples into a list
+struct ListSerializer {
+    list: Vec<NbtTag>,
+    variant: Option<&'static str>,
+}
+
+/// Collects the entries of maps and structs into a compound
+struct CompoundSerializer {
+    compound: NbtCompound,
+    next_key: Option<String>,
+    variant: Option<&'static str>,
+}
+
+/// Wraps `tag` as `{variant: tag}` for enum variants with data
+fn wrap_variant(variant: Option<&'static str>, tag: NbtTag) -> NbtTag {
+    match variant {
+        Some(variant) => {
+            let mut compound = NbtCompound::new();
+            compound.insert(variant.to_owned(), tag);
+            NbtTag::Compound(compound)
+        }
+        None => tag,
+    }
+}
+
+impl Serializer for TagSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = ListSerializer;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = CompoundSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Byte(v as i8))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Byte(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Short(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Int(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Long(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Short(v as i16))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Int(v as i32))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Long(v as i64))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<NbtTag, NbtError> {
+        if v > i64::MAX as u64 {
+            return Err(NbtError::Unsupported("u64 larger than i64::MAX"));
+        }
+        Ok(NbtTag::Long(v as i64))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Float(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Double(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::ByteArray(v.iter().map(|b| *b as i8).collect()))
+    }
+
+    fn serialize_none(self) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::End)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<NbtTag, NbtError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::End)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::Compound(NbtCompound::new()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<NbtTag, NbtError> {
+        Ok(NbtTag::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<NbtTag, NbtError> {
+        into_array(name, value.serialize(self)?)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<NbtTag, NbtError> {
+        Ok(wrap_variant(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ListSerializer, NbtError> {
+        Ok(ListSerializer {
+            list: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ListSerializer, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<ListSerializer, NbtError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<ListSerializer, NbtError> {
+        Ok(ListSerializer {
+            list: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<CompoundSerializer, NbtError> {
+        Ok(CompoundSerializer {
+            compound: NbtCompound::new(),
+            next_key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<CompoundSerializer, NbtError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<CompoundSerializer, NbtError> {
+        Ok(CompoundSerializer {
+            compound: NbtCompound::new(),
+            next_key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+impl SerializeSeq for ListSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), NbtError> {
+        self.list.push(value.serialize(TagSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        Ok(wrap_variant(self.variant, NbtTag::List(self.list)))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), NbtError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), NbtError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for ListSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), NbtError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeMap for CompoundSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), NbtError> {
+        match key.serialize(TagSerializer)? {
+            NbtTag::String(key) => {
+                self.next_key = Some(key);
+                Ok(())
+            }
+            _ => Err(NbtError::KeyMustBeString),
+        }
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), NbtError> {
+        let key = self.next_key.take().ok_or(NbtError::KeyMustBeString)?;
+        match value.serialize(TagSerializer)? {
+            // None and () can't be stored, leave the entry out
+            NbtTag::End => {}
+            tag => {
+                self.compound.insert(key, tag);
+            }
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        Ok(wrap_variant(self.variant, NbtTag::Compound(self.compound)))
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), NbtError> {
+        SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for CompoundSerializer {
+    type Ok = NbtTag;
+    type Error = NbtError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), NbtError> {
+        SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<NbtTag, NbtError> {
+        SerializeMap::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A compound holding one of every tag type
+    fn every_tag() -> NbtTag {
+        let mut nested = NbtCompound::new();
+        nested.insert("name".to_owned(), NbtTag::String("Bananrama".to_owned()));
+        let mut root = NbtCompound::new();
+        root.insert("byte".to_owned(), NbtTag::Byte(-128));
+        root.insert("short".to_owned(), NbtTag::Short(32767));
+        root.insert("int".to_owned(), NbtTag::Int(-2_147_483_648));
+        root.insert("long".to_owned(), NbtTag::Long(9_223_372_036_854_775_807));
+        root.insert("float".to_owned(), NbtTag::Float(0.498_231_47));
+        root.insert("double".to_owned(), NbtTag::Double(0.493_128_713_218_231_5));
+        root.insert("bytes".to_owned(), NbtTag::ByteArray(vec![0, -1, 127]));
+        root.insert(
+            "string".to_owned(),
+            NbtTag::String("HELLO WORLD".to_owned()),
+        );
+        root.insert(
+            "list".to_owned(),
+            NbtTag::List(vec![NbtTag::Long(11), NbtTag::Long(12)]),
+        );
+        root.insert("empty list".to_owned(), NbtTag::List(Vec::new()));
+        root.insert("compound".to_owned(), NbtTag::Compound(nested));
+        root.insert(
+            "ints".to_owned(),
+            NbtTag::IntArray(vec![i32::MIN, 0, i32::MAX]),
+        );
+        root.insert(
+            "longs".to_owned(),
+            NbtTag::LongArray(vec![i64::MIN, i64::MAX]),
+        );
+        NbtTag::Compound(root)
+    }
+
+    fn write(tag: &NbtTag) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_named(&mut buf, "root", tag).unwrap();
+        buf
+    }
+
+    #[test]
+    fn every_tag_roundtrips() {
+        let tag = every_tag();
+        let (name, read) = read_named(&mut &write(&tag)[..]).unwrap();
+        assert_eq!(name, "root");
+        assert_eq!(read, tag);
+    }
+
+    #[test]
+    fn end_is_a_single_byte() {
+        assert_eq!(write(&NbtTag::End), vec![0]);
+        assert_eq!(read_named(&mut &[0u8][..]).unwrap().1, NbtTag::End);
+    }
+
+    #[test]
+    fn compressed_roundtrips() {
+        let tag = every_tag();
+        let mut gzip = Vec::new();
+        write_gzip(&mut gzip, "root", &tag).unwrap();
+        assert_eq!(&gzip[..2], &[0x1F, 0x8B]);
+        assert_eq!(read_compressed(&mut &gzip[..]).unwrap().1, tag);
+
+        let mut zlib = Vec::new();
+        write_zlib(&mut zlib, "root", &tag).unwrap();
+        assert_eq!(zlib[0], 0x78);
+        assert_eq!(read_compressed(&mut &zlib[..]).unwrap().1, tag);
+
+        assert_eq!(read_compressed(&mut &write(&tag)[..]).unwrap().1, tag);
+    }
+
+    #[test]
+    fn modified_utf8() {
+        let mut buf = Vec::new();
+        write_string(&mut buf, "a\0é€😀").unwrap();
+        assert_eq!(
+            buf,
+            vec![
+                0, 14, b'a', 0xC0, 0x80, 0xC3, 0xA9, 0xE2, 0x82, 0xAC, 0xED, 0xA0, 0xBD, 0xED,
+                0xB8, 0x80
+            ]
+        );
+        assert_eq!(read_string(&mut &buf[..]).unwrap(), "a\0é€😀");
+        // plain UTF-8 is read without conversion
+        assert_eq!(read_string(&mut &[0, 2, 0xC3, 0xA9][..]).unwrap(), "é");
+        assert!(read_string(&mut &[0, 1, 0xFF][..]).is_err());
+    }
+
+    #[test]
+    fn hostile_lengths_fail_without_allocating() {
+        // TAG_Long_Array, empty name, i32::MAX elements and a single long
+        let mut buf = vec![12, 0, 0, 0x7F, 0xFF, 0xFF, 0xFF];
+        buf.extend_from_slice(&[0; 8]);
+        let err = read_named(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let negative = [7, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+        let err = read_named(&mut &negative[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a list of i32::MAX TAG_Ends takes no data at all
+        let ends = [9, 0, 0, 0, 0x7F, 0xFF, 0xFF, 0xFF];
+        let err = read_named(&mut &ends[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let mut buf = vec![9, 0, 0];
+        for _ in 0..=MAX_DEPTH {
+            buf.extend_from_slice(&[9, 0, 0, 0, 1]);
+        }
+        assert!(read_named(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn mixed_lists_are_not_written() {
+        let list = NbtTag::List(vec![NbtTag::Byte(1), NbtTag::Int(1)]);
+        assert!(write_named(&mut Vec::new(), "", &list).is_err());
+    }
+
+    #[test]
+    fn tags_roundtrip_through_serde() {
+        let tag = every_tag();
+        assert_eq!(from_nbt::<NbtTag>(to_nbt(&tag).unwrap()).unwrap(), tag);
+        assert_eq!(
+            to_nbt(&NbtTag::IntArray(Vec::new())).unwrap(),
+            NbtTag::IntArray(Vec::new())
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Kind {
+        Plain,
+        Sized(i32),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Item {
+        id: String,
+        count: i8,
+        damage: Option<i16>,
+        lore: Vec<String>,
+        kind: Kind,
+        // NBT lists hold one type, unit variants are strings and the others compounds
+        sizes: Vec<Kind>,
+        enchanted: bool,
+    }
+
+    #[test]
+    fn structs_roundtrip_through_serde() {
+        let item = Item {
+            id: "minecraft:diamond_pickaxe".to_owned(),
+            count: 1,
+            damage: None,
+            lore: vec!["sharp".to_owned()],
+            kind: Kind::Plain,
+            sizes: vec![Kind::Sized(3), Kind::Sized(5)],
+            enchanted: true,
+        };
+        let tag = to_nbt(&item).unwrap();
+        assert_eq!(tag.get("count"), Some(&NbtTag::Byte(1)));
+        assert_eq!(tag.get("damage"), None);
+        assert_eq!(tag.get("enchanted"), Some(&NbtTag::Byte(1)));
+        let (_, read) = read_named(&mut &write(&tag)[..]).unwrap();
+        assert_eq!(from_nbt::<Item>(read).unwrap(), item);
+    }
+
+    #[test]
+    fn array_tags_read_into_vecs() {
+        let mut compound = NbtCompound::new();
+        compound.insert("ints".to_owned(), NbtTag::IntArray(vec![1, 2]));
+        let ints: BTreeMap<String, Vec<i32>> = from_nbt(NbtTag::Compound(compound)).unwrap();
+        assert_eq!(ints["ints"], vec![1, 2]);
+    }
+}
@@ -1,4 +1,4 @@
-use mc_varint::{VarIntRead, VarIntWrite};
+use mc_varint::VarIntWrite;
 
 pub fn echat(message: &str) -> Vec<u8> {
     let mut buf: Vec<u8> = Vec::new();
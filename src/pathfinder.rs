@@ -0,0 +1,783 @@
+use crate::block::BlockClass;
+use crate::digging;
+use crate::entity::Entities;
+use crate::game::Entity;
+use crate::physics::{self, Controls, Physics};
+use crate::protocol::Position;
+use crate::world::{BlockChange, World};
+use crossbeam_channel::Receiver;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// Highest fall that doesn't hurt
+const SAFE_DROP: i32 = 3;
+/// Falls into water can be deeper
+const WATER_DROP: i32 = 16;
+const MAX_NODES: usize = 10_000;
+const WALK_COST: f64 = 1.0;
+const DIAGONAL_COST: f64 = std::f64::consts::SQRT_2;
+const JUMP_COST: f64 = 2.0;
+const DROP_COST: f64 = 0.5;
+const SWIM_COST: f64 = 2.0;
+const BREAK_COST: f64 = 4.0;
+const PLACE_COST: f64 = 4.0;
+/// How close the bot has to get to the center of a step
+const STEP_REACHED: f64 = 0.35;
+/// Without finishing a step for this long the path is planned again
+const STUCK_TICKS: u32 = 60;
+/// A followed entity has to move this far before the path is planned again
+const FOLLOW_REPLAN_DISTANCE: f64 = 2.0;
+
+/// Where the bot should go, positions are those of the feet
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Goal {
+    Block(Position),
+    /// Anywhere within the distance of a block
+    Near(Position, f64),
+    /// Any height of a column
+    XZ(i32, i32),
+    /// Stay within the distance of an entity, by id
+    Follow(i32, f64),
+}
+
+impl Goal {
+    /// Follow goals turn into `Near` the entity's current block, `None` once it's gone
+    fn resolve(&self, entities: &Entities) -> Option<Goal> {
+        match *self {
+            Goal::Follow(id, distance) => {
+                let entity = entities.get(id)?;
+                let position = Position::new(
+                    entity.x.floor() as i32,
+                    entity.y.floor() as i32,
+                    entity.z.floor() as i32,
+                );
+                Some(Goal::Near(position, distance))
+            }
+            goal => Some(goal),
+        }
+    }
+
+    fn reached(&self, node: Position) -> bool {
+        match *self {
+            Goal::Block(position) => node == position,
+            Goal::Near(position, distance) => block_distance(node, position) <= distance,
+            Goal::XZ(x, z) => node.x == x && node.z == z,
+            Goal::Follow(..) => false,
+        }
+    }
+
+    fn heuristic(&self, node: Position) -> f64 {
+        match *self {
+            Goal::Block(position) => block_distance(node, position),
+            Goal::Near(position, distance) => (block_distance(node, position) - distance).max(0.0),
+            Goal::XZ(x, z) => {
+                (((node.x - x) as f64).powi(2) + ((node.z - z) as f64).powi(2)).sqrt()
+            }
+            Goal::Follow(..) => 0.0,
+        }
+    }
+}
+
+fn block_distance(a: Position, b: Position) -> f64 {
+    (((a.x - b.x) as f64).powi(2) + ((a.y - b.y) as f64).powi(2) + ((a.z - b.z) as f64).powi(2))
+        .sqrt()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Move {
+    Walk,
+    /// Up one block
+    Jump,
+    /// Off an edge, onto a lower block
+    Drop,
+    Swim,
+}
+
+/// One block of a path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Where the feet end up
+    pub position: Position,
+    pub kind: Move,
+    /// Blocks to mine before the step can be taken
+    pub breaks: Vec<Position>,
+    /// A block to place to walk over a gap
+    pub place: Option<Position>,
+}
+
+impl Step {
+    fn new(position: Position, kind: Move) -> Step {
+        Step {
+            position,
+            kind,
+            breaks: Vec::new(),
+            place: None,
+        }
+    }
+
+    /// Whether a changed block lies in the way: the floor, the feet, the head or the room
+    /// needed to jump. Planned breaks and placements are expected
+    fn affected_by(&self, change: &BlockChange, world: &World) -> bool {
+        let position = change.position;
+        if self.breaks.contains(&position) && passable(world, position) {
+            return false;
+        }
+        if self.place == Some(position) && solid_floor(world, position) {
+            return false;
+        }
+        position.x == self.position.x
+            && position.z == self.position.z
+            && position.y >= self.position.y - 1
+            && position.y <= self.position.y + 2
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PathOptions {
+    /// Highest drop onto solid ground
+    pub max_drop: i32,
+    pub allow_break: bool,
+    pub allow_place: bool,
+    /// Nodes to search before settling for the closest one found
+    pub max_nodes: usize,
+}
+
+impl Default for PathOptions {
+    fn default() -> PathOptions {
+        PathOptions {
+            max_drop: SAFE_DROP,
+            allow_break: false,
+            allow_place: false,
+            max_nodes: MAX_NODES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    pub steps: Vec<Step>,
+    /// False if the path only leads as close as the search got
+    pub complete: bool,
+}
+
+/// An open node, ordered so the heap pops the lowest estimate first
+struct Open {
+    estimate: f64,
+    node: Position,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Open) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Open) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// A* over the loaded blocks from `start`, the block of the feet. Unloaded chunks are
+/// walls, so far goals get a partial path towards them
+pub fn find_path(
+    world: &World,
+    start: Position,
+    goal: &Goal,
+    options: &PathOptions,
+) -> Option<Path> {
+    let mut open = BinaryHeap::new();
+    let mut costs: HashMap<Position, f64> = HashMap::new();
+    let mut came_from: HashMap<Position, (Position, Step)> = HashMap::new();
+    let mut best = (goal.heuristic(start), start);
+
+    costs.insert(start, 0.0);
+    open.push(Open {
+        estimate: goal.heuristic(start),
+        node: start,
+    });
+    let mut expanded = 0;
+    let mut complete = false;
+    while let Some(Open { node, .. }) = open.pop() {
+        if goal.reached(node) {
+            best = (0.0, node);
+            complete = true;
+            break;
+        }
+        expanded += 1;
+        if expanded > options.max_nodes {
+            break;
+        }
+        let cost = costs[&node];
+        for (step, step_cost) in neighbors(world, node, options) {
+            let next = step.position;
+            let next_cost = cost + step_cost;
+            if costs.get(&next).is_some_and(|known| *known <= next_cost) {
+                continue;
+            }
+            let heuristic = goal.heuristic(next);
+            if heuristic < best.0 {
+                best = (heuristic, next);
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, (node, step));
+            open.push(Open {
+                estimate: next_cost + heuristic,
+                node: next,
+            });
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut node = best.1;
+    while let Some((previous, step)) = came_from.remove(&node) {
+        steps.push(step);
+        node = previous;
+    }
+    if steps.is_empty() && !complete {
+        return None;
+    }
+    steps.reverse();
+    Some(Path { steps, complete })
+}
+
+fn neighbors(world: &World, node: Position, options: &PathOptions) -> Vec<(Step, f64)> {
+    let mut steps = Vec::new();
+    let in_water = water(world, node);
+
+    for &(dx, dz) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        let target = node.offset(dx, 0, dz);
+        if standable(world, target) {
+            let (kind, cost) = if water(world, target) {
+                (Move::Swim, SWIM_COST)
+            } else {
+                (Move::Walk, WALK_COST)
+            };
+            steps.push((Step::new(target, kind), cost));
+            continue;
+        }
+
+        let above = target.offset(0, 1, 0);
+        if standable(world, above)
+            && passable(world, node.offset(0, 2, 0))
+            && (in_water || solid_floor(world, node.offset(0, -1, 0)))
+        {
+            steps.push((Step::new(above, Move::Jump), JUMP_COST));
+            continue;
+        }
+
+        if passable(world, target) && passable(world, above) {
+            if let Some(landing) = drop_target(world, target, options.max_drop) {
+                let cost = WALK_COST + DROP_COST * (target.y - landing.y) as f64;
+                steps.push((Step::new(landing, Move::Drop), cost));
+            } else if options.allow_place && passable(world, target.offset(0, -1, 0)) {
+                let mut step = Step::new(target, Move::Walk);
+                step.place = Some(target.offset(0, -1, 0));
+                steps.push((step, WALK_COST + PLACE_COST));
+            }
+            continue;
+        }
+
+        if options.allow_break
+            && solid_floor(world, target.offset(0, -1, 0))
+            && (passable(world, target) || breakable(world, target))
+            && (passable(world, above) || breakable(world, above))
+        {
+            let mut step = Step::new(target, Move::Walk);
+            step.breaks = [target, above]
+                .iter()
+                .copied()
+                .filter(|block| !passable(world, *block))
+                .collect();
+            let cost = WALK_COST + BREAK_COST * step.breaks.len() as f64;
+            steps.push((step, cost));
+        }
+    }
+
+    // diagonals only when both corners are free, so the box doesn't catch on them
+    for &(dx, dz) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+        let target = node.offset(dx, 0, dz);
+        let corners_free = [node.offset(dx, 0, 0), node.offset(0, 0, dz)]
+            .iter()
+            .all(|corner| passable(world, *corner) && passable(world, corner.offset(0, 1, 0)));
+        if corners_free && standable(world, target) && !water(world, target) {
+            steps.push((Step::new(target, Move::Walk), DIAGONAL_COST));
+        }
+    }
+
+    if in_water {
+        let up = node.offset(0, 1, 0);
+        if passable(world, up) && passable(world, up.offset(0, 1, 0)) {
+            steps.push((Step::new(up, Move::Swim), SWIM_COST));
+        }
+        let down = node.offset(0, -1, 0);
+        if water(world, down) {
+            steps.push((Step::new(down, Move::Swim), SWIM_COST));
+        }
+    }
+    steps
+}
+
+/// Where falling down from `start` ends, if it's safe
+fn drop_target(world: &World, start: Position, max_drop: i32) -> Option<Position> {
+    for depth in 1..=max_drop.max(WATER_DROP) {
+        let below = start.offset(0, -depth, 0);
+        if !passable(world, below) {
+            return None;
+        }
+        if standable(world, below) {
+            return if depth <= max_drop || water(world, below) {
+                Some(below)
+            } else {
+                None
+            };
+        }
+    }
+    None
+}
+
+/// Feet and head fit and there is something to stand on, or water to swim in
+fn standable(world: &World, position: Position) -> bool {
+    passable(world, position)
+        && passable(world, position.offset(0, 1, 0))
+        && (water(world, position) || solid_floor(world, position.offset(0, -1, 0)))
+}
+
+/// Nothing to collide with and nothing that hurts. Unloaded blocks aren't passable
+fn passable(world: &World, position: Position) -> bool {
+    let id = match world.block_at(position.x, position.y, position.z) {
+        Some(id) => id,
+        None => return false,
+    };
+    match world.blocks.state(id) {
+        Some(state) => {
+            physics::block_shape(state).is_empty()
+                && !matches!(
+                    state.name.as_str(),
+                    "minecraft:lava"
+                        | "minecraft:fire"
+                        | "minecraft:cobweb"
+                        | "minecraft:sweet_berry_bush"
+                )
+        }
+        None => world.blocks.class(id) != BlockClass::Solid,
+    }
+}
+
+/// Full-height ground, fences and walls are too high to step on
+fn solid_floor(world: &World, position: Position) -> bool {
+    let id = match world.block_at(position.x, position.y, position.z) {
+        Some(id) => id,
+        None => return false,
+    };
+    match world.blocks.state(id) {
+        Some(state) => {
+            let shape = physics::block_shape(state);
+            !shape.is_empty()
+                && !state.is_liquid()
+                && shape.iter().all(|part| part.max_y <= 1.0)
+                && state.name != "minecraft:cactus"
+                && state.name != "minecraft:magma_block"
+        }
+        None => world.blocks.class(id) == BlockClass::Solid,
+    }
+}
+
+fn water(world: &World, position: Position) -> bool {
+    match world.block_state_at(position.x, position.y, position.z) {
+        Some(state) => state.is_liquid() && state.name != "minecraft:lava",
+        None => false,
+    }
+}
+
+/// Mining it neither fails nor floods the path
+fn breakable(world: &World, position: Position) -> bool {
+    let state = match world.block_state_at(position.x, position.y, position.z) {
+        Some(state) => state,
+        None => return false,
+    };
+    let above = world.block_state_at(position.x, position.y + 1, position.z);
+    !state.is_liquid()
+        && digging::hardness(&state.name).is_some()
+        && !above.is_some_and(|above| above.is_liquid())
+}
+
+/// What the navigator needs this tick
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Navigation {
+    /// No goal
+    Idle,
+    Moving,
+    /// The next step needs this block mined first
+    Break(Position),
+    /// The next step needs a block placed here first
+    Place(Position),
+    Arrived,
+    /// No path, the goal was dropped
+    Unreachable,
+}
+
+/// Follows paths to a goal by steering the physics, planning again when blocks on the way
+/// change or the bot gets stuck
+pub struct Navigator {
+    goal: Option<Goal>,
+    pub options: PathOptions,
+    path: VecDeque<Step>,
+    changes: Receiver<BlockChange>,
+    /// Where a followed entity was when the path was planned
+    planned_for: Option<Position>,
+    stuck_ticks: u32,
+}
+
+impl Navigator {
+    pub fn new(world: &mut World) -> Navigator {
+        Navigator {
+            goal: None,
+            options: PathOptions::default(),
+            path: VecDeque::new(),
+            changes: world.subscribe(),
+            planned_for: None,
+            stuck_ticks: 0,
+        }
+    }
+
+    pub fn set_goal(&mut self, goal: Goal) {
+        self.goal = Some(goal);
+        self.path.clear();
+    }
+
+    pub fn goal(&self) -> Option<Goal> {
+        self.goal
+    }
+
+    /// The steps still ahead
+    pub fn path(&self) -> impl Iterator<Item = &Step> {
+        self.path.iter()
+    }
+
+    pub fn stop(&mut self, physics: &mut Physics) {
+        self.goal = None;
+        self.path.clear();
+        physics.release();
+    }
+
+    /// Steers towards the next step, call once per tick before the physics
+    pub fn tick(
+        &mut self,
+        entity: &mut Entity,
+        physics: &mut Physics,
+        world: &World,
+        entities: &Entities,
+    ) -> Navigation {
+        let changes: Vec<BlockChange> = self.changes.try_iter().collect();
+        let goal = match self.goal {
+            Some(goal) => goal,
+            None => return Navigation::Idle,
+        };
+        let target = match goal.resolve(entities) {
+            Some(target) => target,
+            None => {
+                self.stop(physics);
+                return Navigation::Unreachable;
+            }
+        };
+
+        if changes
+            .iter()
+            .any(|change| self.path.iter().any(|step| step.affected_by(change, world)))
+        {
+            self.path.clear();
+        }
+        if let (Goal::Near(position, _), Some(planned_for)) = (target, self.planned_for) {
+            if block_distance(position, planned_for) > FOLLOW_REPLAN_DISTANCE {
+                self.path.clear();
+            }
+        }
+
+        let feet = Position::new(
+            entity.x.floor() as i32,
+            (entity.y + 0.01).floor() as i32,
+            entity.z.floor() as i32,
+        );
+        let settled = physics.onground || water(world, feet);
+        if target.reached(feet) && settled {
+            physics.release();
+            self.path.clear();
+            // following goes on, the entity may walk away again
+            if !matches!(goal, Goal::Follow(..)) {
+                self.goal = None;
+            }
+            return Navigation::Arrived;
+        }
+
+        if self.path.is_empty() {
+            if !settled {
+                return Navigation::Moving;
+            }
+            match find_path(world, feet, &target, &self.options) {
+                Some(path) if !path.steps.is_empty() => {
+                    self.path = path.steps.into();
+                    self.stuck_ticks = 0;
+                    self.planned_for = match target {
+                        Goal::Near(position, _) => Some(position),
+                        _ => None,
+                    };
+                }
+                _ => {
+                    self.stop(physics);
+                    return Navigation::Unreachable;
+                }
+            }
+        }
+
+        self.stuck_ticks += 1;
+        if self.stuck_ticks > STUCK_TICKS {
+            self.path.clear();
+            return Navigation::Moving;
+        }
+
+        let step = &self.path[0];
+        let center_x = step.position.x as f64 + 0.5;
+        let center_z = step.position.z as f64 + 0.5;
+        let (dx, dz) = (center_x - entity.x, center_z - entity.z);
+        let horizontal = (dx * dx + dz * dz).sqrt();
+        if horizontal < STEP_REACHED && (entity.y - step.position.y as f64).abs() < 0.6 && settled {
+            self.path.pop_front();
+            self.stuck_ticks = 0;
+            return Navigation::Moving;
+        }
+
+        // standing still while the block is worked on isn't being stuck
+        if let Some(block) = step.breaks.iter().find(|block| !passable(world, **block)) {
+            physics.release();
+            self.stuck_ticks = 0;
+            return Navigation::Break(*block);
+        }
+        if let Some(block) = step.place.filter(|block| !solid_floor(world, *block)) {
+            physics.release();
+            self.stuck_ticks = 0;
+            return Navigation::Place(block);
+        }
+
+        if horizontal > 0.01 {
+            entity.yaw = (-dx).atan2(dz).to_degrees() as f32;
+        }
+        let climbing = step.position.y as f64 > entity.y + 0.6;
+        let swimming = water(world, feet) && step.position.y >= feet.y;
+        physics.hold(
+            Controls {
+                forward: if horizontal < STEP_REACHED { 0.0 } else { 1.0 },
+                jump: climbing || swimming || (physics.collided_horizontally && physics.onground),
+                ..Controls::default()
+            },
+            0,
+        );
+        Navigation::Moving
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRegistry;
+    use crate::registry::Registry;
+    use crate::world::{ChunkBlockID, ChunkColumn};
+
+    const REPORT: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+        "minecraft:water": {"states": [{"id": 2, "properties": {"level": "0"}, "default": true}]},
+        "minecraft:lava": {"states": [{"id": 3, "properties": {"level": "0"}, "default": true}]}
+    }"#;
+    const STONE: ChunkBlockID = ChunkBlockID(1);
+    const LAVA: ChunkBlockID = ChunkBlockID(3);
+
+    /// Four loaded chunks with a stone floor at y 63, so feet stand at y 64
+    fn flat_world() -> World {
+        let mut world = World::new(BlockRegistry::from_json(REPORT).unwrap());
+        for x in 0..2 {
+            for z in 0..2 {
+                world.columns.insert((x, z), ChunkColumn::new(x, z));
+            }
+        }
+        for x in 0..32 {
+            for z in 0..32 {
+                world.set_block(Position::new(x, 63, z), STONE);
+            }
+        }
+        world
+    }
+
+    /// A wall along x from z 0 to `to_z`, `height` blocks high
+    fn wall(world: &mut World, x: i32, to_z: i32, height: i32) {
+        for z in 0..=to_z {
+            for y in 64..64 + height {
+                world.set_block(Position::new(x, y, z), STONE);
+            }
+        }
+    }
+
+    fn path(world: &World, start: Position, goal: Goal, options: &PathOptions) -> Path {
+        find_path(world, start, &goal, options).unwrap()
+    }
+
+    #[test]
+    fn straight_and_diagonal_paths() {
+        let world = flat_world();
+        let start = Position::new(2, 64, 2);
+        let straight = path(
+            &world,
+            start,
+            Goal::Block(Position::new(8, 64, 2)),
+            &Default::default(),
+        );
+        assert!(straight.complete);
+        assert_eq!(straight.steps.len(), 6);
+        assert!(straight.steps.iter().all(|step| step.kind == Move::Walk));
+        assert_eq!(
+            straight.steps.last().unwrap().position,
+            Position::new(8, 64, 2)
+        );
+
+        let diagonal = path(
+            &world,
+            start,
+            Goal::Block(Position::new(6, 64, 6)),
+            &Default::default(),
+        );
+        assert_eq!(diagonal.steps.len(), 4);
+        assert!(
+            find_path(&world, start, &Goal::Block(start), &Default::default())
+                .unwrap()
+                .steps
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn walls_are_walked_around_or_jumped() {
+        let mut world = flat_world();
+        wall(&mut world, 5, 20, 2);
+        let goal = Goal::Block(Position::new(8, 64, 2));
+        let around = path(&world, Position::new(2, 64, 2), goal, &Default::default());
+        assert!(around.complete);
+        assert!(around.steps.iter().all(|step| step.position.y == 64));
+        assert!(around.steps.iter().any(|step| step.position.z > 20));
+
+        // a single block high wall is jumped over and dropped down from
+        let mut world = flat_world();
+        wall(&mut world, 5, 31, 1);
+        let over = path(&world, Position::new(2, 64, 2), goal, &Default::default());
+        let kinds: Vec<Move> = over.steps.iter().map(|step| step.kind).collect();
+        assert!(kinds.contains(&Move::Jump) && kinds.contains(&Move::Drop));
+        assert!(over
+            .steps
+            .iter()
+            .any(|step| step.position == Position::new(5, 65, 2)));
+    }
+
+    #[test]
+    fn drops_are_limited() {
+        let mut world = flat_world();
+        // a platform 4 blocks up with a pit in front of it
+        for x in 0..4 {
+            for y in 64..68 {
+                world.set_block(Position::new(x, y, 0), STONE);
+            }
+        }
+        let start = Position::new(1, 68, 0);
+        let goal = Goal::Block(Position::new(1, 64, 1));
+        // nowhere on the platform gets closer, so there is no path at all
+        assert!(find_path(&world, start, &goal, &Default::default()).is_none());
+        let deeper = PathOptions {
+            max_drop: 4,
+            ..Default::default()
+        };
+        let down = path(&world, start, goal, &deeper);
+        assert!(down.complete);
+        assert_eq!(down.steps[0].kind, Move::Drop);
+    }
+
+    #[test]
+    fn breaking_through_walls_when_allowed() {
+        let mut world = flat_world();
+        wall(&mut world, 5, 31, 3);
+        let start = Position::new(2, 64, 2);
+        let goal = Goal::Block(Position::new(8, 64, 2));
+        let blocked = path(&world, start, goal, &Default::default());
+        assert!(!blocked.complete);
+        // the partial path leads as close as it got
+        assert_eq!(blocked.steps.last().unwrap().position.x, 4);
+
+        let options = PathOptions {
+            allow_break: true,
+            ..Default::default()
+        };
+        let through = path(&world, start, goal, &options);
+        assert!(through.complete);
+        let breaks: Vec<Position> = through
+            .steps
+            .iter()
+            .flat_map(|step| step.breaks.clone())
+            .collect();
+        assert_eq!(
+            breaks,
+            vec![Position::new(5, 64, 2), Position::new(5, 65, 2)]
+        );
+    }
+
+    #[test]
+    fn slow_breaks_dont_count_as_stuck() {
+        let mut world = flat_world();
+        wall(&mut world, 5, 31, 3);
+        let mut navigator = Navigator::new(&mut world);
+        navigator.options.allow_break = true;
+        navigator.set_goal(Goal::Block(Position::new(8, 64, 2)));
+        let mut entity = Entity {
+            entityid: 1,
+            x: 4.5,
+            y: 64.0,
+            z: 2.5,
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        let mut physics = Physics::new();
+        physics.onground = true;
+        let entities = Entities::new(Registry::default());
+        // the wall takes longer to dig than the bot may stand still
+        for _ in 0..STUCK_TICKS * 2 {
+            assert_eq!(
+                navigator.tick(&mut entity, &mut physics, &world, &entities),
+                Navigation::Break(Position::new(5, 64, 2))
+            );
+        }
+        assert_eq!(navigator.path().count(), 4);
+    }
+
+    #[test]
+    fn lava_and_unloaded_chunks_are_avoided() {
+        let mut world = flat_world();
+        for z in 0..32 {
+            world.set_block(Position::new(5, 64, z), LAVA);
+        }
+        let start = Position::new(2, 64, 2);
+        let across = path(&world, start, Goal::XZ(8, 2), &Default::default());
+        assert!(!across.complete);
+
+        // nothing is loaded beyond x 31, so the path ends at the edge
+        let far = path(&flat_world(), start, Goal::XZ(100, 2), &Default::default());
+        assert!(!far.complete);
+        assert_eq!(far.steps.last().unwrap().position.x, 31);
+    }
+}
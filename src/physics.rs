@@ -0,0 +1,1195 @@
+use crate::block::{BlockClass, BlockState};
+use crate::game::Entity;
+use crate::protocol::Packet;
+use crate::world::World;
+
+pub const PLAYER_WIDTH: f64 = 0.6;
+pub const PLAYER_HEIGHT: f64 = 1.8;
+/// Crouching lowers the hitbox since 1.14
+pub const SNEAKING_HEIGHT: f64 = 1.5;
+pub const EYE_HEIGHT: f64 = 1.62;
+const STEP_HEIGHT: f64 = 0.6;
+
+/// Movement constants of the vanilla 1.14 client, per tick
+const GRAVITY: f64 = 0.08;
+const VERTICAL_DRAG: f64 = 0.98;
+const AIR_FRICTION: f64 = 0.91;
+const DEFAULT_SLIPPERINESS: f64 = 0.6;
+/// Makes ground acceleration equal the movement speed on blocks of default slipperiness
+const GROUND_ACCELERATION: f64 = 0.16277137;
+const WALK_SPEED: f64 = 0.1;
+const SPRINT_MULTIPLIER: f64 = 1.3;
+const SNEAK_MULTIPLIER: f64 = 0.3;
+const AIR_ACCELERATION: f64 = 0.02;
+const SPRINT_AIR_ACCELERATION: f64 = 0.026;
+const INPUT_DAMPING: f64 = 0.98;
+const JUMP_VELOCITY: f64 = 0.42;
+const SPRINT_JUMP_BOOST: f64 = 0.2;
+/// Ticks between two jumps while jump is held
+const JUMP_COOLDOWN: u32 = 10;
+const LIQUID_ACCELERATION: f64 = 0.02;
+const LIQUID_SWIM_UP: f64 = 0.04;
+const WATER_DRAG: f64 = 0.8;
+const SPRINT_WATER_DRAG: f64 = 0.9;
+const LAVA_DRAG: f64 = 0.5;
+/// Jumping out of a liquid onto a block one higher
+const LIQUID_CLIMB_VELOCITY: f64 = 0.3;
+/// Velocities below this are zeroed every tick
+const MIN_VELOCITY: f64 = 0.003;
+/// How far sneaking backs off from an edge per try
+const EDGE_STEP: f64 = 0.05;
+/// Flags of Player Position And Look, set bits mark values relative to the current ones
+const RELATIVE_X: i8 = 0x01;
+const RELATIVE_Y: i8 = 0x02;
+const RELATIVE_Z: i8 = 0x04;
+const RELATIVE_YAW: i8 = 0x08;
+const RELATIVE_PITCH: i8 = 0x10;
+/// Smaller moves aren't reported
+const MIN_MOVEMENT: f64 = 2.0e-4;
+/// The server expects a position at least once a second even when standing still
+const POSITION_RESEND_TICKS: u32 = 20;
+
+/// An axis-aligned bounding box in world coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub min_z: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+    pub max_z: f64,
+}
+
+impl Aabb {
+    pub fn new(min_x: f64, min_y: f64, min_z: f64, max_x: f64, max_y: f64, max_z: f64) -> Aabb {
+        Aabb {
+            min_x,
+            min_y,
+            min_z,
+            max_x,
+            max_y,
+            max_z,
+        }
+    }
+
+    /// The box of an entity standing at `x, y, z`
+    pub fn entity(x: f64, y: f64, z: f64, width: f64, height: f64) -> Aabb {
+        let half = width / 2.0;
+        Aabb::new(x - half, y, z - half, x + half, y + height, z + half)
+    }
+
+    pub fn offset(&self, x: f64, y: f64, z: f64) -> Aabb {
+        Aabb::new(
+            self.min_x + x,
+            self.min_y + y,
+            self.min_z + z,
+            self.max_x + x,
+            self.max_y + y,
+            self.max_z + z,
+        )
+    }
+
+    /// Grows the box in the direction of a movement
+    pub fn expand(&self, x: f64, y: f64, z: f64) -> Aabb {
+        Aabb::new(
+            self.min_x + x.min(0.0),
+            self.min_y + y.min(0.0),
+            self.min_z + z.min(0.0),
+            self.max_x + x.max(0.0),
+            self.max_y + y.max(0.0),
+            self.max_z + z.max(0.0),
+        )
+    }
+
+    pub fn shrink(&self, amount: f64) -> Aabb {
+        Aabb::new(
+            self.min_x + amount,
+            self.min_y + amount,
+            self.min_z + amount,
+            self.max_x - amount,
+            self.max_y - amount,
+            self.max_z - amount,
+        )
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min_x < other.max_x
+            && self.max_x > other.min_x
+            && self.min_y < other.max_y
+            && self.max_y > other.min_y
+            && self.min_z < other.max_z
+            && self.max_z > other.min_z
+    }
+
+    /// Whether the line from `from` to `to` passes through the inside of this box. Touching
+    /// a side or ending on it doesn't count
+    pub fn intersects_segment(&self, from: (f64, f64, f64), to: (f64, f64, f64)) -> bool {
+        let axes = [
+            (from.0, to.0 - from.0, self.min_x, self.max_x),
+            (from.1, to.1 - from.1, self.min_y, self.max_y),
+            (from.2, to.2 - from.2, self.min_z, self.max_z),
+        ];
+        let (mut enter, mut exit) = (0.0f64, 1.0f64);
+        for &(start, delta, min, max) in axes.iter() {
+            if delta.abs() < 1e-9 {
+                if start <= min || start >= max {
+                    return false;
+                }
+                continue;
+            }
+            let (a, b) = ((min - start) / delta, (max - start) / delta);
+            enter = enter.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+        enter < exit - 1e-7
+    }
+
+    /// How far `other` can move by `dx` before it hits this box
+    fn clip_x(&self, other: &Aabb, mut dx: f64) -> f64 {
+        if other.max_y <= self.min_y
+            || other.min_y >= self.max_y
+            || other.max_z <= self.min_z
+            || other.min_z >= self.max_z
+        {
+            return dx;
+        }
+        if dx > 0.0 && other.max_x <= self.min_x {
+            dx = dx.min(self.min_x - other.max_x);
+        } else if dx < 0.0 && other.min_x >= self.max_x {
+            dx = dx.max(self.max_x - other.min_x);
+        }
+        dx
+    }
+
+    fn clip_y(&self, other: &Aabb, mut dy: f64) -> f64 {
+        if other.max_x <= self.min_x
+            || other.min_x >= self.max_x
+            || other.max_z <= self.min_z
+            || other.min_z >= self.max_z
+        {
+            return dy;
+        }
+        if dy > 0.0 && other.max_y <= self.min_y {
+            dy = dy.min(self.min_y - other.max_y);
+        } else if dy < 0.0 && other.min_y >= self.max_y {
+            dy = dy.max(self.max_y - other.min_y);
+        }
+        dy
+    }
+
+    fn clip_z(&self, other: &Aabb, mut dz: f64) -> f64 {
+        if other.max_x <= self.min_x
+            || other.min_x >= self.max_x
+            || other.max_y <= self.min_y
+            || other.min_y >= self.max_y
+        {
+            return dz;
+        }
+        if dz > 0.0 && other.max_z <= self.min_z {
+            dz = dz.min(self.min_z - other.max_z);
+        } else if dz < 0.0 && other.min_z >= self.max_z {
+            dz = dz.max(self.max_z - other.min_z);
+        }
+        dz
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Fluid {
+    Water,
+    Lava,
+}
+
+/// What the player presses this tick
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Controls {
+    /// 1 forward, -1 backward
+    pub forward: f64,
+    /// 1 left, -1 right
+    pub strafe: f64,
+    pub jump: bool,
+    pub sprint: bool,
+    pub sneak: bool,
+}
+
+/// The last movement the server was told about
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SentMovement {
+    x: f64,
+    y: f64,
+    z: f64,
+    yaw: f32,
+    pitch: f32,
+    onground: bool,
+}
+
+/// Simulates the bot's own movement like the vanilla client, one tick at a time
+pub struct Physics {
+    /// Blocks per tick
+    pub velocity: (f64, f64, f64),
+    pub onground: bool,
+    pub collided_horizontally: bool,
+    pub controls: Controls,
+    /// Ticks left until `controls` are released, 0 holds them until changed
+    control_ticks: u32,
+    jump_ticks: u32,
+    /// `None` until the first teleport
+    sent: Option<SentMovement>,
+    sent_sprinting: bool,
+    sent_sneaking: bool,
+    ticks_since_position: u32,
+}
+
+impl Default for Physics {
+    fn default() -> Physics {
+        Physics::new()
+    }
+}
+
+impl Physics {
+    pub fn new() -> Physics {
+        Physics {
+            velocity: (0.0, 0.0, 0.0),
+            onground: false,
+            collided_horizontally: false,
+            controls: Controls::default(),
+            control_ticks: 0,
+            jump_ticks: 0,
+            sent: None,
+            sent_sprinting: false,
+            sent_sneaking: false,
+            ticks_since_position: 0,
+        }
+    }
+
+    /// Presses `controls` for the next `ticks` ticks, or until changed if `ticks` is 0
+    pub fn hold(&mut self, controls: Controls, ticks: u32) {
+        self.controls = controls;
+        self.control_ticks = ticks;
+    }
+
+    pub fn release(&mut self) {
+        self.hold(Controls::default(), 0);
+    }
+
+    /// Applies a Player Position And Look from the server and returns the Teleport Confirm
+    /// and position the vanilla client answers with. Momentum is kept on relative axes only
+    pub fn teleport(&mut self, entity: &mut Entity, packet: &Packet) -> Vec<Packet> {
+        let (x, y, z, yaw, pitch, flags, teleport_id) = match *packet {
+            Packet::ServerPlayerPositionAndLook {
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                flags,
+                teleportid,
+            } => (x, y, z, yaw, pitch, flags, teleportid),
+            _ => return Vec::new(),
+        };
+        let relative = |flag: i8| flags & flag != 0;
+        let (vx, vy, vz) = self.velocity;
+        self.velocity = (
+            if relative(RELATIVE_X) { vx } else { 0.0 },
+            if relative(RELATIVE_Y) { vy } else { 0.0 },
+            if relative(RELATIVE_Z) { vz } else { 0.0 },
+        );
+        entity.x = if relative(RELATIVE_X) {
+            entity.x + x
+        } else {
+            x
+        };
+        entity.y = if relative(RELATIVE_Y) {
+            entity.y + y
+        } else {
+            y
+        };
+        entity.z = if relative(RELATIVE_Z) {
+            entity.z + z
+        } else {
+            z
+        };
+        entity.yaw = if relative(RELATIVE_YAW) {
+            entity.yaw + yaw
+        } else {
+            yaw
+        };
+        entity.pitch = if relative(RELATIVE_PITCH) {
+            entity.pitch + pitch
+        } else {
+            pitch
+        };
+
+        self.sent = Some(SentMovement {
+            x: entity.x,
+            y: entity.y,
+            z: entity.z,
+            yaw: entity.yaw,
+            pitch: entity.pitch,
+            onground: false,
+        });
+        self.ticks_since_position = 0;
+        vec![
+            Packet::ClientTeleportConfirm { teleport_id },
+            Packet::ClientPlayerPositionAndLook {
+                x: entity.x,
+                y: entity.y,
+                z: entity.z,
+                yaw: entity.yaw,
+                pitch: entity.pitch,
+                onground: false,
+            },
+        ]
+    }
+
+    /// Forgets motion and what was sent, after a respawn nothing moves until the server places
+    /// the bot again
+    pub fn clear(&mut self) {
+        *self = Physics::new();
+    }
+
+    /// Whether the server sent our position yet
+    pub fn spawned(&self) -> bool {
+        self.sent.is_some()
+    }
+
+    pub fn sprinting(&self) -> bool {
+        self.controls.sprint
+            && self.controls.forward > 0.0
+            && !self.controls.sneak
+            && !self.collided_horizontally
+    }
+
+    pub fn bounding_box(&self, entity: &Entity) -> Aabb {
+        let height = if self.controls.sneak {
+            SNEAKING_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
+        Aabb::entity(entity.x, entity.y, entity.z, PLAYER_WIDTH, height)
+    }
+
+    /// Moves `entity` by one tick of input, gravity and collision
+    pub fn tick(&mut self, entity: &mut Entity, world: &World) {
+        // nothing to simulate before the server placed us with the first teleport, and the
+        // vanilla client doesn't move in chunks it hasn't received either
+        if !self.spawned()
+            || world
+                .column(
+                    (entity.x.floor() as i32) >> 4,
+                    (entity.z.floor() as i32) >> 4,
+                )
+                .is_none()
+        {
+            return;
+        }
+        let controls = self.controls;
+        if self.control_ticks > 0 {
+            self.control_ticks -= 1;
+            if self.control_ticks == 0 {
+                self.controls = Controls::default();
+            }
+        }
+        if self.jump_ticks > 0 {
+            self.jump_ticks -= 1;
+        }
+
+        let (vx, vy, vz) = self.velocity;
+        let (mut vx, mut vy, mut vz) = (settle(vx), settle(vy), settle(vz));
+
+        let sprinting = self.sprinting();
+        let fluid = fluid_in(world, &self.bounding_box(entity).shrink(0.001));
+        if controls.jump {
+            if fluid.is_some() {
+                vy += LIQUID_SWIM_UP;
+            } else if self.onground && self.jump_ticks == 0 {
+                vy = JUMP_VELOCITY;
+                if sprinting {
+                    let yaw = (entity.yaw as f64).to_radians();
+                    vx -= yaw.sin() * SPRINT_JUMP_BOOST;
+                    vz += yaw.cos() * SPRINT_JUMP_BOOST;
+                }
+                self.jump_ticks = JUMP_COOLDOWN;
+            }
+        } else {
+            self.jump_ticks = 0;
+        }
+
+        let mut forward = controls.forward.clamp(-1.0, 1.0);
+        let mut strafe = controls.strafe.clamp(-1.0, 1.0);
+        if controls.sneak {
+            forward *= SNEAK_MULTIPLIER;
+            strafe *= SNEAK_MULTIPLIER;
+        }
+        forward *= INPUT_DAMPING;
+        strafe *= INPUT_DAMPING;
+
+        match fluid {
+            Some(fluid) => {
+                let (ax, az) = accelerate(forward, strafe, entity.yaw, LIQUID_ACCELERATION);
+                self.velocity = (vx + ax, vy, vz + az);
+                let start_y = entity.y;
+                self.move_entity(entity, world);
+                let (vx, vy, vz) = self.velocity;
+                self.velocity = match fluid {
+                    Fluid::Water => {
+                        let drag = if sprinting {
+                            SPRINT_WATER_DRAG
+                        } else {
+                            WATER_DRAG
+                        };
+                        (vx * drag, vy * WATER_DRAG - GRAVITY / 16.0, vz * drag)
+                    }
+                    Fluid::Lava => (
+                        vx * LAVA_DRAG,
+                        vy * LAVA_DRAG - GRAVITY / 4.0,
+                        vz * LAVA_DRAG,
+                    ),
+                };
+                let (vx, vy, vz) = self.velocity;
+                if self.collided_horizontally {
+                    let ahead =
+                        self.bounding_box(entity)
+                            .offset(vx, vy + 0.6 - entity.y + start_y, vz);
+                    if collision_boxes(world, &ahead).is_empty()
+                        && fluid_in(world, &ahead).is_none()
+                    {
+                        self.velocity.1 = LIQUID_CLIMB_VELOCITY;
+                    }
+                }
+            }
+            None => {
+                let friction = if self.onground {
+                    slipperiness(world, entity) * AIR_FRICTION
+                } else {
+                    AIR_FRICTION
+                };
+                let acceleration = if self.onground {
+                    let speed = if sprinting {
+                        WALK_SPEED * SPRINT_MULTIPLIER
+                    } else {
+                        WALK_SPEED
+                    };
+                    speed * GROUND_ACCELERATION / friction.powi(3)
+                } else if sprinting {
+                    SPRINT_AIR_ACCELERATION
+                } else {
+                    AIR_ACCELERATION
+                };
+                let (ax, az) = accelerate(forward, strafe, entity.yaw, acceleration);
+                self.velocity = (vx + ax, vy, vz + az);
+                self.move_entity(entity, world);
+                let (vx, vy, vz) = self.velocity;
+                self.velocity = (vx * friction, (vy - GRAVITY) * VERTICAL_DRAG, vz * friction);
+            }
+        }
+    }
+
+    /// Moves by `velocity` as far as blocks allow, stepping up half blocks on the way
+    fn move_entity(&mut self, entity: &mut Entity, world: &World) {
+        let (mut dx, dy, mut dz) = self.velocity;
+        let bounds = self.bounding_box(entity);
+
+        if self.controls.sneak && self.onground && dy <= 0.0 {
+            let over_edge = |x: f64, z: f64| {
+                collision_boxes(world, &bounds.offset(x, -STEP_HEIGHT, z)).is_empty()
+            };
+            while dx != 0.0 && over_edge(dx, 0.0) {
+                dx = back_off(dx);
+            }
+            while dz != 0.0 && over_edge(0.0, dz) {
+                dz = back_off(dz);
+            }
+            while dx != 0.0 && dz != 0.0 && over_edge(dx, dz) {
+                dx = back_off(dx);
+                dz = back_off(dz);
+            }
+        }
+
+        let mut moved = collide(world, &bounds, dx, dy, dz);
+        let blocked_horizontally = moved.0 != dx || moved.2 != dz;
+        if blocked_horizontally && (self.onground || (moved.1 != dy && dy < 0.0)) {
+            let (sx, sy, sz) = collide(world, &bounds, dx, STEP_HEIGHT, dz);
+            if sx * sx + sz * sz > moved.0 * moved.0 + moved.2 * moved.2 {
+                // back down onto whatever we stepped on
+                let (_, down, _) = collide(world, &bounds.offset(sx, sy, sz), 0.0, dy - sy, 0.0);
+                moved = (sx, sy + down, sz);
+            }
+        }
+
+        entity.x += moved.0;
+        entity.y += moved.1;
+        entity.z += moved.2;
+
+        self.collided_horizontally = moved.0 != dx || moved.2 != dz;
+        self.onground = moved.1 != dy && dy < 0.0;
+        let (vx, vy, vz) = self.velocity;
+        self.velocity = (
+            if moved.0 != dx { 0.0 } else { vx },
+            if moved.1 != dy { 0.0 } else { vy },
+            if moved.2 != dz { 0.0 } else { vz },
+        );
+    }
+
+    /// The packets the vanilla client would send after this tick: sprint and sneak toggles,
+    /// then position and look if they changed
+    pub fn movement_packets(&mut self, entity: &Entity) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        let last = match self.sent {
+            Some(last) => last,
+            None => return packets,
+        };
+        let sprinting = self.sprinting();
+        if sprinting != self.sent_sprinting {
+            self.sent_sprinting = sprinting;
+            let action = if sprinting { 3 } else { 4 };
+            packets.push(entity_action(entity, action));
+        }
+        if self.controls.sneak != self.sent_sneaking {
+            self.sent_sneaking = self.controls.sneak;
+            let action = if self.controls.sneak { 0 } else { 1 };
+            packets.push(entity_action(entity, action));
+        }
+
+        let now = SentMovement {
+            x: entity.x,
+            y: entity.y,
+            z: entity.z,
+            yaw: entity.yaw,
+            pitch: entity.pitch,
+            onground: self.onground,
+        };
+        self.ticks_since_position += 1;
+
+        let distance =
+            (now.x - last.x).powi(2) + (now.y - last.y).powi(2) + (now.z - last.z).powi(2);
+        let moved = distance > MIN_MOVEMENT * MIN_MOVEMENT
+            || self.ticks_since_position >= POSITION_RESEND_TICKS;
+        let turned = now.yaw != last.yaw || now.pitch != last.pitch;
+        let mut sent = SentMovement {
+            onground: now.onground,
+            ..last
+        };
+        if moved {
+            self.ticks_since_position = 0;
+            sent.x = now.x;
+            sent.y = now.y;
+            sent.z = now.z;
+        }
+        if turned {
+            sent.yaw = now.yaw;
+            sent.pitch = now.pitch;
+        }
+        self.sent = Some(sent);
+
+        packets.extend(match (moved, turned) {
+            (true, true) => Some(Packet::ClientPlayerPositionAndLook {
+                x: now.x,
+                y: now.y,
+                z: now.z,
+                yaw: now.yaw,
+                pitch: now.pitch,
+                onground: now.onground,
+            }),
+            (true, false) => Some(Packet::ClientPlayerPosition {
+                x: now.x,
+                y: now.y,
+                z: now.z,
+                onground: now.onground,
+            }),
+            (false, true) => Some(Packet::ClientPlayerLook {
+                yaw: now.yaw,
+                pitch: now.pitch,
+                onground: now.onground,
+            }),
+            (false, false) if now.onground != last.onground => Some(Packet::ClientPlayer {
+                onground: now.onground,
+            }),
+            (false, false) => None,
+        });
+        packets
+    }
+}
+
+fn entity_action(entity: &Entity, action: i32) -> Packet {
+    Packet::ClientEntityAction {
+        entity_id: entity.entityid,
+        action,
+        jump_boost: 0,
+    }
+}
+
+fn settle(velocity: f64) -> f64 {
+    if velocity.abs() < MIN_VELOCITY {
+        0.0
+    } else {
+        velocity
+    }
+}
+
+fn back_off(delta: f64) -> f64 {
+    if delta.abs() < EDGE_STEP {
+        0.0
+    } else {
+        delta - EDGE_STEP * delta.signum()
+    }
+}
+
+/// Turns input into horizontal acceleration along the view direction
+fn accelerate(forward: f64, strafe: f64, yaw: f32, acceleration: f64) -> (f64, f64) {
+    let length = forward * forward + strafe * strafe;
+    if length < 1.0e-7 {
+        return (0.0, 0.0);
+    }
+    let scale = acceleration / length.sqrt().max(1.0);
+    let (strafe, forward) = (strafe * scale, forward * scale);
+    let yaw = (yaw as f64).to_radians();
+    (
+        strafe * yaw.cos() - forward * yaw.sin(),
+        forward * yaw.cos() + strafe * yaw.sin(),
+    )
+}
+
+/// Clips a movement against all blocks, vertical first like vanilla
+fn collide(world: &World, bounds: &Aabb, dx: f64, dy: f64, dz: f64) -> (f64, f64, f64) {
+    let boxes = collision_boxes(world, &bounds.expand(dx, dy, dz));
+    let mut bounds = *bounds;
+
+    let dy = boxes.iter().fold(dy, |dy, block| block.clip_y(&bounds, dy));
+    bounds = bounds.offset(0.0, dy, 0.0);
+    let (mut dx, mut dz) = (dx, dz);
+    if dx.abs() < dz.abs() {
+        dz = boxes.iter().fold(dz, |dz, block| block.clip_z(&bounds, dz));
+        bounds = bounds.offset(0.0, 0.0, dz);
+        dx = boxes.iter().fold(dx, |dx, block| block.clip_x(&bounds, dx));
+    } else {
+        dx = boxes.iter().fold(dx, |dx, block| block.clip_x(&bounds, dx));
+        bounds = bounds.offset(dx, 0.0, 0.0);
+        dz = boxes.iter().fold(dz, |dz, block| block.clip_z(&bounds, dz));
+    }
+    (dx, dy, dz)
+}
+
+/// Collision boxes of all blocks touching `area`. Unloaded chunks are walls
+pub fn collision_boxes(world: &World, area: &Aabb) -> Vec<Aabb> {
+    let mut boxes = Vec::new();
+    // fences and walls reach half a block into the block above
+    for y in (area.min_y.floor() as i32 - 1)..=(area.max_y.floor() as i32) {
+        if !(0..=255).contains(&y) {
+            continue;
+        }
+        for x in (area.min_x.floor() as i32)..=(area.max_x.floor() as i32) {
+            for z in (area.min_z.floor() as i32)..=(area.max_z.floor() as i32) {
+                let shape = match world.block_at(x, y, z) {
+                    Some(id) => match world.blocks.state(id) {
+                        Some(state) => block_shape(state),
+                        None if world.blocks.class(id) == BlockClass::Solid => {
+                            vec![FULL_BLOCK]
+                        }
+                        None => Vec::new(),
+                    },
+                    None => vec![FULL_BLOCK],
+                };
+                boxes.extend(
+                    shape
+                        .iter()
+                        .map(|part| part.offset(x as f64, y as f64, z as f64))
+                        .filter(|part| part.intersects(area)),
+                );
+            }
+        }
+    }
+    boxes
+}
+
+fn fluid_in(world: &World, area: &Aabb) -> Option<Fluid> {
+    for y in (area.min_y.floor() as i32)..=(area.max_y.floor() as i32) {
+        for x in (area.min_x.floor() as i32)..=(area.max_x.floor() as i32) {
+            for z in (area.min_z.floor() as i32)..=(area.max_z.floor() as i32) {
+                let state = match world.block_state_at(x, y, z) {
+                    Some(state) if state.is_liquid() => state,
+                    _ => continue,
+                };
+                return Some(if state.name == "minecraft:lava" {
+                    Fluid::Lava
+                } else {
+                    Fluid::Water
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Of the block below the feet
+fn slipperiness(world: &World, entity: &Entity) -> f64 {
+    let below = world.block_state_at(
+        entity.x.floor() as i32,
+        (entity.y - 1.0).floor() as i32,
+        entity.z.floor() as i32,
+    );
+    match below.map(|state| state.name.as_str()) {
+        Some("minecraft:ice") | Some("minecraft:packed_ice") | Some("minecraft:frosted_ice") => {
+            0.98
+        }
+        Some("minecraft:blue_ice") => 0.989,
+        Some("minecraft:slime_block") => 0.8,
+        _ => DEFAULT_SLIPPERINESS,
+    }
+}
+
+const FULL_BLOCK: Aabb = Aabb {
+    min_x: 0.0,
+    min_y: 0.0,
+    min_z: 0.0,
+    max_x: 1.0,
+    max_y: 1.0,
+    max_z: 1.0,
+};
+
+/// A box in sixteenths of a block, like the vanilla shape definitions
+fn pixels(min_x: f64, min_y: f64, min_z: f64, max_x: f64, max_y: f64, max_z: f64) -> Aabb {
+    Aabb::new(
+        min_x / 16.0,
+        min_y / 16.0,
+        min_z / 16.0,
+        max_x / 16.0,
+        max_y / 16.0,
+        max_z / 16.0,
+    )
+}
+
+/// The collision shape of a block state relative to its corner. Blocks the table doesn't
+/// know are full cubes if solid and empty otherwise
+pub fn block_shape(state: &BlockState) -> Vec<Aabb> {
+    let name = state.name.trim_start_matches("minecraft:");
+    if name == "snow" {
+        let layers: f64 = state
+            .property("layers")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(1.0);
+        return match layers as i32 {
+            1 => Vec::new(),
+            _ => vec![pixels(0.0, 0.0, 0.0, 16.0, (layers - 1.0) * 2.0, 16.0)],
+        };
+    }
+    if !state.is_solid() {
+        return Vec::new();
+    }
+    let facing = state.property("facing");
+    let open = state.property("open") == Some("true");
+    let top_half = state.property("half") == Some("top");
+
+    if name.ends_with("_slab") {
+        return match state.property("type") {
+            Some("bottom") => vec![pixels(0.0, 0.0, 0.0, 16.0, 8.0, 16.0)],
+            Some("top") => vec![pixels(0.0, 8.0, 0.0, 16.0, 16.0, 16.0)],
+            _ => vec![FULL_BLOCK],
+        };
+    }
+    if name.ends_with("_stairs") {
+        let (base, step) = if top_half { (8.0, 0.0) } else { (0.0, 8.0) };
+        let step = match facing {
+            Some("north") => pixels(0.0, step, 0.0, 16.0, step + 8.0, 8.0),
+            Some("south") => pixels(0.0, step, 8.0, 16.0, step + 8.0, 16.0),
+            Some("west") => pixels(0.0, step, 0.0, 8.0, step + 8.0, 16.0),
+            _ => pixels(8.0, step, 0.0, 16.0, step + 8.0, 16.0),
+        };
+        return vec![pixels(0.0, base, 0.0, 16.0, base + 8.0, 16.0), step];
+    }
+    if name.ends_with("_fence_gate") {
+        return if open {
+            Vec::new()
+        } else {
+            vec![pixels(0.0, 0.0, 0.0, 16.0, 24.0, 16.0)]
+        };
+    }
+    if name.ends_with("_fence") || name.ends_with("_wall") {
+        return vec![pixels(0.0, 0.0, 0.0, 16.0, 24.0, 16.0)];
+    }
+    if name.ends_with("_trapdoor") {
+        return vec![match (open, facing) {
+            (false, _) if top_half => pixels(0.0, 13.0, 0.0, 16.0, 16.0, 16.0),
+            (false, _) => pixels(0.0, 0.0, 0.0, 16.0, 3.0, 16.0),
+            (true, Some("north")) => pixels(0.0, 0.0, 13.0, 16.0, 16.0, 16.0),
+            (true, Some("south")) => pixels(0.0, 0.0, 0.0, 16.0, 16.0, 3.0),
+            (true, Some("west")) => pixels(13.0, 0.0, 0.0, 16.0, 16.0, 16.0),
+            (true, _) => pixels(0.0, 0.0, 0.0, 3.0, 16.0, 16.0),
+        }];
+    }
+    if name.ends_with("_door") {
+        let north = pixels(0.0, 0.0, 13.0, 16.0, 16.0, 16.0);
+        let south = pixels(0.0, 0.0, 0.0, 16.0, 16.0, 3.0);
+        let west = pixels(13.0, 0.0, 0.0, 16.0, 16.0, 16.0);
+        let east = pixels(0.0, 0.0, 0.0, 3.0, 16.0, 16.0);
+        let right_hinge = state.property("hinge") == Some("right");
+        return vec![match facing {
+            Some("south") if !open => south,
+            Some("south") if right_hinge => east,
+            Some("south") => west,
+            Some("west") if !open => west,
+            Some("west") if right_hinge => south,
+            Some("west") => north,
+            Some("north") if !open => north,
+            Some("north") if right_hinge => west,
+            Some("north") => east,
+            _ if !open => east,
+            _ if right_hinge => north,
+            _ => south,
+        }];
+    }
+    if name.ends_with("_bed") {
+        return vec![pixels(0.0, 0.0, 0.0, 16.0, 9.0, 16.0)];
+    }
+    if name.ends_with("_carpet") {
+        return vec![pixels(0.0, 0.0, 0.0, 16.0, 1.0, 16.0)];
+    }
+    match name {
+        "farmland" | "grass_path" => vec![pixels(0.0, 0.0, 0.0, 16.0, 15.0, 16.0)],
+        "soul_sand" => vec![pixels(0.0, 0.0, 0.0, 16.0, 14.0, 16.0)],
+        "enchanting_table" => vec![pixels(0.0, 0.0, 0.0, 16.0, 12.0, 16.0)],
+        "end_portal_frame" => vec![pixels(0.0, 0.0, 0.0, 16.0, 13.0, 16.0)],
+        "daylight_detector" => vec![pixels(0.0, 0.0, 0.0, 16.0, 6.0, 16.0)],
+        "repeater" | "comparator" => vec![pixels(0.0, 0.0, 0.0, 16.0, 2.0, 16.0)],
+        "lily_pad" => vec![pixels(1.0, 0.0, 1.0, 15.0, 1.5, 15.0)],
+        "cactus" => vec![pixels(1.0, 0.0, 1.0, 15.0, 15.0, 15.0)],
+        "chest" | "trapped_chest" | "ender_chest" => vec![pixels(1.0, 0.0, 1.0, 15.0, 14.0, 15.0)],
+        _ => vec![FULL_BLOCK],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockRegistry;
+    use crate::protocol::Position;
+    use crate::world::{ChunkBlockID, ChunkColumn};
+
+    const REPORT: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:stone": {"states": [{"id": 1, "default": true}]},
+        "minecraft:stone_slab": {"states": [
+            {"id": 2, "properties": {"type": "bottom", "waterlogged": "false"}, "default": true}
+        ]}
+    }"#;
+    const STONE: ChunkBlockID = ChunkBlockID(1);
+    const SLAB: ChunkBlockID = ChunkBlockID(2);
+
+    /// Nine loaded chunks around the origin with a stone floor whose top is at y 64
+    fn flat_world() -> World {
+        let mut world = World::new(BlockRegistry::from_json(REPORT).unwrap());
+        for x in -1..=1 {
+            for z in -1..=1 {
+                world.columns.insert((x, z), ChunkColumn::new(x, z));
+            }
+        }
+        for x in -16..32 {
+            for z in -16..32 {
+                world.set_block(Position { x, y: 63, z }, STONE);
+            }
+        }
+        world
+    }
+
+    /// Placed by the server at x, y, z looking towards +z
+    fn spawn(physics: &mut Physics, x: f64, y: f64, z: f64) -> Entity {
+        let mut entity = Entity {
+            entityid: 1,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        physics.teleport(
+            &mut entity,
+            &Packet::ServerPlayerPositionAndLook {
+                x,
+                y,
+                z,
+                yaw: 0.0,
+                pitch: 0.0,
+                flags: 0,
+                teleportid: 1,
+            },
+        );
+        entity
+    }
+
+    fn walk(sneak: bool) -> Controls {
+        Controls {
+            forward: 1.0,
+            sneak,
+            ..Controls::default()
+        }
+    }
+
+    fn state(name: &str, class: BlockClass, properties: &[(&str, &str)]) -> BlockState {
+        BlockState {
+            id: ChunkBlockID(1),
+            name: format!("minecraft:{}", name),
+            properties: properties
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            class,
+        }
+    }
+
+    fn height(shape: &[Aabb]) -> f64 {
+        shape
+            .iter()
+            .fold(0.0, |height, part| part.max_y.max(height))
+    }
+
+    #[test]
+    fn partial_blocks() {
+        let slab = state("oak_slab", BlockClass::Solid, &[("type", "bottom")]);
+        assert_eq!(
+            block_shape(&slab),
+            vec![pixels(0.0, 0.0, 0.0, 16.0, 8.0, 16.0)]
+        );
+        let double = state("oak_slab", BlockClass::Solid, &[("type", "double")]);
+        assert_eq!(block_shape(&double), vec![FULL_BLOCK]);
+        let carpet = state("white_carpet", BlockClass::Solid, &[]);
+        assert_eq!(height(&block_shape(&carpet)), 1.0 / 16.0);
+        let repeater = state("repeater", BlockClass::Solid, &[]);
+        assert_eq!(height(&block_shape(&repeater)), 2.0 / 16.0);
+    }
+
+    #[test]
+    fn stairs_have_a_step_towards_facing() {
+        let stairs = state(
+            "oak_stairs",
+            BlockClass::Solid,
+            &[("facing", "north"), ("half", "bottom")],
+        );
+        let shape = block_shape(&stairs);
+        assert_eq!(shape.len(), 2);
+        assert_eq!(shape[0].max_y, 0.5);
+        assert_eq!(shape[1], pixels(0.0, 8.0, 0.0, 16.0, 16.0, 8.0));
+    }
+
+    #[test]
+    fn snow_layers() {
+        let thin = state("snow", BlockClass::Transparent, &[("layers", "1")]);
+        assert!(block_shape(&thin).is_empty());
+        let deep = state("snow", BlockClass::Solid, &[("layers", "3")]);
+        assert_eq!(height(&block_shape(&deep)), 4.0 / 16.0);
+    }
+
+    #[test]
+    fn passable_blocks_have_no_shape() {
+        let poppy = state("poppy", BlockClass::Transparent, &[]);
+        assert!(block_shape(&poppy).is_empty());
+        let gate = state("oak_fence_gate", BlockClass::Solid, &[("open", "true")]);
+        assert!(block_shape(&gate).is_empty());
+        let stone = state("stone", BlockClass::Solid, &[]);
+        assert_eq!(block_shape(&stone), vec![FULL_BLOCK]);
+    }
+
+    #[test]
+    fn nothing_moves_before_the_first_teleport_or_outside_loaded_chunks() {
+        let world = flat_world();
+        let mut physics = Physics::new();
+        let mut entity = Entity {
+            entityid: 1,
+            x: 0.5,
+            y: 80.0,
+            z: 0.5,
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        physics.tick(&mut entity, &world);
+        assert_eq!(entity.y, 80.0);
+        assert!(physics.movement_packets(&entity).is_empty());
+
+        let mut far = spawn(&mut physics, 100.5, 80.0, 0.5);
+        physics.tick(&mut far, &world);
+        assert_eq!((far.y, physics.velocity.1), (80.0, 0.0));
+    }
+
+    #[test]
+    fn falls_onto_the_ground() {
+        let world = flat_world();
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 0.5, 70.0, 0.5);
+        physics.tick(&mut entity, &world);
+        physics.tick(&mut entity, &world);
+        // gravity and drag of the first tick only show in the second
+        assert!((entity.y - (70.0 - 0.0784)).abs() < 1e-9);
+        for _ in 0..40 {
+            physics.tick(&mut entity, &world);
+        }
+        assert_eq!(entity.y, 64.0);
+        assert!(physics.onground);
+    }
+
+    #[test]
+    fn walking_and_jumping_like_vanilla() {
+        let world = flat_world();
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 0.5, 64.0, -15.5);
+        // standing still is only onground once gravity pulled against the floor
+        physics.tick(&mut entity, &world);
+        assert!(!physics.onground);
+        physics.tick(&mut entity, &world);
+        assert!(physics.onground);
+
+        physics.hold(walk(false), 0);
+        for _ in 0..40 {
+            physics.tick(&mut entity, &world);
+        }
+        // 4.317 blocks per second
+        let start = entity.z;
+        for _ in 0..20 {
+            physics.tick(&mut entity, &world);
+        }
+        assert!(((entity.z - start) / 20.0 - 0.21585).abs() < 1e-4);
+        assert_eq!(entity.x, 0.5);
+
+        physics.hold(
+            Controls {
+                jump: true,
+                ..Controls::default()
+            },
+            1,
+        );
+        let mut peak: f64 = 0.0;
+        for _ in 0..20 {
+            physics.tick(&mut entity, &world);
+            peak = peak.max(entity.y - 64.0);
+        }
+        assert!((peak - 1.2522).abs() < 1e-3, "jumped {}", peak);
+        assert_eq!(entity.y, 64.0);
+    }
+
+    #[test]
+    fn steps_onto_slabs_but_not_blocks() {
+        let mut world = flat_world();
+        world.set_block(Position { x: 0, y: 64, z: 3 }, SLAB);
+        world.set_block(Position { x: 0, y: 65, z: 8 }, STONE);
+        world.set_block(Position { x: 0, y: 64, z: 8 }, STONE);
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 0.5, 64.0, 0.5);
+        physics.hold(walk(false), 0);
+        for _ in 0..15 {
+            physics.tick(&mut entity, &world);
+        }
+        assert_eq!(entity.y, 64.5);
+        for _ in 0..40 {
+            physics.tick(&mut entity, &world);
+        }
+        assert_eq!((entity.y, entity.z), (64.0, 8.0 - PLAYER_WIDTH / 2.0));
+        assert!(physics.collided_horizontally);
+        assert_eq!(physics.velocity.2, 0.0);
+    }
+
+    #[test]
+    fn sneaking_stops_at_edges() {
+        let mut world = flat_world();
+        for x in -16..32 {
+            for z in 2..32 {
+                world.set_block(Position { x, y: 63, z }, ChunkBlockID::AIR);
+            }
+        }
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 0.5, 64.0, 0.5);
+        physics.tick(&mut entity, &world);
+        physics.hold(walk(true), 0);
+        for _ in 0..100 {
+            physics.tick(&mut entity, &world);
+        }
+        assert_eq!(entity.y, 64.0);
+        assert!(entity.z > 2.0 && entity.z < 2.0 + PLAYER_WIDTH / 2.0);
+
+        physics.hold(walk(false), 0);
+        for _ in 0..20 {
+            physics.tick(&mut entity, &world);
+        }
+        assert!(entity.y < 64.0);
+    }
+
+    #[test]
+    fn teleports_apply_relative_flags() {
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 10.0, 64.0, 10.0);
+        physics.velocity = (0.5, 0.25, -0.5);
+        entity.yaw = 90.0;
+        let packets = physics.teleport(
+            &mut entity,
+            &Packet::ServerPlayerPositionAndLook {
+                x: 1.0,
+                y: 70.0,
+                z: -2.0,
+                yaw: 45.0,
+                pitch: 10.0,
+                flags: RELATIVE_X | RELATIVE_Z | RELATIVE_YAW,
+                teleportid: 7,
+            },
+        );
+        assert_eq!((entity.x, entity.y, entity.z), (11.0, 70.0, 8.0));
+        assert_eq!((entity.yaw, entity.pitch), (135.0, 10.0));
+        // momentum is only kept on the relative axes
+        assert_eq!(physics.velocity, (0.5, 0.0, -0.5));
+        match packets.as_slice() {
+            [Packet::ClientTeleportConfirm { teleport_id: 7 }, Packet::ClientPlayerPositionAndLook {
+                x,
+                y,
+                z,
+                onground: false,
+                ..
+            }] => assert_eq!((*x, *y, *z), (11.0, 70.0, 8.0)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn movement_packets_only_carry_changes() {
+        let world = flat_world();
+        let mut physics = Physics::new();
+        let mut entity = spawn(&mut physics, 0.5, 64.0, 0.5);
+        physics.tick(&mut entity, &world);
+        assert!(physics.movement_packets(&entity).is_empty());
+        physics.tick(&mut entity, &world);
+        assert!(matches!(
+            physics.movement_packets(&entity).as_slice(),
+            [Packet::ClientPlayer { onground: true }]
+        ));
+
+        entity.yaw = 30.0;
+        assert!(matches!(
+            physics.movement_packets(&entity).as_slice(),
+            [Packet::ClientPlayerLook { onground: true, .. }]
+        ));
+        physics.hold(
+            Controls {
+                forward: 1.0,
+                sprint: true,
+                ..Controls::default()
+            },
+            0,
+        );
+        physics.tick(&mut entity, &world);
+        assert!(matches!(
+            physics.movement_packets(&entity).as_slice(),
+            [
+                Packet::ClientEntityAction { action: 3, .. },
+                Packet::ClientPlayerPosition { .. }
+            ]
+        ));
+
+        // standing still, the position is still sent once a second
+        physics.release();
+        let mut positions = 0;
+        for _ in 0..40 {
+            physics.tick(&mut entity, &world);
+            positions += physics
+                .movement_packets(&entity)
+                .iter()
+                .filter(|packet| matches!(packet, Packet::ClientPlayerPosition { .. }))
+                .count();
+        }
+        assert!((2..20).contains(&positions), "{} positions", positions);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::chat::TextComponent;
+use crate::protocol::{Packet, PlayerInfoAction, PlayerProperty};
+use std::collections::HashMap;
+
+/// One line of the tab list
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerListEntry {
+    pub uuid: i128,
+    pub name: String,
+    pub properties: Vec<PlayerProperty>,
+    /// 0 survival, 1 creative, 2 adventure, 3 spectator
+    pub gamemode: i32,
+    /// In milliseconds
+    pub ping: i32,
+    pub display_name: Option<TextComponent>,
+}
+
+/// Everyone online, as the server reports it with Player Info packets
+pub struct PlayerList {
+    players: HashMap<i128, PlayerListEntry>,
+}
+
+impl Default for PlayerList {
+    fn default() -> PlayerList {
+        PlayerList::new()
+    }
+}
+
+impl PlayerList {
+    pub fn new() -> PlayerList {
+        PlayerList {
+            players: HashMap::new(),
+        }
+    }
+
+    /// Updates the list from a Player Info packet, everything else is ignored
+    pub fn apply(&mut self, packet: &Packet) {
+        let action = match packet {
+            Packet::ServerPlayerInfo { action } => action,
+            _ => return,
+        };
+        match action {
+            PlayerInfoAction::AddPlayers(players) => {
+                for (uuid, player) in players {
+                    let entry = PlayerListEntry {
+                        uuid: *uuid,
+                        name: player.name.clone(),
+                        properties: player.properties.clone(),
+                        gamemode: player.gamemode,
+                        ping: player.ping,
+                        display_name: parse_display_name(&player.display_name),
+                    };
+                    self.players.insert(*uuid, entry);
+                }
+            }
+            PlayerInfoAction::UpdateGamemode(players) => {
+                for (uuid, gamemode) in players {
+                    if let Some(entry) = self.players.get_mut(uuid) {
+                        entry.gamemode = *gamemode;
+                    }
+                }
+            }
+            PlayerInfoAction::UpdateLatency(players) => {
+                for (uuid, ping) in players {
+                    if let Some(entry) = self.players.get_mut(uuid) {
+                        entry.ping = *ping;
+                    }
+                }
+            }
+            PlayerInfoAction::UpdateDisplayName(players) => {
+                for (uuid, display_name) in players {
+                    if let Some(entry) = self.players.get_mut(uuid) {
+                        entry.display_name = parse_display_name(display_name);
+                    }
+                }
+            }
+            PlayerInfoAction::RemovePlayers(uuids) => {
+                for uuid in uuids {
+                    self.players.remove(uuid);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, uuid: i128) -> Option<&PlayerListEntry> {
+        self.players.get(&uuid)
+    }
+
+    /// The username behind a UUID, e.g. of a spawned player entity
+    pub fn name_of(&self, uuid: i128) -> Option<&str> {
+        self.get(uuid).map(|entry| entry.name.as_str())
+    }
+
+    /// Usernames are compared case-insensitively like the server does
+    pub fn by_name(&self, name: &str) -> Option<&PlayerListEntry> {
+        self.iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PlayerListEntry> {
+        self.players.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+}
+
+fn parse_display_name(display_name: &Option<String>) -> Option<TextComponent> {
+    display_name
+        .as_ref()
+        .and_then(|name| TextComponent::from_json(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::PlayerInfoAdd;
+
+    fn info(action: PlayerInfoAction) -> Packet {
+        Packet::ServerPlayerInfo { action }
+    }
+
+    fn add(name: &str, display_name: Option<&str>) -> PlayerInfoAdd {
+        PlayerInfoAdd {
+            name: name.to_string(),
+            properties: Vec::new(),
+            gamemode: 0,
+            ping: 0,
+            display_name: display_name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn players_are_added_updated_and_removed() {
+        let mut players = PlayerList::new();
+        players.apply(&info(PlayerInfoAction::AddPlayers(vec![
+            (1, add("Notch", None)),
+            (2, add("jeb_", Some(r#"{"text":"Jeb"}"#))),
+        ])));
+        assert_eq!(players.len(), 2);
+        assert_eq!(players.name_of(1), Some("Notch"));
+        assert_eq!(players.by_name("notch").map(|entry| entry.uuid), Some(1));
+        assert_eq!(
+            players
+                .get(2)
+                .unwrap()
+                .display_name
+                .as_ref()
+                .unwrap()
+                .to_plain(),
+            "Jeb"
+        );
+
+        players.apply(&info(PlayerInfoAction::UpdateGamemode(vec![
+            (1, 3),
+            (9, 1),
+        ])));
+        players.apply(&info(PlayerInfoAction::UpdateLatency(vec![(1, 120)])));
+        players.apply(&info(PlayerInfoAction::UpdateDisplayName(vec![(2, None)])));
+        let notch = players.get(1).unwrap();
+        assert_eq!((notch.gamemode, notch.ping), (3, 120));
+        assert!(players.get(2).unwrap().display_name.is_none());
+        // updates for players we don't know don't add them
+        assert!(players.get(9).is_none());
+
+        players.apply(&info(PlayerInfoAction::RemovePlayers(vec![1])));
+        assert_eq!(players.len(), 1);
+        assert!(players.by_name("Notch").is_none());
+    }
+}
@@ -1,18 +1,23 @@
-use crate::compression::*;
+use crate::framing::MAX_FRAME_LENGTH;
 use crate::game::CompressionStatus::Enabled;
 use crate::game::{CompressionStatus, ConnectionState, MinecraftConnection};
-#[macro_use]
-use crate::macros;
+use crate::item::{ItemStack, Slot};
+use crate::nbt::{self, NbtTag};
+use crate::version::ProtocolVersion;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use err_derive::Error;
 use flate2::read::{ZlibDecoder, ZlibEncoder};
 use flate2::Compression;
 use mc_varint::{VarIntRead, VarIntWrite};
-use std::borrow::BorrowMut;
+use std::collections::BTreeMap;
 use std::io;
-use std::io::{Cursor, Error, Read, Result as IOResult, Write};
+use std::io::{Cursor, Read, Result as IOResult, Write};
+use std::marker::PhantomData;
 use std::result::Result;
 
+/// Vanilla refuses compressed frames that inflate to more than this
+const MAX_UNCOMPRESSED_LENGTH: i32 = 2_097_152;
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 pub enum RawPacketValueType {
@@ -30,6 +35,9 @@ pub enum RawPacketValueType {
     String,
     Position,
     boolean,
+    ByteArray,
+    Nbt,
+    Slot,
 }
 
 impl RawPacketValueType {
@@ -51,8 +59,7 @@ impl RawPacketValueType {
             RawPacketValueType::int128 => RawPacketValue::int128(buf.read_i128::<BigEndian>()?),
             RawPacketValueType::String => {
                 let len = buf.read_var_i32()?;
-                let mut sbuf = vec![0u8; len as usize];
-                buf.read_exact(&mut sbuf);
+                let sbuf = read_exact_len(buf, len)?;
                 RawPacketValue::String(String::from_utf8_lossy(&sbuf).to_string())
             }
             RawPacketValueType::Position => {
@@ -60,10 +67,50 @@ impl RawPacketValueType {
                 RawPacketValue::Position(val >> 38, val << 26 >> 52, val << 38 >> 38)
             }
             RawPacketValueType::boolean => RawPacketValue::boolean(buf.read_u8()? != 0),
+            RawPacketValueType::ByteArray => {
+                let len = buf.read_var_i32()?;
+                RawPacketValue::ByteArray(read_exact_len(buf, len)?)
+            }
+            RawPacketValueType::Nbt => RawPacketValue::Nbt(nbt::read_named(buf)?.1),
+            RawPacketValueType::Slot => {
+                if buf.read_u8()? == 0 {
+                    RawPacketValue::Slot(None)
+                } else {
+                    let item_id = buf.read_var_i32()?;
+                    let count = buf.read_i8()?;
+                    let nbt = nbt::read_named(buf)?.1;
+                    RawPacketValue::Slot(Some(ItemStack {
+                        item_id,
+                        count,
+                        damage: 0,
+                        nbt: Some(nbt).filter(|nbt| *nbt != NbtTag::End),
+                    }))
+                }
+            }
         })
     }
 }
 
+/// Reads `len` bytes of a length prefixed field. The buffer only grows with the bytes that
+/// actually arrived, so a bogus length can't allocate more than the frame holds
+pub fn read_exact_len<R: Read>(buf: &mut R, len: i32) -> IOResult<Vec<u8>> {
+    if len < 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("negative length {}", len),
+        ));
+    }
+    let mut bytes = Vec::new();
+    buf.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("length {} is longer than the rest of the frame", len),
+        ));
+    }
+    Ok(bytes)
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Clone, Debug)]
 pub enum RawPacketValue {
@@ -81,6 +128,11 @@ pub enum RawPacketValue {
     String(String),
     Position(i64, i64, i64),
     boolean(bool),
+    ByteArray(Vec<u8>),
+    /// A root tag with an empty name, `NbtTag::End` if there is none
+    Nbt(NbtTag),
+    /// Present flag, item id, count and NBT as sent since 1.13.2
+    Slot(Slot),
 }
 
 impl RawPacketValue {
@@ -88,7 +140,7 @@ impl RawPacketValue {
     where
         W: Write,
     {
-        Ok(match &self {
+        match self {
             RawPacketValue::byte(v) => buf.write_i8(*v)?,
             RawPacketValue::ubyte(v) => buf.write_u8(*v)?,
             RawPacketValue::short(v) => buf.write_i16::<BigEndian>(*v)?,
@@ -108,8 +160,959 @@ impl RawPacketValue {
             )?,
             RawPacketValue::boolean(v) => buf.write_u8(if *v { 1 } else { 0 })?,
             RawPacketValue::long(v) => buf.write_i64::<BigEndian>(*v)?,
+            RawPacketValue::ByteArray(v) => {
+                buf.write_var_i32(v.len() as i32)?;
+                buf.write_all(v)?;
+            }
+            RawPacketValue::Nbt(v) => nbt::write_named(buf, "", v)?,
+            RawPacketValue::Slot(None) => buf.write_u8(0)?,
+            RawPacketValue::Slot(Some(item)) => {
+                buf.write_u8(1)?;
+                buf.write_var_i32(item.item_id)?;
+                buf.write_i8(item.count)?;
+                nbt::write_named(buf, "", item.nbt.as_ref().unwrap_or(&NbtTag::End))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and writes a field of type `T`, every packet field in `impl_packets!` goes through one
+pub trait FieldCodec<T> {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<T>;
+    fn write<W: Write>(value: &T, buf: &mut W, version: ProtocolVersion) -> IOResult<()>;
+}
+
+impl_raw_field_codecs! {
+    i8 => byte,
+    u8 => ubyte,
+    i16 => short,
+    u16 => ushort,
+    i32 => int,
+    i64 => long,
+    f32 => float,
+    f64 => double,
+    i128 => int128,
+    String => String,
+    bool => boolean,
+    Vec<u8> => ByteArray,
+    NbtTag => Nbt,
+}
+
+/// An `i32` sent as VarInt
+pub struct VarInt;
+
+impl FieldCodec<i32> for VarInt {
+    fn read<R: Read>(buf: &mut R, _: ProtocolVersion) -> IOResult<i32> {
+        match RawPacketValueType::varint.from_buf(buf)? {
+            RawPacketValue::varint(value) => Ok(value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write<W: Write>(value: &i32, buf: &mut W, _: ProtocolVersion) -> IOResult<()> {
+        RawPacketValue::varint(*value).serialize(buf)
+    }
+}
+
+/// An `i32` sent as Short, for Short length prefixes
+pub struct Short;
+
+impl FieldCodec<i32> for Short {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<i32> {
+        i16::read(buf, version).map(i32::from)
+    }
+
+    fn write<W: Write>(value: &i32, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        i16::write(&(*value as i16), buf, version)
+    }
+}
+
+/// The field's own codec, the default element codec of `Array`
+pub struct Plain;
+
+impl<T: FieldCodec<T>> FieldCodec<T> for Plain {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<T> {
+        T::read(buf, version)
+    }
+
+    fn write<W: Write>(value: &T, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        T::write(value, buf, version)
+    }
+}
+
+/// A length followed by that many `T`s. The length is sent with `L` (VarInt by default),
+/// the elements with `E`
+pub struct Array<L = VarInt, E = Plain>(PhantomData<(L, E)>);
+
+impl<T, L: FieldCodec<i32>, E: FieldCodec<T>> FieldCodec<Vec<T>> for Array<L, E> {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<Vec<T>> {
+        let len = L::read(buf, version)?;
+        if len < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "negative array length",
+            ));
+        }
+        (0..len).map(|_| E::read(buf, version)).collect()
+    }
+
+    fn write<W: Write>(value: &Vec<T>, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        L::write(&(value.len() as i32), buf, version)?;
+        value
+            .iter()
+            .try_for_each(|element| E::write(element, buf, version))
+    }
+}
+
+/// A rotation in degrees, sent as steps of 1/256 of a full turn
+pub struct Angle;
+
+impl FieldCodec<f32> for Angle {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<f32> {
+        u8::read(buf, version).map(|steps| steps as f32 * 360.0 / 256.0)
+    }
+
+    fn write<W: Write>(value: &f32, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        let steps = (value.rem_euclid(360.0) * 256.0 / 360.0) as u32 as u8;
+        u8::write(&steps, buf, version)
+    }
+}
+
+/// A block position, packed into a Long
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Position {
+        Position { x, y, z }
+    }
+
+    pub fn offset(&self, x: i32, y: i32, z: i32) -> Position {
+        Position::new(self.x + x, self.y + y, self.z + z)
+    }
+}
+
+/// A side of a block, as numbered on the wire
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Face {
+    Bottom = 0,
+    Top = 1,
+    North = 2,
+    South = 3,
+    West = 4,
+    East = 5,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::Bottom,
+        Face::Top,
+        Face::North,
+        Face::South,
+        Face::West,
+        Face::East,
+    ];
+
+    /// The direction the face points to
+    pub fn normal(self) -> (i32, i32, i32) {
+        match self {
+            Face::Bottom => (0, -1, 0),
+            Face::Top => (0, 1, 0),
+            Face::North => (0, 0, -1),
+            Face::South => (0, 0, 1),
+            Face::West => (-1, 0, 0),
+            Face::East => (1, 0, 0),
+        }
+    }
+
+    /// The face of the block at `position` that points most towards `x, y, z`
+    pub fn toward(position: Position, x: f64, y: f64, z: f64) -> Face {
+        let (dx, dy, dz) = (
+            x - (position.x as f64 + 0.5),
+            y - (position.y as f64 + 0.5),
+            z - (position.z as f64 + 0.5),
+        );
+        if dy.abs() >= dx.abs() && dy.abs() >= dz.abs() {
+            if dy > 0.0 {
+                Face::Top
+            } else {
+                Face::Bottom
+            }
+        } else if dx.abs() >= dz.abs() {
+            if dx > 0.0 {
+                Face::East
+            } else {
+                Face::West
+            }
+        } else if dz > 0.0 {
+            Face::South
+        } else {
+            Face::North
+        }
+    }
+}
+
+/// 1.14 moved y to the lowest 12 bits
+impl FieldCodec<Position> for Position {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<Position> {
+        if version >= ProtocolVersion::V1_14_4 {
+            let val = i64::read(buf, version)?;
+            return Ok(Position {
+                x: (val >> 38) as i32,
+                y: (val << 52 >> 52) as i32,
+                z: (val << 26 >> 38) as i32,
+            });
+        }
+        match RawPacketValueType::Position.from_buf(buf)? {
+            RawPacketValue::Position(x, y, z) => Ok(Position {
+                x: x as i32,
+                y: y as i32,
+                z: z as i32,
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write<W: Write>(value: &Position, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        let (x, y, z) = (value.x as i64, value.y as i64, value.z as i64);
+        if version >= ProtocolVersion::V1_14_4 {
+            let val = (x & 0x3FFFFFF) << 38 | (z & 0x3FFFFFF) << 12 | (y & 0xFFF);
+            return i64::write(&val, buf, version);
+        }
+        RawPacketValue::Position(x, y, z).serialize(buf)
+    }
+}
+
+/// One changed block of a Multi Block Change, relative to its chunk
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlockChangeRecord {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+    pub block_id: i32,
+}
+
+impl FieldCodec<BlockChangeRecord> for BlockChangeRecord {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<BlockChangeRecord> {
+        let horizontal = u8::read(buf, version)?;
+        Ok(BlockChangeRecord {
+            x: horizontal >> 4,
+            z: horizontal & 15,
+            y: u8::read(buf, version)?,
+            block_id: VarInt::read(buf, version)?,
+        })
+    }
+
+    fn write<W: Write>(
+        value: &BlockChangeRecord,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        u8::write(&(value.x << 4 | value.z & 15), buf, version)?;
+        u8::write(&value.y, buf, version)?;
+        VarInt::write(&value.block_id, buf, version)
+    }
+}
+
+/// A block destroyed by an Explosion, relative to the explosion
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ExplosionRecord {
+    pub x: i8,
+    pub y: i8,
+    pub z: i8,
+}
+
+impl FieldCodec<ExplosionRecord> for ExplosionRecord {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<ExplosionRecord> {
+        Ok(ExplosionRecord {
+            x: i8::read(buf, version)?,
+            y: i8::read(buf, version)?,
+            z: i8::read(buf, version)?,
+        })
+    }
+
+    fn write<W: Write>(
+        value: &ExplosionRecord,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        i8::write(&value.x, buf, version)?;
+        i8::write(&value.y, buf, version)?;
+        i8::write(&value.z, buf, version)
+    }
+}
+
+/// 1.12 sends a Short item id (-1 for empty), count, damage and NBT instead of the present flag
+impl FieldCodec<Slot> for Slot {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<Slot> {
+        if version >= ProtocolVersion::V1_14_4 {
+            return match RawPacketValueType::Slot.from_buf(buf)? {
+                RawPacketValue::Slot(slot) => Ok(slot),
+                _ => unreachable!(),
+            };
+        }
+        let item_id = i16::read(buf, version)?;
+        if item_id < 0 {
+            return Ok(None);
+        }
+        let count = i8::read(buf, version)?;
+        let damage = i16::read(buf, version)?;
+        let nbt = NbtTag::read(buf, version)?;
+        Ok(Some(ItemStack {
+            item_id: item_id as i32,
+            count,
+            damage,
+            nbt: Some(nbt).filter(|nbt| *nbt != NbtTag::End),
+        }))
+    }
+
+    fn write<W: Write>(value: &Slot, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        if version >= ProtocolVersion::V1_14_4 {
+            return RawPacketValue::Slot(value.clone()).serialize(buf);
+        }
+        match value {
+            None => i16::write(&-1, buf, version),
+            Some(item) => {
+                i16::write(&(item.item_id as i16), buf, version)?;
+                i8::write(&item.count, buf, version)?;
+                i16::write(&item.damage, buf, version)?;
+                let nbt = item.nbt.clone().unwrap_or_default();
+                NbtTag::write(&nbt, buf, version)
+            }
+        }
+    }
+}
+
+/// A Bool followed by the value if it is true
+pub struct Optional<C = Plain>(PhantomData<C>);
+
+impl<T, C: FieldCodec<T>> FieldCodec<Option<T>> for Optional<C> {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<Option<T>> {
+        if bool::read(buf, version)? {
+            C::read(buf, version).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write<W: Write>(value: &Option<T>, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        bool::write(&value.is_some(), buf, version)?;
+        match value {
+            Some(value) => C::write(value, buf, version),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Pose {
+    #[default]
+    Standing,
+    FallFlying,
+    Sleeping,
+    Swimming,
+    SpinAttack,
+    Sneaking,
+    Dying,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParticleData {
+    None,
+    /// block and falling_dust
+    Block(i32),
+    Dust {
+        red: f32,
+        green: f32,
+        blue: f32,
+        scale: f32,
+    },
+    Item(Slot),
+}
+
+/// Particle ids with extra data in 1.14.4
+const PARTICLE_BLOCK: i32 = 3;
+const PARTICLE_DUST: i32 = 14;
+const PARTICLE_FALLING_DUST: i32 = 23;
+const PARTICLE_ITEM: i32 = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    VarInt(i32),
+    Float(f32),
+    String(String),
+    /// JSON text
+    Chat(String),
+    OptChat(Option<String>),
+    Slot(Slot),
+    Boolean(bool),
+    Rotation(f32, f32, f32),
+    Position(Position),
+    OptPosition(Option<Position>),
+    Direction(i32),
+    OptUuid(Option<i128>),
+    OptBlockId(Option<i32>),
+    Nbt(NbtTag),
+    Particle(i32, ParticleData),
+    VillagerData {
+        kind: i32,
+        profession: i32,
+        level: i32,
+    },
+    OptVarInt(Option<i32>),
+    Pose(Pose),
+}
+
+/// Metadata values by index, only the indices the packet carried
+pub type EntityMetadata = BTreeMap<u8, MetadataValue>;
+
+/// Entity metadata as sent since 1.14: index, type and value until the 0xFF index
+pub struct Metadata;
+
+impl FieldCodec<EntityMetadata> for Metadata {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<EntityMetadata> {
+        let mut metadata = EntityMetadata::new();
+        loop {
+            let index = u8::read(buf, version)?;
+            if index == 0xFF {
+                return Ok(metadata);
+            }
+            let value = match VarInt::read(buf, version)? {
+                0 => MetadataValue::Byte(i8::read(buf, version)?),
+                1 => MetadataValue::VarInt(VarInt::read(buf, version)?),
+                2 => MetadataValue::Float(f32::read(buf, version)?),
+                3 => MetadataValue::String(String::read(buf, version)?),
+                4 => MetadataValue::Chat(String::read(buf, version)?),
+                5 => MetadataValue::OptChat(Optional::<Plain>::read(buf, version)?),
+                6 => MetadataValue::Slot(Slot::read(buf, version)?),
+                7 => MetadataValue::Boolean(bool::read(buf, version)?),
+                8 => MetadataValue::Rotation(
+                    f32::read(buf, version)?,
+                    f32::read(buf, version)?,
+                    f32::read(buf, version)?,
+                ),
+                9 => MetadataValue::Position(Position::read(buf, version)?),
+                10 => MetadataValue::OptPosition(Optional::<Plain>::read(buf, version)?),
+                11 => MetadataValue::Direction(VarInt::read(buf, version)?),
+                12 => MetadataValue::OptUuid(Optional::<Plain>::read(buf, version)?),
+                13 => MetadataValue::OptBlockId(match VarInt::read(buf, version)? {
+                    0 => None,
+                    id => Some(id),
+                }),
+                14 => MetadataValue::Nbt(NbtTag::read(buf, version)?),
+                15 => {
+                    let id = VarInt::read(buf, version)?;
+                    let data = match id {
+                        PARTICLE_BLOCK | PARTICLE_FALLING_DUST => {
+                            ParticleData::Block(VarInt::read(buf, version)?)
+                        }
+                        PARTICLE_DUST => ParticleData::Dust {
+                            red: f32::read(buf, version)?,
+                            green: f32::read(buf, version)?,
+                            blue: f32::read(buf, version)?,
+                            scale: f32::read(buf, version)?,
+                        },
+                        PARTICLE_ITEM => ParticleData::Item(Slot::read(buf, version)?),
+                        _ => ParticleData::None,
+                    };
+                    MetadataValue::Particle(id, data)
+                }
+                16 => MetadataValue::VillagerData {
+                    kind: VarInt::read(buf, version)?,
+                    profession: VarInt::read(buf, version)?,
+                    level: VarInt::read(buf, version)?,
+                },
+                17 => MetadataValue::OptVarInt(match VarInt::read(buf, version)? {
+                    0 => None,
+                    value => Some(value - 1),
+                }),
+                18 => MetadataValue::Pose(match VarInt::read(buf, version)? {
+                    0 => Pose::Standing,
+                    1 => Pose::FallFlying,
+                    2 => Pose::Sleeping,
+                    3 => Pose::Swimming,
+                    4 => Pose::SpinAttack,
+                    5 => Pose::Sneaking,
+                    6 => Pose::Dying,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid pose")),
+                }),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unknown entity metadata type",
+                    ))
+                }
+            };
+            metadata.insert(index, value);
+        }
+    }
+
+    fn write<W: Write>(
+        value: &EntityMetadata,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        for (index, value) in value {
+            u8::write(index, buf, version)?;
+            match value {
+                MetadataValue::Byte(v) => {
+                    VarInt::write(&0, buf, version)?;
+                    i8::write(v, buf, version)?;
+                }
+                MetadataValue::VarInt(v) => {
+                    VarInt::write(&1, buf, version)?;
+                    VarInt::write(v, buf, version)?;
+                }
+                MetadataValue::Float(v) => {
+                    VarInt::write(&2, buf, version)?;
+                    f32::write(v, buf, version)?;
+                }
+                MetadataValue::String(v) => {
+                    VarInt::write(&3, buf, version)?;
+                    String::write(v, buf, version)?;
+                }
+                MetadataValue::Chat(v) => {
+                    VarInt::write(&4, buf, version)?;
+                    String::write(v, buf, version)?;
+                }
+                MetadataValue::OptChat(v) => {
+                    VarInt::write(&5, buf, version)?;
+                    Optional::<Plain>::write(v, buf, version)?;
+                }
+                MetadataValue::Slot(v) => {
+                    VarInt::write(&6, buf, version)?;
+                    Slot::write(v, buf, version)?;
+                }
+                MetadataValue::Boolean(v) => {
+                    VarInt::write(&7, buf, version)?;
+                    bool::write(v, buf, version)?;
+                }
+                MetadataValue::Rotation(x, y, z) => {
+                    VarInt::write(&8, buf, version)?;
+                    f32::write(x, buf, version)?;
+                    f32::write(y, buf, version)?;
+                    f32::write(z, buf, version)?;
+                }
+                MetadataValue::Position(v) => {
+                    VarInt::write(&9, buf, version)?;
+                    Position::write(v, buf, version)?;
+                }
+                MetadataValue::OptPosition(v) => {
+                    VarInt::write(&10, buf, version)?;
+                    Optional::<Plain>::write(v, buf, version)?;
+                }
+                MetadataValue::Direction(v) => {
+                    VarInt::write(&11, buf, version)?;
+                    VarInt::write(v, buf, version)?;
+                }
+                MetadataValue::OptUuid(v) => {
+                    VarInt::write(&12, buf, version)?;
+                    Optional::<Plain>::write(v, buf, version)?;
+                }
+                MetadataValue::OptBlockId(v) => {
+                    VarInt::write(&13, buf, version)?;
+                    VarInt::write(&v.unwrap_or(0), buf, version)?;
+                }
+                MetadataValue::Nbt(v) => {
+                    VarInt::write(&14, buf, version)?;
+                    NbtTag::write(v, buf, version)?;
+                }
+                MetadataValue::Particle(id, data) => {
+                    VarInt::write(&15, buf, version)?;
+                    VarInt::write(id, buf, version)?;
+                    match data {
+                        ParticleData::None => {}
+                        ParticleData::Block(block) => VarInt::write(block, buf, version)?,
+                        ParticleData::Dust {
+                            red,
+                            green,
+                            blue,
+                            scale,
+                        } => {
+                            f32::write(red, buf, version)?;
+                            f32::write(green, buf, version)?;
+                            f32::write(blue, buf, version)?;
+                            f32::write(scale, buf, version)?;
+                        }
+                        ParticleData::Item(item) => Slot::write(item, buf, version)?,
+                    }
+                }
+                MetadataValue::VillagerData {
+                    kind,
+                    profession,
+                    level,
+                } => {
+                    VarInt::write(&16, buf, version)?;
+                    VarInt::write(kind, buf, version)?;
+                    VarInt::write(profession, buf, version)?;
+                    VarInt::write(level, buf, version)?;
+                }
+                MetadataValue::OptVarInt(v) => {
+                    VarInt::write(&17, buf, version)?;
+                    VarInt::write(&v.map(|v| v + 1).unwrap_or(0), buf, version)?;
+                }
+                MetadataValue::Pose(v) => {
+                    VarInt::write(&18, buf, version)?;
+                    VarInt::write(&(*v as i32), buf, version)?;
+                }
+            }
+        }
+        u8::write(&0xFF, buf, version)
+    }
+}
+
+/// A property of a player profile, like its skin `textures`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl FieldCodec<PlayerProperty> for PlayerProperty {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<PlayerProperty> {
+        Ok(PlayerProperty {
+            name: String::read(buf, version)?,
+            value: String::read(buf, version)?,
+            signature: Optional::<Plain>::read(buf, version)?,
+        })
+    }
+
+    fn write<W: Write>(
+        value: &PlayerProperty,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        String::write(&value.name, buf, version)?;
+        String::write(&value.value, buf, version)?;
+        Optional::<Plain>::write(&value.signature, buf, version)
+    }
+}
+
+/// A player joining the tab list
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerInfoAdd {
+    pub name: String,
+    pub properties: Vec<PlayerProperty>,
+    pub gamemode: i32,
+    /// In milliseconds
+    pub ping: i32,
+    /// JSON text
+    pub display_name: Option<String>,
+}
+
+impl FieldCodec<PlayerInfoAdd> for PlayerInfoAdd {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<PlayerInfoAdd> {
+        Ok(PlayerInfoAdd {
+            name: String::read(buf, version)?,
+            properties: Array::<VarInt>::read(buf, version)?,
+            gamemode: VarInt::read(buf, version)?,
+            ping: VarInt::read(buf, version)?,
+            display_name: Optional::<Plain>::read(buf, version)?,
+        })
+    }
+
+    fn write<W: Write>(
+        value: &PlayerInfoAdd,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        String::write(&value.name, buf, version)?;
+        Array::<VarInt>::write(&value.properties, buf, version)?;
+        VarInt::write(&value.gamemode, buf, version)?;
+        VarInt::write(&value.ping, buf, version)?;
+        Optional::<Plain>::write(&value.display_name, buf, version)
+    }
+}
+
+/// What a Player Info packet does, each with the UUIDs it applies to
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerInfoAction {
+    AddPlayers(Vec<(i128, PlayerInfoAdd)>),
+    UpdateGamemode(Vec<(i128, i32)>),
+    UpdateLatency(Vec<(i128, i32)>),
+    UpdateDisplayName(Vec<(i128, Option<String>)>),
+    RemovePlayers(Vec<i128>),
+}
+
+/// Reads `count` UUIDs each followed by a `T` read with `read`
+fn read_player_info<R: Read, T>(
+    buf: &mut R,
+    version: ProtocolVersion,
+    read: impl Fn(&mut R, ProtocolVersion) -> IOResult<T>,
+) -> IOResult<Vec<(i128, T)>> {
+    let count = VarInt::read(buf, version)?;
+    (0..count.max(0))
+        .map(|_| Ok((i128::read(buf, version)?, read(buf, version)?)))
+        .collect()
+}
+
+fn write_player_info<W: Write, T>(
+    entries: &[(i128, T)],
+    buf: &mut W,
+    version: ProtocolVersion,
+    write: impl Fn(&T, &mut W, ProtocolVersion) -> IOResult<()>,
+) -> IOResult<()> {
+    VarInt::write(&(entries.len() as i32), buf, version)?;
+    for (uuid, value) in entries {
+        i128::write(uuid, buf, version)?;
+        write(value, buf, version)?;
+    }
+    Ok(())
+}
+
+impl FieldCodec<PlayerInfoAction> for PlayerInfoAction {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<PlayerInfoAction> {
+        Ok(match VarInt::read(buf, version)? {
+            0 => PlayerInfoAction::AddPlayers(read_player_info(buf, version, PlayerInfoAdd::read)?),
+            1 => PlayerInfoAction::UpdateGamemode(read_player_info(buf, version, VarInt::read)?),
+            2 => PlayerInfoAction::UpdateLatency(read_player_info(buf, version, VarInt::read)?),
+            3 => PlayerInfoAction::UpdateDisplayName(read_player_info(
+                buf,
+                version,
+                Optional::<Plain>::read,
+            )?),
+            4 => PlayerInfoAction::RemovePlayers(
+                read_player_info(buf, version, |_, _| Ok(()))?
+                    .into_iter()
+                    .map(|(uuid, _)| uuid)
+                    .collect(),
+            ),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid player info action",
+                ))
+            }
+        })
+    }
+
+    fn write<W: Write>(
+        value: &PlayerInfoAction,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        match value {
+            PlayerInfoAction::AddPlayers(entries) => {
+                VarInt::write(&0, buf, version)?;
+                write_player_info(entries, buf, version, PlayerInfoAdd::write)
+            }
+            PlayerInfoAction::UpdateGamemode(entries) => {
+                VarInt::write(&1, buf, version)?;
+                write_player_info(entries, buf, version, VarInt::write)
+            }
+            PlayerInfoAction::UpdateLatency(entries) => {
+                VarInt::write(&2, buf, version)?;
+                write_player_info(entries, buf, version, VarInt::write)
+            }
+            PlayerInfoAction::UpdateDisplayName(entries) => {
+                VarInt::write(&3, buf, version)?;
+                write_player_info(entries, buf, version, Optional::<Plain>::write)
+            }
+            PlayerInfoAction::RemovePlayers(uuids) => {
+                VarInt::write(&4, buf, version)?;
+                VarInt::write(&(uuids.len() as i32), buf, version)?;
+                uuids
+                    .iter()
+                    .try_for_each(|uuid| i128::write(uuid, buf, version))
+            }
+        }
+    }
+}
+
+/// What a Use Entity packet does to its target. The hand is 0 for the main hand
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityUse {
+    Interact {
+        hand: i32,
+    },
+    Attack,
+    /// Right click on a point of the hitbox, relative to the entity's feet
+    InteractAt {
+        x: f32,
+        y: f32,
+        z: f32,
+        hand: i32,
+    },
+}
+
+impl FieldCodec<EntityUse> for EntityUse {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<EntityUse> {
+        Ok(match VarInt::read(buf, version)? {
+            0 => EntityUse::Interact {
+                hand: VarInt::read(buf, version)?,
+            },
+            1 => EntityUse::Attack,
+            2 => EntityUse::InteractAt {
+                x: f32::read(buf, version)?,
+                y: f32::read(buf, version)?,
+                z: f32::read(buf, version)?,
+                hand: VarInt::read(buf, version)?,
+            },
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid use entity type",
+                ))
+            }
+        })
+    }
+
+    fn write<W: Write>(value: &EntityUse, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        match value {
+            EntityUse::Interact { hand } => {
+                VarInt::write(&0, buf, version)?;
+                VarInt::write(hand, buf, version)
+            }
+            EntityUse::Attack => VarInt::write(&1, buf, version),
+            EntityUse::InteractAt { x, y, z, hand } => {
+                VarInt::write(&2, buf, version)?;
+                f32::write(x, buf, version)?;
+                f32::write(y, buf, version)?;
+                f32::write(z, buf, version)?;
+                VarInt::write(hand, buf, version)
+            }
+        }
+    }
+}
+
+/// What a Combat Event packet reports. Only `EntityDead` matters to a client, the death
+/// screen message comes with it
+#[derive(Debug, Clone, PartialEq)]
+pub enum CombatEvent {
+    EnterCombat,
+    EndCombat {
+        duration: i32,
+        entity_id: i32,
+    },
+    EntityDead {
+        player_id: i32,
+        entity_id: i32,
+        message: String,
+    },
+}
+
+impl FieldCodec<CombatEvent> for CombatEvent {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<CombatEvent> {
+        Ok(match VarInt::read(buf, version)? {
+            0 => CombatEvent::EnterCombat,
+            1 => CombatEvent::EndCombat {
+                duration: VarInt::read(buf, version)?,
+                entity_id: i32::read(buf, version)?,
+            },
+            2 => CombatEvent::EntityDead {
+                player_id: VarInt::read(buf, version)?,
+                entity_id: i32::read(buf, version)?,
+                message: String::read(buf, version)?,
+            },
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid combat event",
+                ))
+            }
         })
     }
+
+    fn write<W: Write>(value: &CombatEvent, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        match value {
+            CombatEvent::EnterCombat => VarInt::write(&0, buf, version),
+            CombatEvent::EndCombat {
+                duration,
+                entity_id,
+            } => {
+                VarInt::write(&1, buf, version)?;
+                VarInt::write(duration, buf, version)?;
+                i32::write(entity_id, buf, version)
+            }
+            CombatEvent::EntityDead {
+                player_id,
+                entity_id,
+                message,
+            } => {
+                VarInt::write(&2, buf, version)?;
+                VarInt::write(player_id, buf, version)?;
+                i32::write(entity_id, buf, version)?;
+                String::write(message, buf, version)
+            }
+        }
+    }
+}
+
+/// Max players of Join Game, an unsigned byte before 1.16 and a VarInt since
+pub struct MaxPlayers;
+
+impl FieldCodec<i32> for MaxPlayers {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<i32> {
+        if version >= ProtocolVersion::V1_16_5 {
+            VarInt::read(buf, version)
+        } else {
+            u8::read(buf, version).map(i32::from)
+        }
+    }
+
+    fn write<W: Write>(value: &i32, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        if version >= ProtocolVersion::V1_16_5 {
+            VarInt::write(value, buf, version)
+        } else {
+            u8::write(&(*value as u8), buf, version)
+        }
+    }
+}
+
+/// A UUID kept as string, sent as string before 1.16 and as 128 bit integer since
+pub struct UuidString;
+
+impl FieldCodec<String> for UuidString {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<String> {
+        if version >= ProtocolVersion::V1_16_5 {
+            i128::read(buf, version).map(uuid_to_string)
+        } else {
+            String::read(buf, version)
+        }
+    }
+
+    fn write<W: Write>(value: &String, buf: &mut W, version: ProtocolVersion) -> IOResult<()> {
+        if version >= ProtocolVersion::V1_16_5 {
+            let uuid = uuid_from_string(value)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid UUID"))?;
+            i128::write(&uuid, buf, version)
+        } else {
+            String::write(value, buf, version)
+        }
+    }
+}
+
+/// The next state of the handshake
+impl FieldCodec<ConnectionState> for ConnectionState {
+    fn read<R: Read>(buf: &mut R, version: ProtocolVersion) -> IOResult<ConnectionState> {
+        match VarInt::read(buf, version)? {
+            1 => Ok(ConnectionState::Status),
+            2 => Ok(ConnectionState::Login),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid next state",
+            )),
+        }
+    }
+
+    fn write<W: Write>(
+        value: &ConnectionState,
+        buf: &mut W,
+        version: ProtocolVersion,
+    ) -> IOResult<()> {
+        let next_state = match value {
+            ConnectionState::Status => 1,
+            _ => 2,
+        };
+        VarInt::write(&next_state, buf, version)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -126,43 +1129,128 @@ pub enum PacketError {
     DeserializeIOError(std::io::Error),
 }
 
+impl From<PacketError> for io::Error {
+    fn from(err: PacketError) -> io::Error {
+        match err {
+            PacketError::DeserializeIOError(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
 impl_packets! {
     Packet, PacketType,
     ///////// C -> S (serverbound)
     // Login state
     None, Server, 0x00, ClientHandshake {
+        protocol_version: i32 as VarInt,
         host_address: String,
         port: u16,
+        next_state: ConnectionState,
     },
     Login, Server, 0x00, ClientJoin {
         player_name: String,
     },
+    Login, Server, 0x01, ClientEncryptionResponse {
+        shared_secret: Vec<u8>,
+        verify_token: Vec<u8>,
+    },
 
     // Status state
-    // ..
+    Status, Server, 0x00, ClientStatusRequest {
+    },
+    Status, Server, 0x01, ClientStatusPing {
+        payload: i64,
+    },
 
     // Play state
-    Play, Server, 0x0F, ClientKeepAlive {
+    Play, Server, [V1_12_2: 0x00, V1_14_4: 0x00, V1_16_5: 0x00], ClientTeleportConfirm {
+        teleport_id: i32 as VarInt,
+    },
+    // status 0 started, 1 cancelled, 2 finished digging
+    Play, Server, [V1_12_2: 0x14, V1_14_4: 0x1A, V1_16_5: 0x1B], ClientPlayerDigging {
+        status: i32 as VarInt,
+        location: Position,
+        face: i8,
+    },
+    // swings the arm, hand 0 is the main hand
+    Play, Server, [V1_12_2: 0x1D, V1_14_4: 0x2A, V1_16_5: 0x2C], ClientAnimation {
+        hand: i32 as VarInt,
+    },
+    // right click on a block face, the cursor is relative to the clicked block
+    Play, Server, [V1_14_4: 0x2C, V1_16_5: 0x2E], ClientPlayerBlockPlacement {
+        hand: i32 as VarInt,
+        location: Position,
+        face: i32 as VarInt,
+        cursor_x: f32,
+        cursor_y: f32,
+        cursor_z: f32,
+        inside_block: bool,
+    },
+    Play, Server, [V1_12_2: 0x0A, V1_14_4: 0x0E, V1_16_5: 0x0E], ClientUseEntity {
+        target: i32 as VarInt,
+        action: EntityUse,
+        #[since(V1_16_5)] sneaking: bool,
+    },
+    // action 0 respawns, 1 asks for the statistics
+    Play, Server, [V1_12_2: 0x03, V1_14_4: 0x04, V1_16_5: 0x04], ClientStatus {
+        action: i32 as VarInt,
+    },
+    // right click in the air with the item in hand
+    Play, Server, [V1_12_2: 0x20, V1_14_4: 0x2D, V1_16_5: 0x2F], ClientUseItem {
+        hand: i32 as VarInt,
+    },
+    Play, Server, [V1_12_2: 0x0B, V1_14_4: 0x0F, V1_16_5: 0x10], ClientKeepAlive {
         magic: i64,
     },
-    Play, Server, 0x04, ClientPlayerPosition {
+    Play, Server, [V1_12_2: 0x0C, V1_14_4: 0x14, V1_16_5: 0x15], ClientPlayer {
+        onground: bool,
+    },
+    Play, Server, [V1_12_2: 0x0D, V1_14_4: 0x11, V1_16_5: 0x12], ClientPlayerPosition {
         x: f64,
         y: f64,
         z: f64,
         onground: bool,
     },
-    Play, Server, 0x05 ,ClientPlayerLook {
+    Play, Server, [V1_12_2: 0x0F, V1_14_4: 0x13, V1_16_5: 0x14], ClientPlayerLook {
         yaw: f32,
         pitch: f32,
         onground: bool,
     },
-    Play, Server, 0x03, ClientChat {
+    Play, Server, [V1_12_2: 0x02, V1_14_4: 0x03, V1_16_5: 0x03], ClientChat {
         message: String,
     },
-    Play, Server, 0x23 ,ClientHeldItemChange {
+    Play, Server, [V1_12_2: 0x1A, V1_14_4: 0x23, V1_16_5: 0x25], ClientHeldItemChange {
+        slot: i16,
+    },
+    Play, Server, [V1_12_2: 0x1B, V1_14_4: 0x26, V1_16_5: 0x28], ClientCreativeInventoryAction {
+        slot: i16,
+        item: Slot,
+    },
+    Play, Server, [V1_14_4: 0x09], ClientClickWindow {
+        window_id: u8,
         slot: i16,
+        button: i8,
+        action_number: i16,
+        mode: i32 as VarInt,
+        // the slot as it was before the click
+        clicked_item: Slot,
+    },
+    Play, Server, [V1_14_4: 0x07], ClientConfirmTransaction {
+        window_id: i8,
+        action_number: i16,
+        accepted: bool,
+    },
+    Play, Server, [V1_14_4: 0x0A], ClientCloseWindow {
+        window_id: u8,
+    },
+    // 0 start sneaking, 1 stop sneaking, 3 start sprinting, 4 stop sprinting
+    Play, Server, [V1_12_2: 0x15, V1_14_4: 0x1B, V1_16_5: 0x1C], ClientEntityAction {
+        entity_id: i32 as VarInt,
+        action: i32 as VarInt,
+        jump_boost: i32 as VarInt,
     },
-    Play, Server, 0x06 ,ClientPlayerPositionAndLook {
+    Play, Server, [V1_12_2: 0x0E, V1_14_4: 0x12, V1_16_5: 0x13], ClientPlayerPositionAndLook {
         x: f64,
         y: f64,
         z: f64,
@@ -172,53 +1260,282 @@ impl_packets! {
     },
     ///////// S -> C (clientbound)
     // Login state
-    Login, Client, 0x03 ,ServerCompressionLevelSet {
-        compression_level: i32,
+    Login, Client, 0x00, ServerLoginDisconnect {
+        reason: String,
+    },
+    Login, Client, 0x03, ServerCompressionLevelSet {
+        compression_level: i32 as VarInt,
     },
-     Login, Client, 0x02 ,ServerLoginSuccess {
-        uuid: String,
+    Login, Client, 0x02, ServerLoginSuccess {
+        uuid: String as UuidString,
         name: String,
     },
-     Login, Client, 0x01 ,ServerEncryptionRequest {
+    Login, Client, 0x01, ServerEncryptionRequest {
         serverid: String,
         pubkey: Vec<u8>,
         verifytoken: Vec<u8>,
     },
     // Status state
-    // ..
+    Status, Client, 0x00, ServerStatusResponse {
+        response: String,
+    },
+    Status, Client, 0x01, ServerStatusPong {
+        payload: i64,
+    },
 
     // Play state
-    Play, Client, 0x20 ,ServerKeepAlive {
+    Play, Client, [V1_12_2: 0x1F, V1_14_4: 0x20, V1_16_5: 0x1F], ServerKeepAlive {
         magic: i64,
     },
-    Play, Client, 0x4E ,ServerWorldTimeUpdate {
+    Play, Client, [V1_12_2: 0x47, V1_14_4: 0x4E, V1_16_5: 0x4E], ServerWorldTimeUpdate {
         age: i64,
         time: i64,
     },
-    Play, Client, 0x1A ,ServerDisconnectPacket {
+    Play, Client, [V1_12_2: 0x1A, V1_14_4: 0x1A, V1_16_5: 0x19], ServerDisconnectPacket {
         reason: String,
     },
-    Play, Client, 0x0E ,ServerChatPacket {
+    Play, Client, [V1_12_2: 0x0F, V1_14_4: 0x0E, V1_16_5: 0x0E], ServerChatPacket {
         message: String,
         position: i8,
+        #[since(V1_16_5)] sender: i128,
     },
-    Play, Client, 0x25 ,ServerJoinGame {
+    Play, Client, [V1_12_2: 0x23, V1_14_4: 0x25, V1_16_5: 0x24], ServerJoinGame {
         entity_id: i32,
+        #[since(V1_16_5)] is_hardcore: bool,
         gamemode: u8,
-        dimension: i8,
-        difficulty: u8,
-        max_players: u8,
-        level_type: String,
+        #[since(V1_16_5)] previous_gamemode: i8,
+        #[since(V1_16_5)] world_names: Vec<String> as Array,
+        #[since(V1_16_5)] dimension_codec: NbtTag,
+        // 1.16 replaced the dimension id with its type as NBT plus the world name
+        #[since(V1_16_5)] dimension_type: NbtTag,
+        #[since(V1_16_5)] world_name: String,
+        #[until(V1_14_4)] dimension: i32,
+        #[until(V1_12_2)] difficulty: u8,
+        #[since(V1_16_5)] hashed_seed: i64,
+        max_players: i32 as MaxPlayers,
+        #[until(V1_14_4)] level_type: String,
+        #[since(V1_14_4)] view_distance: i32 as VarInt,
         reduced_debug_info: bool,
+        #[since(V1_16_5)] enable_respawn_screen: bool,
+        #[since(V1_16_5)] is_debug: bool,
+        #[since(V1_16_5)] is_flat: bool,
+    },
+    Play, Client, [V1_12_2: 0x35, V1_14_4: 0x3A, V1_16_5: 0x39], ServerRespawn {
+        #[since(V1_16_5)] dimension_type: NbtTag,
+        #[since(V1_16_5)] world_name: String,
+        #[until(V1_14_4)] dimension: i32,
+        #[until(V1_12_2)] difficulty: u8,
+        #[since(V1_16_5)] hashed_seed: i64,
+        gamemode: u8,
+        #[since(V1_16_5)] previous_gamemode: i8,
+        #[since(V1_16_5)] is_debug: bool,
+        #[since(V1_16_5)] is_flat: bool,
+        #[since(V1_16_5)] copy_metadata: bool,
+        #[until(V1_14_4)] level_type: String,
+    },
+    // health 0 or less means dead, food and saturation go up to 20
+    Play, Client, [V1_12_2: 0x41, V1_14_4: 0x48, V1_16_5: 0x49], ServerUpdateHealth {
+        health: f32,
+        food: i32 as VarInt,
+        saturation: f32,
+    },
+    Play, Client, [V1_12_2: 0x2D, V1_14_4: 0x32, V1_16_5: 0x31], ServerCombatEvent {
+        event: CombatEvent,
+    },
+    // 1.12 sends the window type as string and a slot count
+    Play, Client, [V1_14_4: 0x2E], ServerOpenWindow {
+        window_id: i32 as VarInt,
+        kind: i32 as VarInt,
+        title: String,
+    },
+    Play, Client, [V1_14_4: 0x15], ServerWindowProperty {
+        window_id: u8,
+        property: i16,
+        value: i16,
+    },
+    Play, Client, [V1_14_4: 0x13], ServerCloseWindow {
+        window_id: u8,
+    },
+    Play, Client, [V1_14_4: 0x12], ServerConfirmTransaction {
+        window_id: i8,
+        action_number: i16,
+        accepted: bool,
+    },
+    Play, Client, [V1_14_4: 0x3F], ServerHeldItemChange {
+        slot: i8,
+    },
+    Play, Client, [V1_12_2: 0x14, V1_14_4: 0x14, V1_16_5: 0x13], ServerWindowItems {
+        window_id: u8,
+        slots: Vec<Slot> as Array<Short>,
+    },
+    Play, Client, [V1_12_2: 0x16, V1_14_4: 0x16, V1_16_5: 0x15], ServerSetSlot {
+        window_id: i8,
+        slot: i16,
+        item: Slot,
+    },
+    Play, Client, [V1_14_4: 0x0B], ServerBlockChange {
+        location: Position,
+        block_id: i32 as VarInt,
+    },
+    // 1.16 packs the records into VarLongs of a chunk section
+    Play, Client, [V1_14_4: 0x0F], ServerMultiBlockChange {
+        chunk_x: i32,
+        chunk_z: i32,
+        records: Vec<BlockChangeRecord> as Array,
+    },
+    Play, Client, [V1_14_4: 0x1D], ServerUnloadChunk {
+        chunk_x: i32,
+        chunk_z: i32,
+    },
+    Play, Client, [V1_14_4: 0x40], ServerUpdateViewPosition {
+        chunk_x: i32 as VarInt,
+        chunk_z: i32 as VarInt,
+    },
+    Play, Client, [V1_14_4: 0x1C], ServerExplosion {
+        x: f32,
+        y: f32,
+        z: f32,
+        strength: f32,
+        records: Vec<ExplosionRecord> as Array<i32>,
+        player_motion_x: f32,
+        player_motion_y: f32,
+        player_motion_z: f32,
+    },
+    // 1.12 sends a byte as object type and 1.16 dropped the metadata of mobs and players
+    Play, Client, [V1_14_4: 0x00], ServerSpawnObject {
+        entity_id: i32 as VarInt,
+        uuid: i128,
+        kind: i32 as VarInt,
+        x: f64,
+        y: f64,
+        z: f64,
+        pitch: f32 as Angle,
+        yaw: f32 as Angle,
+        data: i32,
+        velocity_x: i16,
+        velocity_y: i16,
+        velocity_z: i16,
+    },
+    Play, Client, [V1_14_4: 0x03], ServerSpawnMob {
+        entity_id: i32 as VarInt,
+        uuid: i128,
+        kind: i32 as VarInt,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32 as Angle,
+        pitch: f32 as Angle,
+        head_yaw: f32 as Angle,
+        velocity_x: i16,
+        velocity_y: i16,
+        velocity_z: i16,
+        metadata: EntityMetadata as Metadata,
+    },
+    Play, Client, [V1_14_4: 0x05], ServerSpawnPlayer {
+        entity_id: i32 as VarInt,
+        uuid: i128,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32 as Angle,
+        pitch: f32 as Angle,
+        metadata: EntityMetadata as Metadata,
+    },
+    // deltas are in 1/4096 blocks
+    Play, Client, [V1_14_4: 0x28], ServerEntityRelativeMove {
+        entity_id: i32 as VarInt,
+        delta_x: i16,
+        delta_y: i16,
+        delta_z: i16,
+        onground: bool,
+    },
+    Play, Client, [V1_14_4: 0x29], ServerEntityLookAndRelativeMove {
+        entity_id: i32 as VarInt,
+        delta_x: i16,
+        delta_y: i16,
+        delta_z: i16,
+        yaw: f32 as Angle,
+        pitch: f32 as Angle,
+        onground: bool,
+    },
+    Play, Client, [V1_14_4: 0x2A], ServerEntityLook {
+        entity_id: i32 as VarInt,
+        yaw: f32 as Angle,
+        pitch: f32 as Angle,
+        onground: bool,
+    },
+    Play, Client, [V1_14_4: 0x56], ServerEntityTeleport {
+        entity_id: i32 as VarInt,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32 as Angle,
+        pitch: f32 as Angle,
+        onground: bool,
+    },
+    // in 1/8000 blocks per tick
+    Play, Client, [V1_14_4: 0x45], ServerEntityVelocity {
+        entity_id: i32 as VarInt,
+        velocity_x: i16,
+        velocity_y: i16,
+        velocity_z: i16,
+    },
+    Play, Client, [V1_14_4: 0x3B], ServerEntityHeadLook {
+        entity_id: i32 as VarInt,
+        head_yaw: f32 as Angle,
+    },
+    Play, Client, [V1_12_2: 0x2E, V1_14_4: 0x33, V1_16_5: 0x32], ServerPlayerInfo {
+        action: PlayerInfoAction,
+    },
+    Play, Client, [V1_14_4: 0x43], ServerEntityMetadata {
+        entity_id: i32 as VarInt,
+        metadata: EntityMetadata as Metadata,
+    },
+    Play, Client, [V1_14_4: 0x37], ServerDestroyEntities {
+        entity_ids: Vec<i32> as Array<VarInt, VarInt>,
+    },
+    // amplifier 0 is level I, flags: 0x01 ambient, 0x02 show particles, 0x04 show icon
+    Play, Client, [V1_14_4: 0x59], ServerEntityEffect {
+        entity_id: i32 as VarInt,
+        effect_id: i8,
+        amplifier: i8,
+        duration: i32 as VarInt,
+        flags: i8,
     },
-    Play, Client, 0x35 ,ServerPlayerPositionAndLook {
+    Play, Client, [V1_14_4: 0x38], ServerRemoveEntityEffect {
+        entity_id: i32 as VarInt,
+        effect_id: i8,
+    },
+    Play, Client, [V1_14_4: 0x5C, V1_16_5: 0x07], ServerAcknowledgePlayerDigging {
+        location: Position,
+        block: i32 as VarInt,
+        status: i32 as VarInt,
+        successful: bool,
+    },
+    // 1.12 has no heightmaps and 1.16 moved the biomes out of the data
+    Play, Client, [V1_14_4: 0x21], ServerChunkData {
+        x: i32,
+        z: i32,
+        full_chunk: bool,
+        primary_bit_mask: i32 as VarInt,
+        heightmaps: NbtTag,
+        data: Vec<u8>,
+        block_entities: Vec<NbtTag> as Array,
+    },
+    // 1.16 sends a list of equipment slots instead
+    Play, Client, [V1_12_2: 0x3F, V1_14_4: 0x46], ServerEntityEquipment {
+        entity_id: i32 as VarInt,
+        equipment_slot: i32 as VarInt,
+        item: Slot,
+    },
+    Play, Client, [V1_12_2: 0x2F, V1_14_4: 0x35, V1_16_5: 0x34], ServerPlayerPositionAndLook {
         x: f64,
         y: f64,
         z: f64,
         yaw: f32,
         pitch: f32,
         flags: i8,
-        teleportid: i32,
+        teleportid: i32 as VarInt,
     },
 }
 
@@ -233,41 +1550,51 @@ impl Packet {
     {
         let packet_len = buf
             .read_var_i32()
-            .map_err(|e| PacketError::DeserializeIOError(e))?;
-        let mut packet = vec![0u8; packet_len as usize];
-        buf.read_exact(&mut packet)
-            .map_err(|e| PacketError::DeserializeIOError(e))?;
-        let mut packet_cursor = Cursor::new(packet.clone());
-        let mut packet_data_cursor = Cursor::new(vec![0u8; packet_len as usize]);
-
-        if let Enabled(compression_level) = connection.compression {
+            .map_err(PacketError::DeserializeIOError)?;
+        if !(0..=MAX_FRAME_LENGTH).contains(&packet_len) {
+            return Err(PacketError::MalformedPacket("invalid frame length"));
+        }
+        let packet = read_exact_len(buf, packet_len).map_err(PacketError::DeserializeIOError)?;
+        Packet::from_frame(packet, connection)
+    }
+
+    /// Decodes one complete frame (without its length prefix) into a Packet
+    pub fn from_frame(
+        packet: Vec<u8>,
+        connection: &MinecraftConnection,
+    ) -> Result<Packet, PacketError> {
+        let mut packet_cursor = Cursor::new(packet);
+        let mut packet_data_cursor;
+
+        if let Enabled(_) = connection.compression {
             let uncompressed_size = packet_cursor
                 .read_var_i32()
-                .map_err(|e| PacketError::DeserializeIOError(e))?;
+                .map_err(PacketError::DeserializeIOError)?;
 
+            if !(0..=MAX_UNCOMPRESSED_LENGTH).contains(&uncompressed_size) {
+                return Err(PacketError::MalformedPacket("invalid uncompressed length"));
+            }
             if uncompressed_size != 0 {
                 let mut new = Vec::with_capacity(uncompressed_size as usize);
                 {
-                    let mut reader = ZlibDecoder::new(packet_cursor);
-                    match reader.read_to_end(&mut new) {
-                        Err(e) => {
-                            dbg!(e);
-                        }
-                        Ok(_) => (),
-                    };
+                    // a zip bomb can't inflate past the size it claims
+                    let mut reader = ZlibDecoder::new(packet_cursor).take(uncompressed_size as u64);
+                    reader
+                        .read_to_end(&mut new)
+                        .map_err(PacketError::DeserializeIOError)?;
                 }
 
                 packet_data_cursor = io::Cursor::new(new);
             } else {
-                packet_data_cursor = packet_cursor.clone();
+                packet_data_cursor = packet_cursor;
             }
         } else {
-            packet_data_cursor = packet_cursor.clone();
+            packet_data_cursor = packet_cursor;
         }
 
         let type_id = packet_data_cursor
             .read_var_i32()
-            .map_err(|e| PacketError::DeserializeIOError(e))?;
+            .map_err(PacketError::DeserializeIOError)?;
         //println!("len: {:?}  data: {:X?}", packet_len, packet_data.clone());
         /*
                 println!(
@@ -277,207 +1604,28 @@ impl Packet {
         */
         // println!("state: {:10?}, id: 0x{:02X}", connection.state, type_id);
 
-        let packet_type =
-            PacketType::from_state_and_id_and_direction(connection.state, type_id, BoundTo::Client);
+        let packet_type = PacketType::from_state_and_id_and_direction(
+            connection.protocol_version,
+            connection.state,
+            type_id,
+            BoundTo::Client,
+        );
 
         if let Some(packet_type) = packet_type {
             println!("PacketType: {:?} ", packet_type);
-            match packet_type {
-                PacketType::ServerEncryptionRequest => {
-                    let mut sbuf = vec![0u8; 20 as usize];
-                    packet_data_cursor.read_exact(&mut sbuf);
-                    let serverid = String::from_utf8_lossy(&sbuf).to_string();
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::varint, RawPacketValueType::varint],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::varint(pubkeylen), RawPacketValue::varint(vertokenlen)] =
-                        packet_fields.as_slice()
-                    {
-                        let mut pubkey = vec![0u8; *pubkeylen as usize];
-                        packet_data_cursor.read_exact(&mut pubkey);
-                        let mut verifytoken = vec![0u8; *vertokenlen as usize];
-                        packet_data_cursor.read_exact(&mut verifytoken);
-
-                        Ok(Packet::ServerEncryptionRequest {
-                            serverid,
-                            pubkey,
-                            verifytoken,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("neger"))
-                    }
-                }
-                PacketType::ServerDisconnectPacket => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::String],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::String(reason)] = packet_fields.as_slice() {
-                        Ok(Packet::ServerDisconnectPacket {
-                            reason: reason.clone(),
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("join packet romped"))
-                    }
-                }
-                // state:Login
-                PacketType::ServerLoginSuccess => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::String, RawPacketValueType::String],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::String(uuid), RawPacketValue::String(name)] =
-                        packet_fields.as_slice()
-                    {
-                        Ok(Packet::ServerLoginSuccess {
-                            uuid: uuid.clone(),
-                            name: name.clone(),
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("join packet romped"))
-                    }
-                }
+            let packet = Packet::read_fields(
+                packet_type,
+                &mut packet_data_cursor,
+                connection.protocol_version,
+            )
+            .map_err(PacketError::DeserializeIOError)?;
 
-                PacketType::ServerCompressionLevelSet => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::varint],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::varint(compression_level)] = packet_fields.as_slice() {
-                        Ok(Packet::ServerCompressionLevelSet {
-                            compression_level: *compression_level,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("compression packet romped"))
-                    }
-                }
-
-                //  state: Play
-                PacketType::ServerKeepAlive => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::long],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::long(magic)] = packet_fields.as_slice() {
-                        Ok(Packet::ServerKeepAlive { magic: *magic })
-                    } else {
-                        Err(PacketError::MalformedPacket("KeepAlive packet was BRRRRed"))
-                    }
-                }
-                PacketType::ServerJoinGame => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[
-                            RawPacketValueType::int,
-                            RawPacketValueType::ubyte,
-                            RawPacketValueType::byte,
-                            RawPacketValueType::ubyte,
-                            RawPacketValueType::ubyte,
-                            RawPacketValueType::String,
-                            RawPacketValueType::boolean,
-                        ],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::int(entity_id), RawPacketValue::ubyte(gamemode), RawPacketValue::byte(dimension), RawPacketValue::ubyte(difficulty), RawPacketValue::ubyte(max_players), RawPacketValue::String(level_type), RawPacketValue::boolean(reduced_debug_info)] =
-                        packet_fields.as_slice()
-                    {
-                        Ok(Packet::ServerJoinGame {
-                            entity_id: *entity_id,
-                            gamemode: *gamemode,
-                            dimension: *dimension,
-                            difficulty: *difficulty,
-                            max_players: *max_players,
-                            level_type: level_type.clone(),
-                            reduced_debug_info: *reduced_debug_info,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("Handshake packet was BRRRRed"))
-                    }
-                }
-                PacketType::ServerChatPacket => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::String, RawPacketValueType::byte],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::String(message), RawPacketValue::byte(position)] =
-                        packet_fields.as_slice()
-                    {
-                        Ok(Packet::ServerChatPacket {
-                            message: message.clone(),
-                            position: *position,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("Handshake packet was BRRRRed"))
-                    }
-                }
-                PacketType::ServerWorldTimeUpdate => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[RawPacketValueType::long, RawPacketValueType::long],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::long(age), RawPacketValue::long(time)] =
-                        packet_fields.as_slice()
-                    {
-                        Ok(Packet::ServerWorldTimeUpdate {
-                            age: *age,
-                            time: *time,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("Handshake packet was BRRRRed"))
-                    }
-                }
-                PacketType::ServerPlayerPositionAndLook => {
-                    let packet_fields = read_values_from_template(
-                        &mut packet_data_cursor,
-                        &[
-                            RawPacketValueType::double,
-                            RawPacketValueType::double,
-                            RawPacketValueType::double,
-                            RawPacketValueType::float,
-                            RawPacketValueType::float,
-                            RawPacketValueType::byte,
-                            RawPacketValueType::varint,
-                        ],
-                    )
-                    .map_err(|io_err| PacketError::DeserializeIOError(io_err))?;
-
-                    if let [RawPacketValue::double(x), RawPacketValue::double(y), RawPacketValue::double(z), RawPacketValue::float(yaw), RawPacketValue::float(pitch), RawPacketValue::byte(flags), RawPacketValue::varint(teleportid)] =
-                        packet_fields.as_slice()
-                    {
-                        Ok(Packet::ServerPlayerPositionAndLook {
-                            x: *x,
-                            y: *y,
-                            z: *z,
-                            yaw: *yaw,
-                            pitch: *pitch,
-                            flags: *flags,
-                            teleportid: *teleportid,
-                        })
-                    } else {
-                        Err(PacketError::MalformedPacket("Handshake packet was BRRRRed"))
-                    }
-                }
-                (p) => {
-                    dbg!(p);
-                    Err(PacketError::MalformedPacket("Handshake packet was BRRRRed"))
-                }
+            if packet_data_cursor.position() as usize != packet_data_cursor.get_ref().len() {
+                return Err(PacketError::MalformedPacket(
+                    "packet longer than its template",
+                ));
             }
+            Ok(packet)
         } else {
             Err(PacketError::UnknownPacketIdentifier {
                 id: type_id,
@@ -488,183 +1636,359 @@ impl Packet {
 
     /// Takes the packet and serializes it for the server to receive
     pub fn serialize(self, connection: &MinecraftConnection) -> IOResult<Vec<u8>> {
-        let mut buf = Vec::new();
-        let my_id = self.ty().id();
+        let my_id = self.ty().id(connection.protocol_version).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:?} does not exist in {}",
+                    self.ty(),
+                    connection.protocol_version.name()
+                ),
+            )
+        })?;
 
-        Ok(match self {
-            Packet::ClientHandshake { host_address, port } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[
-                        RawPacketValue::varint(498), //TODO: CONST
-                        RawPacketValue::String(host_address),
-                        RawPacketValue::ushort(port),
-                        RawPacketValue::varint(2),
-                    ],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientJoin { player_name } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[RawPacketValue::String(player_name)],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientKeepAlive { magic } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[RawPacketValue::long(magic)],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientPlayerPositionAndLook {
-                x,
-                y,
-                z,
-                yaw,
-                pitch,
-                onground,
-            } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[
-                        RawPacketValue::double(x),
-                        RawPacketValue::double(y),
-                        RawPacketValue::double(z),
-                        RawPacketValue::float(yaw),
-                        RawPacketValue::float(pitch),
-                        RawPacketValue::boolean(onground),
-                    ],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientPlayerPosition { x, y, z, onground } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[
-                        RawPacketValue::double(x),
-                        RawPacketValue::double(y),
-                        RawPacketValue::double(z),
-                        RawPacketValue::boolean(onground),
-                    ],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientPlayerPositionAndLook {
-                x,
-                y,
-                z,
-                yaw,
-                pitch,
-                onground,
-            } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[
-                        RawPacketValue::double(x),
-                        RawPacketValue::double(y),
-                        RawPacketValue::double(z),
-                        RawPacketValue::float(yaw),
-                        RawPacketValue::float(pitch),
-                        RawPacketValue::boolean(onground),
-                    ],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientChat { message } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[RawPacketValue::String(message)],
-                    &connection.compression,
-                )?;
-                buf
-            }
-            Packet::ClientHeldItemChange { slot } => {
-                write_packet_fields(
-                    &mut buf,
-                    my_id,
-                    &[RawPacketValue::short(slot)],
-                    &connection.compression,
-                )?;
-                buf
-            }
+        let mut packet_data = Vec::new();
+        packet_data.write_var_i32(my_id)?;
+        self.write_fields(&mut packet_data, connection.protocol_version)?;
 
-            /*Packet::ServerCompressionLevelSet { .. } => {}
-            Packet::ServerLoginSuccess { .. } => {}*/
-            p => unimplemented!("this packet is not serializable: {:?}", p),
-        })
+        let mut buf = Vec::new();
+        write_packet(&mut buf, packet_data, &connection.compression)?;
+        Ok(buf)
     }
 }
 
-pub fn read_values_from_template(
-    buf: &mut Cursor<Vec<u8>>,
-    template: &[RawPacketValueType],
-) -> IOResult<Vec<RawPacketValue>> {
-    let mut out = Vec::with_capacity(template.len());
-    for ty in template {
-        out.push(ty.from_buf(buf)?);
-        // println!("reading data {:X?}", &*buf);
-    }
-
-    assert_eq!(
-        buf.get_ref().len(),
-        buf.position() as usize,
-        "packet malformed (or packet template ({:?})) ",
-        template
-    );
+/// Formats a UUID sent as 128 bit integer the way older versions send it as string
+pub fn uuid_to_string(uuid: i128) -> String {
+    let uuid = uuid as u128;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        uuid >> 96,
+        (uuid >> 80) & 0xFFFF,
+        (uuid >> 64) & 0xFFFF,
+        (uuid >> 48) & 0xFFFF,
+        uuid & 0xFFFF_FFFF_FFFF
+    )
+}
 
-    Ok(out)
+pub fn uuid_from_string(uuid: &str) -> Option<i128> {
+    u128::from_str_radix(&uuid.replace("-", ""), 16)
+        .ok()
+        .map(|uuid| uuid as i128)
 }
 
-pub fn write_packet_fields<W>(
+/// Frames the packet data (id + fields) and compresses it if the threshold is reached
+pub fn write_packet<W>(
     buf: &mut W,
-    packet_type_id: i32,
-    template: &[RawPacketValue],
+    packet_data: Vec<u8>,
     compression_state: &CompressionStatus,
 ) -> IOResult<()>
 where
     W: Write,
 {
-    let mut temp_buf = Vec::new();
-    let mut temp_cursor = Cursor::new(&mut temp_buf);
+    let mut frame = Vec::new();
+    if let Enabled(threshold) = compression_state {
+        if packet_data.len() as i32 >= *threshold {
+            frame.write_var_i32(packet_data.len() as i32)?;
+            let mut write = ZlibEncoder::new(io::Cursor::new(packet_data), Compression::default());
+            write.read_to_end(&mut frame)?;
+        } else {
+            frame.write_var_i32(0)?; // Wenn compression dann anderes packetspec und weil hier an ist aber das packet zu klein ist eine 0
+            frame.extend_from_slice(&packet_data);
+        }
+    } else {
+        frame = packet_data;
+    }
+    buf.write_var_i32(frame.len() as i32)?; // schreibt als ERSTES in den buffer
+    buf.write_all(&frame)?; // hängt den rest dran
+
+    Ok(())
+}
 
-    temp_cursor.write_var_i32(packet_type_id)?;
-    for ty in template {
-        ty.serialize(&mut temp_cursor);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nbt::NbtCompound;
+    use std::net::SocketAddrV4;
+
+    fn connection() -> MinecraftConnection {
+        let mut connection = MinecraftConnection::new(
+            SocketAddrV4::new([127, 0, 0, 1].into(), 25565),
+            "bot".into(),
+        );
+        connection.state = ConnectionState::Play;
+        connection
     }
-    let mut extra = 0;
-    if let Enabled(threshold) = compression_state {
-        extra = 1;
-        if temp_buf.len() as i32 > *threshold {
-            let uncompressed_size = temp_buf.len();
-            let mut new = Vec::new();
-            new.write_var_i32(uncompressed_size as i32);
-            let mut write = ZlibEncoder::new(io::Cursor::new(temp_buf), Compression::default());
-            write.read_to_end(&mut new)?;
-            temp_buf = new;
+
+    fn varint(value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_var_i32(value).unwrap();
+        buf
+    }
+
+    #[test]
+    fn negative_string_length_is_invalid() {
+        let err = String::read(&mut Cursor::new(varint(-1)), ProtocolVersion::V1_14_4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn byte_array_longer_than_the_frame_fails_without_allocating() {
+        let mut buf = varint(i32::MAX);
+        buf.extend_from_slice(&[1, 2, 3]);
+        let err = Vec::<u8>::read(&mut Cursor::new(buf), ProtocolVersion::V1_14_4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn negative_frame_length_is_malformed() {
+        let result = Packet::deserialize(&mut Cursor::new(varint(-5)), &connection());
+        assert!(matches!(result, Err(PacketError::MalformedPacket(_))));
+    }
+
+    #[test]
+    fn string_roundtrip() {
+        let mut buf = Vec::new();
+        String::write(&"größe".to_string(), &mut buf, ProtocolVersion::V1_14_4).unwrap();
+        let value = String::read(&mut Cursor::new(buf), ProtocolVersion::V1_14_4).unwrap();
+        assert_eq!(value, "größe");
+    }
+
+    fn roundtrip<T, C: FieldCodec<T>>(value: &T, version: ProtocolVersion) -> (T, Vec<u8>) {
+        let mut buf = Vec::new();
+        C::write(value, &mut buf, version).unwrap();
+        let mut cursor = Cursor::new(&buf);
+        let read = C::read(&mut cursor, version).unwrap();
+        assert_eq!(cursor.position() as usize, buf.len(), "bytes left over");
+        (read, buf)
+    }
+
+    fn named_item() -> ItemStack {
+        let mut display = NbtCompound::new();
+        display.insert("Name".into(), NbtTag::String(r#"{"text":"Pick"}"#.into()));
+        let mut nbt = NbtCompound::new();
+        nbt.insert("Damage".into(), NbtTag::Int(7));
+        nbt.insert("display".into(), NbtTag::Compound(display));
+        ItemStack {
+            nbt: Some(NbtTag::Compound(nbt)),
+            ..ItemStack::new(552, 1)
         }
     }
-    buf.write_var_i32(temp_buf.len() as i32 + extra)?; // schreibt als ERSTES in den buffer
-    if let Enabled(threshold) = compression_state {
-        if *threshold > temp_buf.len() as i32 {
-            buf.write_var_i32(0 as i32)?; // Wenn compression dann anderes packetspec und weil hier an ist aber das packet zu klein ist eine 0
+
+    #[test]
+    fn slots_roundtrip() {
+        let v1_14 = ProtocolVersion::V1_14_4;
+        let (empty, bytes) = roundtrip::<Slot, Slot>(&None, v1_14);
+        assert_eq!((empty, bytes), (None, vec![0]));
+        let (plain, bytes) = roundtrip::<Slot, Slot>(&Some(ItemStack::new(1, 64)), v1_14);
+        assert_eq!(plain, Some(ItemStack::new(1, 64)));
+        // present, id, count and an empty NBT
+        assert_eq!(bytes, vec![1, 1, 64, 0]);
+
+        let item = named_item();
+        let (read, _) = roundtrip::<Slot, Slot>(&Some(item.clone()), v1_14);
+        let read = read.unwrap();
+        assert_eq!(read, item);
+        assert_eq!(read.damage(), 7);
+        assert_eq!(read.display_name(), Some(r#"{"text":"Pick"}"#));
+    }
+
+    #[test]
+    fn slots_before_1_13_carry_damage() {
+        let v1_12 = ProtocolVersion::V1_12_2;
+        let (empty, bytes) = roundtrip::<Slot, Slot>(&None, v1_12);
+        assert_eq!((empty, bytes), (None, vec![0xFF, 0xFF]));
+        let item = ItemStack {
+            damage: 3,
+            ..ItemStack::new(35, 2)
+        };
+        let (read, bytes) = roundtrip::<Slot, Slot>(&Some(item.clone()), v1_12);
+        assert_eq!(read, Some(item));
+        assert_eq!(bytes, vec![0, 35, 2, 0, 3, 0]);
+        assert_eq!(read.unwrap().damage(), 3);
+    }
+
+    #[test]
+    fn metadata_roundtrips_every_type() {
+        let values = vec![
+            MetadataValue::Byte(-3),
+            MetadataValue::VarInt(300),
+            MetadataValue::Float(0.25),
+            MetadataValue::String("text".into()),
+            MetadataValue::Chat(r#"{"text":"hi"}"#.into()),
+            MetadataValue::OptChat(None),
+            MetadataValue::OptChat(Some(r#"{"text":"name"}"#.into())),
+            MetadataValue::Slot(Some(named_item())),
+            MetadataValue::Boolean(true),
+            MetadataValue::Rotation(1.0, -2.0, 3.5),
+            MetadataValue::Position(Position {
+                x: -5,
+                y: 70,
+                z: 33,
+            }),
+            MetadataValue::OptPosition(Some(Position { x: 1, y: 2, z: 3 })),
+            MetadataValue::Direction(4),
+            MetadataValue::OptUuid(Some(-1)),
+            MetadataValue::OptBlockId(None),
+            MetadataValue::OptBlockId(Some(9)),
+            MetadataValue::Nbt(NbtTag::Compound(NbtCompound::new())),
+            MetadataValue::Particle(
+                PARTICLE_DUST,
+                ParticleData::Dust {
+                    red: 1.0,
+                    green: 0.0,
+                    blue: 0.5,
+                    scale: 2.0,
+                },
+            ),
+            MetadataValue::Particle(PARTICLE_ITEM, ParticleData::Item(None)),
+            MetadataValue::Particle(PARTICLE_BLOCK, ParticleData::Block(12)),
+            MetadataValue::Particle(0, ParticleData::None),
+            MetadataValue::VillagerData {
+                kind: 1,
+                profession: 2,
+                level: 3,
+            },
+            MetadataValue::OptVarInt(None),
+            MetadataValue::OptVarInt(Some(0)),
+            MetadataValue::Pose(Pose::Sneaking),
+        ];
+        let metadata: EntityMetadata = values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (index as u8, value))
+            .collect();
+        let (read, bytes) = roundtrip::<_, Metadata>(&metadata, ProtocolVersion::V1_14_4);
+        assert_eq!(read, metadata);
+        assert_eq!(bytes.last(), Some(&0xFF));
+    }
+
+    #[test]
+    fn metadata_wire_format() {
+        // index 8, Float type, then the value, index 6, Pose type, sneaking, end
+        let bytes = vec![8, 2, 0x3F, 0x80, 0, 0, 6, 18, 5, 0xFF];
+        let metadata = Metadata::read(&mut Cursor::new(bytes), ProtocolVersion::V1_14_4).unwrap();
+        assert_eq!(metadata[&8], MetadataValue::Float(1.0));
+        assert_eq!(metadata[&6], MetadataValue::Pose(Pose::Sneaking));
+
+        let unknown = vec![0, 40, 0, 0xFF];
+        let err = Metadata::read(&mut Cursor::new(unknown), ProtocolVersion::V1_14_4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let pose = vec![6, 18, 7, 0xFF];
+        assert!(Metadata::read(&mut Cursor::new(pose), ProtocolVersion::V1_14_4).is_err());
+    }
+
+    #[test]
+    fn player_info_roundtrips_every_action() {
+        let add = PlayerInfoAdd {
+            name: "Notch".into(),
+            properties: vec![PlayerProperty {
+                name: "textures".into(),
+                value: "e30=".into(),
+                signature: Some("c2ln".into()),
+            }],
+            gamemode: 1,
+            ping: 42,
+            display_name: Some(r#"{"text":"The Notch"}"#.into()),
+        };
+        let actions = vec![
+            PlayerInfoAction::AddPlayers(vec![(7, add)]),
+            PlayerInfoAction::UpdateGamemode(vec![(7, 3), (-8, 0)]),
+            PlayerInfoAction::UpdateLatency(vec![(7, 250)]),
+            PlayerInfoAction::UpdateDisplayName(vec![(7, None)]),
+            PlayerInfoAction::RemovePlayers(vec![7, -8]),
+        ];
+        for action in &actions {
+            let (read, _) = roundtrip::<_, PlayerInfoAction>(action, ProtocolVersion::V1_14_4);
+            assert_eq!(&read, action);
         }
+
+        // action, count, then the UUIDs
+        let (_, bytes) = roundtrip::<_, PlayerInfoAction>(
+            &PlayerInfoAction::RemovePlayers(vec![1]),
+            ProtocolVersion::V1_14_4,
+        );
+        let mut expected = vec![4, 1];
+        expected.extend_from_slice(&1i128.to_be_bytes());
+        assert_eq!(bytes, expected);
+        let mut invalid = Cursor::new(vec![5, 0]);
+        assert!(PlayerInfoAction::read(&mut invalid, ProtocolVersion::V1_14_4).is_err());
     }
-    buf.write_all(&temp_buf)?; // hängt den rest dran
 
-    Ok(())
+    #[test]
+    fn packets_roundtrip_with_version_gated_fields() {
+        for &(version, id, len) in &[
+            (ProtocolVersion::V1_12_2, 0x0F, 5),
+            (ProtocolVersion::V1_14_4, 0x0E, 5),
+            (ProtocolVersion::V1_16_5, 0x0E, 21),
+        ] {
+            let mut connection = connection();
+            connection.protocol_version = version;
+            let chat = Packet::ServerChatPacket {
+                message: "hi".into(),
+                position: 1,
+                sender: 7,
+            };
+            let frame = chat.serialize(&connection).unwrap();
+            assert_eq!(frame[..2], [len, id]);
+            match Packet::deserialize(&mut Cursor::new(frame), &connection).unwrap() {
+                Packet::ServerChatPacket {
+                    message,
+                    position: 1,
+                    sender,
+                } => {
+                    assert_eq!(message, "hi");
+                    // missing fields are left at their default
+                    let expected = if version >= ProtocolVersion::V1_16_5 {
+                        7
+                    } else {
+                        0
+                    };
+                    assert_eq!(sender, expected);
+                }
+                packet => panic!("unexpected {:?}", packet),
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_packets_roundtrip() {
+        let mut connection = connection();
+        connection.compression = CompressionStatus::Enabled(16);
+        let short = Packet::ServerKeepAlive { magic: 42 };
+        let frame = short.serialize(&connection).unwrap();
+        // below the threshold only an uncompressed length of 0 is added
+        assert_eq!(frame[..3], [10, 0, 0x20]);
+        assert!(matches!(
+            Packet::deserialize(&mut Cursor::new(frame), &connection),
+            Ok(Packet::ServerKeepAlive { magic: 42 })
+        ));
+
+        let long = Packet::ServerDisconnectPacket {
+            reason: "a".repeat(100),
+        };
+        let frame = long.serialize(&connection).unwrap();
+        assert!(frame.len() < 100);
+        match Packet::deserialize(&mut Cursor::new(frame), &connection) {
+            Ok(Packet::ServerDisconnectPacket { reason }) => assert_eq!(reason, "a".repeat(100)),
+            packet => panic!("unexpected {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn packets_missing_from_a_version_are_not_serialized() {
+        let mut connection = connection();
+        connection.protocol_version = ProtocolVersion::V1_12_2;
+        let click = Packet::ClientClickWindow {
+            window_id: 0,
+            slot: 36,
+            button: 0,
+            action_number: 1,
+            mode: 0,
+            clicked_item: None,
+        };
+        let err = click.clone().serialize(&connection).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        connection.protocol_version = ProtocolVersion::V1_14_4;
+        assert!(click.serialize(&connection).is_ok());
+    }
 }
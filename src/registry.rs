@@ -0,0 +1,96 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Id <-> name mapping of one registry, like `minecraft:item` or `minecraft:entity_type`.
+///
+/// Ids change between versions, registries are read from the `registries.json` report
+/// the vanilla server writes for its version:
+/// `java -cp server.jar net.minecraft.data.Main --reports`
+#[derive(Debug, Default)]
+pub struct Registry {
+    names: Vec<String>,
+    ids: HashMap<String, i32>,
+}
+
+impl Registry {
+    pub fn from_report<P: AsRef<Path>>(path: P, registry: &str) -> Result<Registry> {
+        Registry::from_json(&fs::read_to_string(path)?, registry)
+    }
+
+    pub fn from_json(json: &str, registry: &str) -> Result<Registry> {
+        let report: Value = serde_json::from_str(json)?;
+        let entries = report[registry]["entries"].as_object().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("no {} registry in the report", registry),
+            )
+        })?;
+
+        let mut names = vec![String::new(); entries.len()];
+        let mut ids = HashMap::new();
+        for (name, entry) in entries {
+            let id = entry["protocol_id"]
+                .as_i64()
+                .filter(|id| (*id as usize) < entries.len())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid protocol id"))?;
+            names[id as usize] = name.clone();
+            ids.insert(name.clone(), id as i32);
+        }
+        Ok(Registry { names, ids })
+    }
+
+    pub fn name(&self, id: i32) -> Option<&str> {
+        self.names.get(id as usize).map(String::as_str)
+    }
+
+    /// Accepts names with and without the `minecraft:` namespace
+    pub fn id(&self, name: &str) -> Option<i32> {
+        match self.ids.get(name) {
+            Some(id) => Some(*id),
+            None => self.ids.get(&format!("minecraft:{}", name)).copied(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = r#"{
+        "minecraft:item": {"protocol_id": 6, "entries": {
+            "minecraft:air": {"protocol_id": 0},
+            "minecraft:stone": {"protocol_id": 1},
+            "minecraft:diamond_pickaxe": {"protocol_id": 2}
+        }}
+    }"#;
+
+    #[test]
+    fn names_and_ids() {
+        let items = Registry::from_json(REPORT, "minecraft:item").unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items.name(2), Some("minecraft:diamond_pickaxe"));
+        assert_eq!(items.name(3), None);
+        assert_eq!(items.name(-1), None);
+        assert_eq!(items.id("minecraft:stone"), Some(1));
+        assert_eq!(items.id("stone"), Some(1));
+        assert_eq!(items.id("dirt"), None);
+    }
+
+    #[test]
+    fn bad_reports_are_errors() {
+        assert!(Registry::from_json(REPORT, "minecraft:block").is_err());
+        let gap = r#"{"minecraft:item": {"entries": {"minecraft:air": {"protocol_id": 5}}}}"#;
+        assert!(Registry::from_json(gap, "minecraft:item").is_err());
+    }
+}
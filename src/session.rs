@@ -0,0 +1,206 @@
+use crate::hash::server_hash;
+use err_derive::Error;
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+pub const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+/// The credentials of an authenticated Minecraft account
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub access_token: String,
+    /// The profile UUID without dashes
+    pub profile_id: String,
+}
+
+impl Account {
+    /// Reads the account from `MINECRAFT_ACCESS_TOKEN` and `MINECRAFT_PROFILE_ID`
+    pub fn from_env() -> Option<Account> {
+        Some(Account {
+            access_token: env::var("MINECRAFT_ACCESS_TOKEN").ok()?,
+            profile_id: env::var("MINECRAFT_PROFILE_ID").ok()?.replace("-", ""),
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error(display = "no account configured, cannot join an online-mode server")]
+    NoAccount,
+    #[error(display = "session server unreachable: {}", 0)]
+    Unreachable(String),
+    #[error(display = "session server rejected the join ({}): {}", status, message)]
+    Rejected { status: u16, message: String },
+}
+
+/// Tells the session server that an account is about to join the server identified by `server_hash`
+pub trait SessionService {
+    fn join_server(&self, account: &Account, server_hash: &str) -> Result<(), SessionError>;
+}
+
+/// Announces the upcoming join of an online-mode server to the session server
+pub fn join_session(
+    session: &dyn SessionService,
+    account: Option<&Account>,
+    server_id: &str,
+    shared_secret: &[u8],
+    public_key: &[u8],
+) -> Result<(), SessionError> {
+    let account = account.ok_or(SessionError::NoAccount)?;
+    session.join_server(account, &server_hash(server_id, shared_secret, public_key))
+}
+
+/// Talks to a Yggdrasil compatible session server over HTTP(S)
+#[derive(Debug, Clone)]
+pub struct HttpSessionService {
+    pub endpoint: String,
+    pub timeout: Duration,
+}
+
+impl HttpSessionService {
+    pub fn new(endpoint: &str) -> HttpSessionService {
+        HttpSessionService {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SessionService for HttpSessionService {
+    fn join_server(&self, account: &Account, server_hash: &str) -> Result<(), SessionError> {
+        let response = ureq::post(&format!("{}/session/minecraft/join", self.endpoint))
+            .timeout_connect(self.timeout.as_millis() as u64)
+            .timeout(self.timeout)
+            .send_json(json!({
+                "accessToken": account.access_token,
+                "selectedProfile": account.profile_id,
+                "serverId": server_hash,
+            }));
+
+        if let Some(err) = response.synthetic_error() {
+            return Err(SessionError::Unreachable(err.to_string()));
+        }
+        if response.ok() {
+            return Ok(());
+        }
+
+        let status = response.status();
+        let body = response.into_string().unwrap_or_default();
+        // {"error": "ForbiddenOperationException", "errorMessage": "Invalid token."}
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|json| {
+                json.get("errorMessage")
+                    .or_else(|| json.get("error"))
+                    .and_then(Value::as_str)
+                    .map(str::to_owned)
+            })
+            .unwrap_or(body);
+        Err(SessionError::Rejected { status, message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A session server on localhost that answers one request with `status` and `body`, or
+    /// not at all without a status. Hands the request body back
+    fn stand_in(
+        status: Option<&'static str>,
+        body: &'static str,
+    ) -> (HttpSessionService, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let session =
+            HttpSessionService::new(&format!("http://{}/", listener.local_addr().unwrap()));
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                let line = line.to_ascii_lowercase();
+                if let Some(value) = line.strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut request = vec![0u8; length];
+            reader.read_exact(&mut request).unwrap();
+            match status {
+                Some(status) => {
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    reader.get_mut().write_all(response.as_bytes()).unwrap();
+                }
+                None => thread::sleep(Duration::from_secs(1)),
+            }
+            String::from_utf8(request).unwrap()
+        });
+        (session, server)
+    }
+
+    fn account() -> Account {
+        Account {
+            access_token: "token".into(),
+            profile_id: "0123456789abcdef0123456789abcdef".into(),
+        }
+    }
+
+    #[test]
+    fn join_posts_the_hash() {
+        let (session, server) = stand_in(Some("204 No Content"), "");
+        session.join_server(&account(), "-7c9d5b").unwrap();
+        let body: Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+        assert_eq!(body["accessToken"], "token");
+        assert_eq!(body["selectedProfile"], "0123456789abcdef0123456789abcdef");
+        assert_eq!(body["serverId"], "-7c9d5b");
+    }
+
+    #[test]
+    fn rejection_carries_the_error_message() {
+        let (session, server) = stand_in(
+            Some("403 Forbidden"),
+            r#"{"error":"ForbiddenOperationException","errorMessage":"Invalid token"}"#,
+        );
+        match session.join_server(&account(), "0") {
+            Err(SessionError::Rejected { status, message }) => {
+                assert_eq!(status, 403);
+                assert_eq!(message, "Invalid token");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn silent_server_times_out() {
+        let (mut session, server) = stand_in(None, "");
+        session.timeout = Duration::from_millis(200);
+        assert!(matches!(
+            session.join_server(&account(), "0"),
+            Err(SessionError::Unreachable(_))
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn joining_without_account_fails() {
+        let session = HttpSessionService::new(MOJANG_SESSION_SERVER);
+        assert!(matches!(
+            join_session(&session, None, "", &[], &[]),
+            Err(SessionError::NoAccount)
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::chat::TextComponent;
+use serde::{Deserialize, Deserializer};
+use std::io::{Error, ErrorKind, Result};
+
+const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+/// The JSON document a server answers a status request with
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerStatus {
+    pub version: StatusVersion,
+    pub players: StatusPlayers,
+    #[serde(rename = "description")]
+    pub motd: TextComponent,
+    /// The decoded PNG bytes of the server icon
+    #[serde(default, deserialize_with = "deserialize_favicon")]
+    pub favicon: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusPlayers {
+    pub max: i32,
+    pub online: i32,
+    #[serde(default)]
+    pub sample: Vec<StatusPlayer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusPlayer {
+    pub name: String,
+    pub id: String,
+}
+
+impl ServerStatus {
+    pub fn from_json(json: &str) -> Result<ServerStatus> {
+        serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+fn deserialize_favicon<'de, D>(deserializer: D) -> std::result::Result<Option<Vec<u8>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let favicon: Option<String> = Option::deserialize(deserializer)?;
+    match favicon {
+        Some(favicon) => {
+            let data = favicon.trim_start_matches(FAVICON_PREFIX).replace('\n', "");
+            base64::decode(&data)
+                .map(Some)
+                .map_err(serde::de::Error::custom)
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_status_response() {
+        let status = ServerStatus::from_json(
+            r#"{
+                "version": {"name": "1.14.4", "protocol": 498},
+                "players": {"max": 20, "online": 1, "sample": [{"name": "Steve", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},
+                "description": {"text": "A Minecraft ", "extra": ["Server"]},
+                "favicon": "data:image/png;base64,iVBO\nRw=="
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(status.version.protocol, 498);
+        assert_eq!(status.players.sample[0].name, "Steve");
+        assert_eq!(status.motd.to_plain(), "A Minecraft Server");
+        assert_eq!(status.favicon.unwrap(), b"\x89PNG");
+    }
+
+    #[test]
+    fn old_servers_send_a_plain_motd_and_no_favicon() {
+        let status = ServerStatus::from_json(
+            r#"{"version": {"name": "1.12.2", "protocol": 340}, "players": {"max": 8, "online": 0}, "description": "hi"}"#,
+        )
+        .unwrap();
+        assert_eq!(status.motd.to_plain(), "hi");
+        assert!(status.players.sample.is_empty());
+        assert!(status.favicon.is_none());
+        assert!(ServerStatus::from_json(
+            r#"{"version": {"name": "1.12.2", "protocol": 340}, "players": {"max": 8, "online": 0}, "description": "hi", "favicon": "data:image/png;base64,!"}"#,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,72 @@
+/// The protocol versions the bot can speak
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProtocolVersion {
+    V1_12_2,
+    #[default]
+    V1_14_4,
+    /// 1.16.2 up to 1.16.5, they share all packet ids and layouts
+    V1_16_5,
+}
+
+impl ProtocolVersion {
+    /// The number sent in the handshake
+    pub fn protocol_number(self) -> i32 {
+        match self {
+            ProtocolVersion::V1_12_2 => 340,
+            ProtocolVersion::V1_14_4 => 498,
+            ProtocolVersion::V1_16_5 => 754,
+        }
+    }
+
+    pub fn from_protocol_number(protocol_number: i32) -> Option<ProtocolVersion> {
+        match protocol_number {
+            340 => Some(ProtocolVersion::V1_12_2),
+            498 => Some(ProtocolVersion::V1_14_4),
+            751 | 753 | 754 => Some(ProtocolVersion::V1_16_5),
+            _ => None,
+        }
+    }
+
+    /// Whether the bot can play on this version and not just ping and log in. Terrain, entity
+    /// and window packets only have 1.14.4 ids and layouts, and the block and entity
+    /// registries are loaded from 1.14.4 reports
+    pub fn is_playable(self) -> bool {
+        self == ProtocolVersion::V1_14_4
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProtocolVersion::V1_12_2 => "1.12.2",
+            ProtocolVersion::V1_14_4 => "1.14.4",
+            ProtocolVersion::V1_16_5 => "1.16.5",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_numbers_roundtrip() {
+        for version in &[
+            ProtocolVersion::V1_12_2,
+            ProtocolVersion::V1_14_4,
+            ProtocolVersion::V1_16_5,
+        ] {
+            assert_eq!(
+                ProtocolVersion::from_protocol_number(version.protocol_number()),
+                Some(*version)
+            );
+        }
+    }
+
+    #[test]
+    fn patch_releases_share_a_version() {
+        assert_eq!(
+            ProtocolVersion::from_protocol_number(751),
+            Some(ProtocolVersion::V1_16_5)
+        );
+        assert_eq!(ProtocolVersion::from_protocol_number(736), None);
+    }
+}
@@ -1,32 +1,565 @@
-use derive_newtype::NewType;
+use crate::block::{BlockRegistry, BlockState};
+use crate::nbt::NbtTag;
+use crate::protocol::{BlockChangeRecord, ExplosionRecord, Packet, Position};
+use byteorder::{BigEndian, ReadBytesExt};
+use crossbeam_channel::{Receiver, Sender};
+use mc_varint::VarIntRead;
+use std::collections::HashMap;
+use std::io::{Cursor, Error, ErrorKind, Read, Result};
 use std::ops::Index;
 
-#[repr(transparent)]
-#[derive(NewType)]
-pub struct ChunkBlockID(u8);
+/// Sections using more bits per block than this index the global palette directly
+const MAX_PALETTE_BITS: u8 = 8;
+/// Bits per block of the 1.14.4 global palette
+const GLOBAL_PALETTE_BITS: u8 = 14;
 
-pub struct BiomeData {}
+/// A global block-state id
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ChunkBlockID(pub u16);
+
+impl ChunkBlockID {
+    pub const AIR: ChunkBlockID = ChunkBlockID(0);
+}
+
+impl From<u16> for ChunkBlockID {
+    fn from(id: u16) -> ChunkBlockID {
+        ChunkBlockID(id)
+    }
+}
+
+/// One 16x16x16 section of a chunk column
+#[derive(Clone)]
 pub struct ChunkData {
-    block_ids: [[[ChunkBlockID; 16]; 16]; 16],
+    /// Non-air blocks, as sent by the server
+    pub block_count: i16,
+    /// Indexed by `y << 8 | z << 4 | x`
+    block_ids: Vec<ChunkBlockID>,
 }
+
+impl Default for ChunkData {
+    fn default() -> ChunkData {
+        ChunkData::new()
+    }
+}
+
 impl ChunkData {
     pub fn new() -> ChunkData {
         ChunkData {
-            block_ids: [[[0.into(); 16]; 16]; 16],
+            block_count: 0,
+            block_ids: vec![0.into(); 4096],
         }
     }
+
+    /// Reads a paletted section of the Chunk Data packet
+    pub fn decode<R: Read>(buf: &mut R) -> Result<ChunkData> {
+        let block_count = buf.read_i16::<BigEndian>()?;
+        let mut bits_per_block = buf.read_u8()?;
+        let palette = if bits_per_block <= MAX_PALETTE_BITS {
+            bits_per_block = bits_per_block.max(4);
+            let len = buf.read_var_i32()?;
+            // a section has at most 4096 different blocks, don't trust the length any further
+            let mut palette = Vec::with_capacity(len.clamp(0, 4096) as usize);
+            for _ in 0..len {
+                palette.push(buf.read_var_i32()? as u16);
+            }
+            Some(palette)
+        } else {
+            bits_per_block = GLOBAL_PALETTE_BITS;
+            None
+        };
+
+        let len = buf.read_var_i32()? as usize;
+        if len != 4096 * bits_per_block as usize / 64 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "chunk section data doesn't match its bits per block",
+            ));
+        }
+        let mut data = vec![0u64; len];
+        buf.read_u64_into::<BigEndian>(&mut data)?;
+
+        let bits = bits_per_block as usize;
+        let mask = (1u64 << bits) - 1;
+        let mut block_ids = Vec::with_capacity(4096);
+        for i in 0..4096 {
+            // before 1.16 values may span two longs
+            let bit = i * bits;
+            let (start, offset) = (bit / 64, bit % 64);
+            let mut value = data[start] >> offset;
+            if offset + bits > 64 {
+                value |= data[start + 1] << (64 - offset);
+            }
+            let value = (value & mask) as usize;
+            let id = match &palette {
+                Some(palette) => *palette.get(value).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidData, "chunk palette index out of range")
+                })?,
+                None => value as u16,
+            };
+            block_ids.push(id.into());
+        }
+
+        Ok(ChunkData {
+            block_count,
+            block_ids,
+        })
+    }
+}
+
+impl ChunkData {
+    /// Coordinates inside the section, returns the previous block
+    pub fn set(&mut self, x: usize, y: usize, z: usize, id: ChunkBlockID) -> ChunkBlockID {
+        let old = std::mem::replace(&mut self.block_ids[y << 8 | z << 4 | x], id);
+        if old == ChunkBlockID::AIR && id != ChunkBlockID::AIR {
+            self.block_count += 1;
+        } else if old != ChunkBlockID::AIR && id == ChunkBlockID::AIR {
+            self.block_count -= 1;
+        }
+        old
+    }
 }
 
 impl Index<(usize, usize, usize)> for ChunkData {
     type Output = ChunkBlockID;
 
+    /// Coordinates inside the section, (x, y, z)
     fn index(&self, index: (usize, usize, usize)) -> &Self::Output {
-        unimplemented!()
+        let (x, y, z) = index;
+        &self.block_ids[y << 8 | z << 4 | x]
     }
 }
 
-pub struct ChunkNibbleData {}
-pub struct ChunkColumn {}
+/// 16 sections stacked on top of each other, with biomes and block entities
+pub struct ChunkColumn {
+    pub x: i32,
+    pub z: i32,
+    /// Empty (all air) sections are `None`
+    pub sections: Vec<Option<ChunkData>>,
+    /// One biome id per x/z column, indexed by `z << 4 | x`
+    pub biomes: Vec<i32>,
+    pub heightmaps: NbtTag,
+    /// Keyed by absolute block position
+    pub block_entities: HashMap<(i32, i32, i32), NbtTag>,
+}
+
+impl ChunkColumn {
+    pub fn new(x: i32, z: i32) -> ChunkColumn {
+        ChunkColumn {
+            x,
+            z,
+            sections: vec![None; 16],
+            biomes: vec![0; 256],
+            heightmaps: NbtTag::End,
+            block_entities: HashMap::new(),
+        }
+    }
+
+    /// Replaces the sections in `primary_bit_mask` with the ones in `data`,
+    /// a full chunk also carries biomes and clears everything else
+    pub fn load(
+        &mut self,
+        full_chunk: bool,
+        primary_bit_mask: i32,
+        data: &[u8],
+        block_entities: Vec<NbtTag>,
+    ) -> Result<()> {
+        let mut buf = Cursor::new(data);
+        for y in 0..16 {
+            if primary_bit_mask & (1 << y) != 0 {
+                self.sections[y] = Some(ChunkData::decode(&mut buf)?);
+            } else if full_chunk {
+                self.sections[y] = None;
+            }
+        }
+        if full_chunk {
+            buf.read_i32_into::<BigEndian>(&mut self.biomes)?;
+            self.block_entities.clear();
+        }
 
-pub struct Chunk {}
-pub struct World {}
+        for block_entity in block_entities {
+            let coordinate = |key| block_entity.get(key).and_then(NbtTag::as_i64);
+            if let (Some(x), Some(y), Some(z)) = (coordinate("x"), coordinate("y"), coordinate("z"))
+            {
+                self.block_entities
+                    .insert((x as i32, y as i32, z as i32), block_entity);
+            }
+        }
+        Ok(())
+    }
+
+    /// Coordinates inside the column, air for empty sections
+    pub fn block_at(&self, x: usize, y: usize, z: usize) -> ChunkBlockID {
+        match self.sections.get(y >> 4) {
+            Some(Some(section)) => section[(x, y & 15, z)],
+            _ => ChunkBlockID(0),
+        }
+    }
+
+    /// Coordinates inside the column, returns the previous block
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, id: ChunkBlockID) -> ChunkBlockID {
+        let section = &mut self.sections[y >> 4];
+        if section.is_none() {
+            if id == ChunkBlockID::AIR {
+                return ChunkBlockID::AIR;
+            }
+            *section = Some(ChunkData::new());
+        }
+        section.as_mut().unwrap().set(x, y & 15, z, id)
+    }
+
+    pub fn biome_at(&self, x: usize, z: usize) -> i32 {
+        self.biomes[z << 4 | x]
+    }
+}
+
+/// A block that changed, sent to everyone who subscribed to the world
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlockChange {
+    pub position: Position,
+    pub old: ChunkBlockID,
+    pub new: ChunkBlockID,
+}
+
+/// All chunk columns the server sent us
+pub struct World {
+    pub columns: HashMap<(i32, i32), ChunkColumn>,
+    pub blocks: BlockRegistry,
+    /// The chunk the server centers our view on
+    pub view_center: (i32, i32),
+    /// In chunks, 0 until Join Game told us
+    pub view_distance: i32,
+    /// Like `minecraft:overworld`, empty until Join Game told us
+    pub dimension: String,
+    subscribers: Vec<Sender<BlockChange>>,
+}
+
+impl World {
+    pub fn new(blocks: BlockRegistry) -> World {
+        World {
+            columns: HashMap::new(),
+            blocks,
+            view_center: (0, 0),
+            view_distance: 0,
+            dimension: String::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    /// Every change made through `set_block` from now on is sent to the returned receiver
+    pub fn subscribe(&mut self) -> Receiver<BlockChange> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn notify(&mut self, change: BlockChange) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(change).is_ok());
+    }
+
+    /// Changes a block in a loaded chunk and returns the previous one, `None` if the chunk isn't loaded
+    pub fn set_block(&mut self, position: Position, id: ChunkBlockID) -> Option<ChunkBlockID> {
+        let Position { x, y, z } = position;
+        if !(0..=255).contains(&y) {
+            return None;
+        }
+        let column = self.columns.get_mut(&(x >> 4, z >> 4))?;
+        let old = column.set_block((x & 15) as usize, y as usize, (z & 15) as usize, id);
+        if old != id {
+            self.notify(BlockChange {
+                position,
+                old,
+                new: id,
+            });
+        }
+        Some(old)
+    }
+
+    /// Applies the records of a Multi Block Change
+    pub fn multi_block_change(
+        &mut self,
+        chunk_x: i32,
+        chunk_z: i32,
+        records: &[BlockChangeRecord],
+    ) {
+        for record in records {
+            let position = Position {
+                x: chunk_x << 4 | record.x as i32,
+                y: record.y as i32,
+                z: chunk_z << 4 | record.z as i32,
+            };
+            self.set_block(position, ChunkBlockID(record.block_id as u16));
+        }
+    }
+
+    /// Removes the blocks an explosion destroyed
+    pub fn explode(&mut self, x: f32, y: f32, z: f32, records: &[ExplosionRecord]) {
+        // relative to the block the center is in, the vanilla client floors it too
+        let (x, y, z) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+        for record in records {
+            let position = Position {
+                x: x + record.x as i32,
+                y: y + record.y as i32,
+                z: z + record.z as i32,
+            };
+            self.set_block(position, ChunkBlockID::AIR);
+        }
+    }
+
+    pub fn unload_chunk(&mut self, chunk_x: i32, chunk_z: i32) {
+        self.columns.remove(&(chunk_x, chunk_z));
+    }
+
+    /// Forgets all chunks if `dimension` isn't the one we are in, returns whether it changed
+    pub fn set_dimension(&mut self, dimension: String) -> bool {
+        if self.dimension == dimension {
+            return false;
+        }
+        self.columns.clear();
+        self.dimension = dimension;
+        true
+    }
+
+    /// Moves the view and forgets the chunks that left it
+    pub fn set_view_center(&mut self, chunk_x: i32, chunk_z: i32) {
+        self.view_center = (chunk_x, chunk_z);
+        if self.view_distance <= 0 {
+            return;
+        }
+        let radius = self.view_distance + 1;
+        self.columns
+            .retain(|(x, z), _| (x - chunk_x).abs() <= radius && (z - chunk_z).abs() <= radius);
+    }
+
+    /// Handles a Chunk Data packet. Partial updates of chunks we don't have are dropped
+    pub fn load_chunk(&mut self, packet: &Packet) -> Result<()> {
+        let (x, z, full_chunk, primary_bit_mask, heightmaps, data, block_entities) = match packet {
+            Packet::ServerChunkData {
+                x,
+                z,
+                full_chunk,
+                primary_bit_mask,
+                heightmaps,
+                data,
+                block_entities,
+            } => (
+                *x,
+                *z,
+                *full_chunk,
+                *primary_bit_mask,
+                heightmaps,
+                data,
+                block_entities,
+            ),
+            _ => return Ok(()),
+        };
+        let in_chunk = |err: Error| Error::new(err.kind(), format!("chunk {} {}: {}", x, z, err));
+        if full_chunk {
+            let mut column = ChunkColumn::new(x, z);
+            column
+                .load(true, primary_bit_mask, data, block_entities.clone())
+                .map_err(in_chunk)?;
+            column.heightmaps = heightmaps.clone();
+            self.columns.insert((x, z), column);
+            Ok(())
+        } else {
+            match self.columns.get_mut(&(x, z)) {
+                Some(column) => {
+                    column.heightmaps = heightmaps.clone();
+                    column
+                        .load(false, primary_bit_mask, data, block_entities.clone())
+                        .map_err(in_chunk)
+                }
+                None => Ok(()),
+            }
+        }
+    }
+
+    pub fn column(&self, chunk_x: i32, chunk_z: i32) -> Option<&ChunkColumn> {
+        self.columns.get(&(chunk_x, chunk_z))
+    }
+
+    /// The block at absolute coordinates, `None` if its chunk isn't loaded or y is outside the world
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<ChunkBlockID> {
+        if !(0..=255).contains(&y) {
+            return None;
+        }
+        let column = self.column(x >> 4, z >> 4)?;
+        Some(column.block_at((x & 15) as usize, y as usize, (z & 15) as usize))
+    }
+
+    /// Name and properties of the block at absolute coordinates
+    pub fn block_state_at(&self, x: i32, y: i32, z: i32) -> Option<&BlockState> {
+        self.blocks.state(self.block_at(x, y, z)?)
+    }
+}
+
+/// The name of a dimension. Before 1.16 Join Game and Respawn only send an id,
+/// since they send the name and the id is left at 0
+pub fn dimension_name(dimension: i32, world_name: &str) -> String {
+    if !world_name.is_empty() {
+        return world_name.to_string();
+    }
+    match dimension {
+        -1 => "minecraft:the_nether",
+        1 => "minecraft:the_end",
+        _ => "minecraft:overworld",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use mc_varint::VarIntWrite;
+
+    const STONE: ChunkBlockID = ChunkBlockID(1);
+
+    fn world() -> World {
+        let mut world = World::new(BlockRegistry::default());
+        for &(x, z) in &[(-1, -1), (-1, 0), (0, -1), (0, 0)] {
+            world.columns.insert((x, z), ChunkColumn::new(x, z));
+        }
+        world
+    }
+
+    #[test]
+    fn explosions_are_relative_to_the_floored_center() {
+        let mut world = world();
+        let cleared = Position {
+            x: -1,
+            y: 64,
+            z: -2,
+        };
+        let kept = Position { x: 0, y: 64, z: -1 };
+        world.set_block(cleared, STONE);
+        world.set_block(kept, STONE);
+        let record = ExplosionRecord { x: 0, y: 0, z: -1 };
+        world.explode(-0.5, 64.5, -0.5, &[record]);
+        assert_eq!(world.block_at(-1, 64, -2), Some(ChunkBlockID::AIR));
+        assert_eq!(world.block_at(0, 64, -1), Some(STONE));
+    }
+
+    #[test]
+    fn changes_reach_subscribers() {
+        let mut world = world();
+        let changes = world.subscribe();
+        let position = Position { x: 3, y: 10, z: -7 };
+        assert_eq!(world.set_block(position, STONE), Some(ChunkBlockID::AIR));
+        // setting the same block again isn't a change
+        world.set_block(position, STONE);
+        assert_eq!(
+            changes.try_iter().collect::<Vec<_>>(),
+            vec![BlockChange {
+                position,
+                old: ChunkBlockID::AIR,
+                new: STONE,
+            }]
+        );
+        assert_eq!(world.set_block(Position { x: 40, y: 0, z: 0 }, STONE), None);
+    }
+
+    /// A section in the 1.14 format, values packed across longs like the server does it
+    fn encode_section(bits: u8, palette: Option<&[i32]>, values: &[u64]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_i16::<BigEndian>(values.iter().filter(|v| **v != 0).count() as i16)
+            .unwrap();
+        buf.write_u8(bits).unwrap();
+        if let Some(palette) = palette {
+            buf.write_var_i32(palette.len() as i32).unwrap();
+            for id in palette {
+                buf.write_var_i32(*id).unwrap();
+            }
+        }
+        let bits = bits.max(4) as usize;
+        let mut data = vec![0u64; 4096 * bits / 64];
+        for (i, value) in values.iter().enumerate() {
+            let bit = i * bits;
+            let (start, offset) = (bit / 64, bit % 64);
+            data[start] |= value << offset;
+            if offset + bits > 64 {
+                data[start + 1] |= value >> (64 - offset);
+            }
+        }
+        buf.write_var_i32(data.len() as i32).unwrap();
+        for long in data {
+            buf.write_u64::<BigEndian>(long).unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn paletted_sections() {
+        // palette indices, stone on the bottom layer and one granite in the corner
+        let mut values = vec![0u64; 4096];
+        for value in &mut values[..256] {
+            *value = 1;
+        }
+        values[4095] = 2;
+        let buf = encode_section(4, Some(&[0, 1, 2]), &values);
+        let section = ChunkData::decode(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(section.block_count, 257);
+        assert_eq!(section[(3, 0, 9)], STONE);
+        assert_eq!(section[(3, 1, 9)], ChunkBlockID::AIR);
+        assert_eq!(section[(15, 15, 15)], ChunkBlockID(2));
+    }
+
+    #[test]
+    fn global_palette_values_span_longs() {
+        // 14 bits don't divide 64, so some values start in one long and end in the next
+        let values: Vec<u64> = (0..4096).map(|i| (i * 7 % 11_000) as u64).collect();
+        let buf = encode_section(GLOBAL_PALETTE_BITS, None, &values);
+        let section = ChunkData::decode(&mut Cursor::new(buf)).unwrap();
+        for (i, value) in values.iter().enumerate() {
+            let (x, y, z) = (i & 15, i >> 8, i >> 4 & 15);
+            assert_eq!(section[(x, y, z)], ChunkBlockID(*value as u16));
+        }
+    }
+
+    #[test]
+    fn malformed_sections_are_rejected() {
+        let mut values = vec![0u64; 4096];
+        values[10] = 3;
+        let buf = encode_section(4, Some(&[0, 1]), &values);
+        let err = ChunkData::decode(&mut Cursor::new(buf)).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut buf = encode_section(4, Some(&[0]), &[]);
+        // claim 5 bits per block for 4 bits worth of data
+        buf[2] = 5;
+        let err = ChunkData::decode(&mut Cursor::new(buf)).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunk_data_loads_columns() {
+        let mut world = World::new(BlockRegistry::default());
+        let mut data = encode_section(4, Some(&[0, 1]), &[1; 4096]);
+        for biome in 0..256 {
+            data.write_i32::<BigEndian>(biome % 3).unwrap();
+        }
+        let chunk = |full_chunk, data: &[u8]| Packet::ServerChunkData {
+            x: -2,
+            z: 5,
+            full_chunk,
+            // only the section from y 16 to 31
+            primary_bit_mask: 0b10,
+            heightmaps: NbtTag::End,
+            data: data.to_vec(),
+            block_entities: Vec::new(),
+        };
+        // partial updates of unknown chunks are dropped
+        world.load_chunk(&chunk(false, &data)).unwrap();
+        assert!(world.column(-2, 5).is_none());
+
+        world.load_chunk(&chunk(true, &data)).unwrap();
+        assert_eq!(world.block_at(-32, 15, 80), Some(ChunkBlockID::AIR));
+        assert_eq!(world.block_at(-17, 31, 95), Some(STONE));
+        assert_eq!(world.block_at(-17, 32, 95), Some(ChunkBlockID::AIR));
+        assert_eq!(world.column(-2, 5).unwrap().biome_at(1, 0), 1);
+
+        let err = world.load_chunk(&chunk(true, &data[..100])).err().unwrap();
+        assert!(err.to_string().starts_with("chunk -2 5"));
+    }
+}